@@ -1,4 +1,6 @@
+use chrono::{DateTime, FixedOffset};
 use clap::{ArgEnum, Parser, Subcommand};
+use std::{path::PathBuf, time::Duration};
 use uuid::Uuid;
 
 /// Connect to a gRPC job server
@@ -17,6 +19,8 @@ pub struct ArgParser {
     pub sub_command: SubCommand,
 }
 
+// the args are parsed once, there's no point in boxing the start options
+#[allow(clippy::large_enum_variant)]
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Subcommand)]
 pub enum SubCommand {
     /// start a new job
@@ -33,17 +37,239 @@ pub enum SubCommand {
         /// list of environment variables
         envs: Vec<(String, String)>,
 
+        #[clap(long = "cpu-max", parse(try_from_str = quota_period))]
+        /// cgroup cpu.max limit as QUOTA_US/PERIOD_US, e.g. 50000/100000 for half a cpu
+        cpu_max: Option<CpuMax>,
+
+        #[clap(long = "memory-max")]
+        /// cgroup memory.max limit in bytes
+        memory_max: Option<u64>,
+
+        #[clap(long = "io", multiple_occurrences = true, parse(try_from_str = io_limit))]
+        /// cgroup io limit for a device as MAJ:MIN[,weight=W][,rbps=N][,wbps=N][,riops=N][,wiops=N]
+        io: Vec<IoLimit>,
+
+        #[clap(
+            long = "isolate",
+            arg_enum,
+            multiple_occurrences = true,
+            use_value_delimiter = true
+        )]
+        /// linux namespaces to isolate the job in, comma separated
+        isolate: Vec<Namespace>,
+
+        #[clap(short = 't', long = "tty")]
+        /// run the job in a pseudo-terminal the size of the local terminal. Use `attach` to interact with it
+        tty: bool,
+
+        #[clap(long = "stdin", arg_enum, default_value = "null")]
+        /// where the job's stdin comes from
+        stdin: StdinMode,
+
+        #[clap(long = "timeout", parse(try_from_str = duration))]
+        /// stop the job if it runs longer than this, e.g. 30s, 5m, 1h
+        timeout: Option<Duration>,
+
+        #[clap(long = "stop-signal", parse(try_from_str = signal))]
+        /// signal sent to stop the job, by name or number [default: TERM]
+        stop_signal: Option<i32>,
+
+        #[clap(long = "grace", parse(try_from_str = duration))]
+        /// how long a stopped job gets to exit before it is SIGKILLed [default: 10s]
+        grace: Option<Duration>,
+
+        #[clap(long = "restart", arg_enum, default_value = "never")]
+        /// when to start the job again after it exits. A stopped job is never restarted
+        restart: RestartMode,
+
+        #[clap(long = "max-attempts")]
+        /// with --restart on-failure, how many times the job may run in total [default: no limit]
+        max_attempts: Option<u32>,
+
+        #[clap(long = "backoff", parse(try_from_str = duration))]
+        /// wait before the first restart, doubled for each restart after it [default: 1s]
+        backoff: Option<Duration>,
+
+        #[clap(long = "max-backoff", parse(try_from_str = duration))]
+        /// longest wait between restarts [default: 1m]
+        max_backoff: Option<Duration>,
+
+        #[clap(long = "pipefail")]
+        /// report the last command of the pipeline that failed as the job's status, instead of the last command
+        pipefail: bool,
+
+        #[clap(long = "priority", default_value = "0", allow_hyphen_values = true)]
+        /// if the server is running as many jobs as it allows, jobs with a higher priority leave the queue first
+        priority: i32,
+
+        #[clap(long = "name")]
+        /// name to refer to the job by instead of its id, unique among your jobs
+        name: Option<String>,
+
+        #[clap(short = 'l', long = "label", multiple_occurrences = true, parse(try_from_str = var_eq_val))]
+        /// label for the job as KEY=VAL, may be given more than once
+        labels: Vec<(String, String)>,
+
+        /// args for the command. A lone | starts the next command of a pipeline, which reads this one's stdout
         args: Vec<String>,
     },
     /// stop a job
     Stop {
+        /// override the job's grace period before it is SIGKILLed, e.g. 0 to kill it right away
+        #[clap(long = "grace", parse(try_from_str = duration))]
+        grace: Option<Duration>,
+
+        /// job id, a prefix of it that matches only one job, or job name
+        job: String,
+    },
+    /// send a signal to a job
+    Kill {
+        /// signal to send, by name or number
+        #[clap(short = 's', long = "signal", default_value = "TERM", parse(try_from_str = self::signal))]
+        signal: i32,
+
+        /// job id, a prefix of it that matches only one job, or job name
+        job: String,
+    },
+    /// attach the local terminal to a job started with --tty
+    Attach {
+        /// job id, a prefix of it that matches only one job, or job name
+        job: String,
+    },
+    /// pause a job, suspending all of its processes
+    Pause {
+        /// job id, a prefix of it that matches only one job, or job name
+        job: String,
+    },
+    /// resume a paused job
+    Resume {
+        /// job id, a prefix of it that matches only one job, or job name
+        job: String,
+    },
+    /// stream local stdin to a job started with --stdin stream
+    WriteStdin {
+        /// leave the job's stdin open after local stdin ends, so more can be written later
+        #[clap(long = "no-eof")]
+        no_eof: bool,
+
+        /// job id, a prefix of it that matches only one job, or job name
+        job: String,
+    },
+    /// submit a workflow of jobs described by a YAML file
+    SubmitWorkflow {
+        /// path to the workflow file
+        file: PathBuf,
+    },
+    /// get a workflow's status
+    WorkflowStatus {
         /// Uuid v4 string
-        job_id: Uuid,
+        workflow_id: Uuid,
     },
-    /// get a job's status
-    Status {
+    /// start a job on a cron schedule
+    Schedule {
+        #[clap(long = "cron")]
+        /// when to start the job, as a cron expression with an optional leading seconds field, e.g. "0 3 * * *"
+        cron: String,
+
+        #[clap(long = "timezone")]
+        /// IANA timezone the cron expression is in, e.g. Europe/Berlin [default: UTC]
+        timezone: Option<String>,
+
+        #[clap(long = "overlap", arg_enum, default_value = "skip")]
+        /// what to do when a tick comes while the last job started by the schedule is still running
+        overlap: Overlap,
+
+        #[clap(short = 'c', long = "command")]
+        /// name of the command to run
+        command: String,
+
+        #[clap(short = 'd', long = "dir")]
+        /// working directory for the command
+        dir: String,
+
+        #[clap(short = 'e', long = "envs", multiple_values = true, parse(try_from_str = var_eq_val))]
+        /// list of environment variables
+        envs: Vec<(String, String)>,
+
+        #[clap(long = "timeout", parse(try_from_str = duration))]
+        /// stop each job if it runs longer than this, e.g. 30s, 5m, 1h
+        timeout: Option<Duration>,
+
+        #[clap(long = "pipefail")]
+        /// report the last command of the pipeline that failed as the job's status, instead of the last command
+        pipefail: bool,
+
+        #[clap(long = "priority", default_value = "0", allow_hyphen_values = true)]
+        /// if the server is running as many jobs as it allows, jobs with a higher priority leave the queue first
+        priority: i32,
+
+        /// args for the command. A lone | starts the next command of a pipeline, which reads this one's stdout
+        args: Vec<String>,
+    },
+    /// list schedules, with the jobs they started
+    Schedules,
+    /// delete a schedule. Jobs it already started keep running
+    DeleteSchedule {
         /// Uuid v4 string
-        job_id: Uuid,
+        schedule_id: Uuid,
+    },
+    /// forget a finished job and its output
+    Delete {
+        /// job id, a prefix of it that matches only one job, or job name
+        job: String,
+    },
+    /// print what happens to jobs as it happens: starts, pauses, resumes, exits and deletions
+    Watch {
+        #[clap(long = "owner")]
+        /// only jobs started by this user
+        owner: Option<String>,
+
+        /// only this job: its id, a prefix of it that matches only one job, or its name
+        job: Option<String>,
+    },
+    /// list jobs, oldest first
+    List {
+        #[clap(long = "owner")]
+        /// only jobs started by this user
+        owner: Option<String>,
+
+        #[clap(
+            long = "status",
+            arg_enum,
+            multiple_occurrences = true,
+            use_value_delimiter = true
+        )]
+        /// only jobs in one of these states, comma separated
+        status: Vec<State>,
+
+        #[clap(short = 'l', long = "label", multiple_occurrences = true, parse(try_from_str = var_eq_val))]
+        /// only jobs with this label as KEY=VAL, may be given more than once
+        labels: Vec<(String, String)>,
+
+        #[clap(long = "started-after", parse(try_from_str = timestamp))]
+        /// only jobs started after this time, e.g. 2026-10-17T09:00:00Z
+        started_after: Option<DateTime<FixedOffset>>,
+
+        #[clap(long = "started-before", parse(try_from_str = timestamp))]
+        /// only jobs started before this time, e.g. 2026-10-17T17:00:00+02:00
+        started_before: Option<DateTime<FixedOffset>>,
+
+        #[clap(long = "limit")]
+        /// list at most this many jobs [default: no limit]
+        limit: Option<usize>,
+    },
+    /// get a job's status and details
+    Status {
+        /// also print the environment variables the job runs with
+        #[clap(long = "env")]
+        env: bool,
+
+        /// print everything about the job as JSON instead, environment included
+        #[clap(long = "json")]
+        json: bool,
+
+        /// job id, a prefix of it that matches only one job, or job name
+        job: String,
     },
     /// stream a job's output
     Output {
@@ -51,8 +277,16 @@ pub enum SubCommand {
         #[clap(arg_enum)]
         output_type: OutputType,
 
-        /// Uuid v4 string
-        job_id: Uuid,
+        /// start from this byte of the output, e.g. where an interrupted stream stopped
+        #[clap(long = "offset", default_value = "0")]
+        offset: u64,
+
+        /// start each line with the time it was captured
+        #[clap(short = 't', long = "timestamps")]
+        timestamps: bool,
+
+        /// job id, a prefix of it that matches only one job, or job name
+        job: String,
     },
 }
 
@@ -66,6 +300,91 @@ pub enum OutputType {
     All,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
+pub enum StdinMode {
+    /// /dev/null
+    Null,
+    /// all of local stdin, read before the job starts
+    Bytes,
+    /// written while the job runs, with write-stdin
+    Stream,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
+pub enum RestartMode {
+    /// never restart the job
+    Never,
+    /// restart the job when it exits with a non-zero code or is killed
+    OnFailure,
+    /// restart the job whenever it exits
+    Always,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
+pub enum Overlap {
+    /// skip the tick
+    Skip,
+    /// start the job for the tick once the running one finishes
+    Queue,
+    /// start the job anyway
+    Allow,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
+pub enum State {
+    /// running
+    Running,
+    /// paused
+    Paused,
+    /// waiting to be restarted
+    Restarting,
+    /// waiting for the server to run fewer jobs
+    Queued,
+    /// stopped before it started
+    Cancelled,
+    /// couldn't be started when it left the queue
+    FailedToStart,
+    /// exited with an exit code
+    Exited,
+    /// killed by a signal
+    Killed,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
+pub enum Namespace {
+    /// new pid namespace with a private /proc (implies mount)
+    Pid,
+    /// new mount namespace
+    Mount,
+    /// new network namespace with only loopback networking
+    Network,
+    /// new hostname namespace
+    Uts,
+    /// new ipc namespace
+    Ipc,
+    /// all of the above
+    All,
+}
+
+/// cgroup cpu.max limit
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CpuMax {
+    pub quota_us: u64,
+    pub period_us: u64,
+}
+
+/// cgroup io limits for one device. Zero means no limit.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct IoLimit {
+    pub major: u32,
+    pub minor: u32,
+    pub weight: u32,
+    pub rbps: u64,
+    pub wbps: u64,
+    pub riops: u64,
+    pub wiops: u64,
+}
+
 /// try_from_str parse function for command env variables
 fn var_eq_val(s: &str) -> Result<(String, String), String> {
     let mut v: Vec<String> = s.split('=').map(str::to_string).collect();
@@ -77,3 +396,92 @@ fn var_eq_val(s: &str) -> Result<(String, String), String> {
         Ok((var, val))
     }
 }
+
+/// try_from_str parse function for cpu.max limits
+fn quota_period(s: &str) -> Result<CpuMax, String> {
+    let (quota, period) = s
+        .split_once('/')
+        .ok_or_else(|| "Required format is QUOTA_US/PERIOD_US".to_string())?;
+    Ok(CpuMax {
+        quota_us: quota.parse().map_err(|_| "Invalid quota".to_string())?,
+        period_us: period.parse().map_err(|_| "Invalid period".to_string())?,
+    })
+}
+
+/// try_from_str parse function for per-device io limits
+fn io_limit(s: &str) -> Result<IoLimit, String> {
+    let mut parts = s.split(',');
+    let device = parts.next().unwrap_or_default();
+    let (major, minor) = device
+        .split_once(':')
+        .ok_or_else(|| "Required format is MAJ:MIN[,KEY=VAL]...".to_string())?;
+    let mut limit = IoLimit {
+        major: major
+            .parse()
+            .map_err(|_| "Invalid device major".to_string())?,
+        minor: minor
+            .parse()
+            .map_err(|_| "Invalid device minor".to_string())?,
+        ..Default::default()
+    };
+    for part in parts {
+        let (key, val) = var_eq_val(part)?;
+        let invalid = || format!("Invalid value for {}", key);
+        match key.as_str() {
+            "weight" => limit.weight = val.parse().map_err(|_| invalid())?,
+            "rbps" => limit.rbps = val.parse().map_err(|_| invalid())?,
+            "wbps" => limit.wbps = val.parse().map_err(|_| invalid())?,
+            "riops" => limit.riops = val.parse().map_err(|_| invalid())?,
+            "wiops" => limit.wiops = val.parse().map_err(|_| invalid())?,
+            _ => return Err(format!("Unknown io limit {}", key)),
+        }
+    }
+    Ok(limit)
+}
+
+/// try_from_str parse function for durations like 500ms, 30s, 5m or 1h. A bare number is seconds.
+pub(crate) fn duration(s: &str) -> Result<Duration, String> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let value: u64 = value
+        .parse()
+        .map_err(|_| "Required format is a number with an optional ms/s/m/h unit".to_string())?;
+    match unit {
+        "ms" => Ok(Duration::from_millis(value)),
+        "" | "s" => Ok(Duration::from_secs(value)),
+        "m" => Ok(Duration::from_secs(value * 60)),
+        "h" => Ok(Duration::from_secs(value * 60 * 60)),
+        _ => Err(format!("Unknown duration unit {}", unit)),
+    }
+}
+
+/// try_from_str parse function for RFC 3339 timestamps
+fn timestamp(s: &str) -> Result<DateTime<FixedOffset>, String> {
+    DateTime::parse_from_rfc3339(s)
+        .map_err(|_| "Required format is RFC 3339, e.g. 2026-10-17T09:00:00Z".to_string())
+}
+
+/// try_from_str parse function for signals, by number or by name with or without the SIG prefix
+fn signal(s: &str) -> Result<i32, String> {
+    if let Ok(signal) = s.parse() {
+        return Ok(signal);
+    }
+    let name = s.to_ascii_uppercase();
+    let signal = match name.strip_prefix("SIG").unwrap_or(&name) {
+        "HUP" => libc::SIGHUP,
+        "INT" => libc::SIGINT,
+        "QUIT" => libc::SIGQUIT,
+        "KILL" => libc::SIGKILL,
+        "USR1" => libc::SIGUSR1,
+        "USR2" => libc::SIGUSR2,
+        "PIPE" => libc::SIGPIPE,
+        "ALRM" => libc::SIGALRM,
+        "TERM" => libc::SIGTERM,
+        "CONT" => libc::SIGCONT,
+        "STOP" => libc::SIGSTOP,
+        "TSTP" => libc::SIGTSTP,
+        "WINCH" => libc::SIGWINCH,
+        _ => return Err(format!("Unknown signal {}", s)),
+    };
+    Ok(signal)
+}
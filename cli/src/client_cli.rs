@@ -1,12 +1,29 @@
-use std::collections::HashMap;
-use std::io::Write;
+use std::io::{Read, Write};
 
+use crate::terminal::{self, RawMode};
 use protobuf::{
-    output_request::OutputType, remote_jobs_client::RemoteJobsClient, status_response::JobStatus,
-    OutputRequest, OutputResponse, StartRequest, StatusRequest,
+    attach_request, attempt_outcome::Outcome, job_event, job_info, job_summary, output_chunk,
+    output_request::OutputType, output_response, remote_jobs_client::RemoteJobsClient,
+    stage_outcome, status_response::JobStatus, stdin_request, step_status,
+    workflow_status_response, AttachRequest, CreateScheduleRequest, CreateScheduleResponse,
+    DeleteScheduleRequest, GetJobRequest, GracePeriod, JobInfo, JobState, ListJobsRequest,
+    ListSchedulesRequest, OutputRequest, OutputResponse, OverlapPolicy, ResolveJobRequest,
+    StartRequest, StatusRequest, StatusResponse, StdinRequest, StopReason, WatchJobsRequest,
+    WorkflowRequest, WorkflowResponse, WorkflowStatusRequest,
 };
 
-use std::path::PathBuf;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    time::{Duration, UNIX_EPOCH},
+};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::mpsc,
+};
+use tokio_stream::wrappers::UnboundedReceiverStream;
 use tonic::{
     transport::{Certificate, Channel, ClientTlsConfig, Identity},
     Request, Status,
@@ -35,20 +52,8 @@ impl ClientCli {
         }
     }
 
-    pub async fn start_job(
-        &mut self,
-        cmd: &str,
-        args: &[String],
-        dir: &str,
-        envs: &[(String, String)],
-    ) -> Result<(), Status> {
-        // start an echo job
-        let request = tonic::Request::new(StartRequest {
-            cmd: cmd.into(),
-            args: args.into(),
-            dir: dir.into(),
-            envs: HashMap::from_iter(envs.iter().cloned()),
-        });
+    pub async fn start_job(&mut self, start_request: StartRequest) -> Result<(), Status> {
+        let request = tonic::Request::new(start_request);
         let response = self.inner.start_job(request).await?;
         let job_id = response.into_inner().job_id;
         let uuid = JobId::from_slice(&job_id).expect("server responded with invalid uuid");
@@ -56,48 +61,537 @@ impl ClientCli {
         Ok(())
     }
 
-    pub async fn stop_job(&mut self, job_id: JobId) -> Result<(), Status> {
+    /// Find the id of the job `job` refers to, by its id, a prefix of its id, or its name.
+    pub async fn resolve_job(&mut self, job: String) -> Result<JobId, Status> {
+        let request = Request::new(ResolveJobRequest { job });
+        let response = self.inner.resolve_job(request).await?;
+        let job_id = response.into_inner().job_id;
+        Ok(JobId::from_slice(&job_id).expect("server responded with invalid uuid"))
+    }
+
+    pub async fn stop_job(
+        &mut self,
+        job_id: JobId,
+        grace_period: Option<GracePeriod>,
+    ) -> Result<(), Status> {
         let request = Request::new(protobuf::StopRequest {
             job_id: job_id.as_bytes().to_vec(),
+            grace_period,
         });
         let _ = self.inner.stop_job(request).await?;
         println!("Stopped job id: {}", job_id);
         Ok(())
     }
 
-    pub async fn query_status(&mut self, job_id: JobId) -> Result<(), Status> {
+    pub async fn signal_job(&mut self, job_id: JobId, signal: i32) -> Result<(), Status> {
+        let request = Request::new(protobuf::SignalRequest {
+            job_id: job_id.as_bytes().to_vec(),
+            signal,
+        });
+        let _ = self.inner.signal_job(request).await?;
+        println!("Sent signal {} to job id: {}", signal, job_id);
+        Ok(())
+    }
+
+    pub async fn pause_job(&mut self, job_id: JobId) -> Result<(), Status> {
+        let request = Request::new(protobuf::PauseRequest {
+            job_id: job_id.as_bytes().to_vec(),
+        });
+        let _ = self.inner.pause_job(request).await?;
+        println!("Paused job id: {}", job_id);
+        Ok(())
+    }
+
+    pub async fn resume_job(&mut self, job_id: JobId) -> Result<(), Status> {
+        let request = Request::new(protobuf::ResumeRequest {
+            job_id: job_id.as_bytes().to_vec(),
+        });
+        let _ = self.inner.resume_job(request).await?;
+        println!("Resumed job id: {}", job_id);
+        Ok(())
+    }
+
+    pub async fn delete_job(&mut self, job_id: JobId) -> Result<(), Status> {
+        let request = Request::new(protobuf::DeleteJobRequest {
+            job_id: job_id.as_bytes().to_vec(),
+        });
+        let _ = self.inner.delete_job(request).await?;
+        println!("Deleted job id: {}", job_id);
+        Ok(())
+    }
+
+    /// Print a line for every event of the jobs being watched, until the server hangs up.
+    pub async fn watch_jobs(&mut self, job_id: Option<JobId>, owner: String) -> Result<(), Status> {
+        let request = Request::new(WatchJobsRequest {
+            job_id: job_id.map_or(vec![], |job_id| job_id.as_bytes().to_vec()),
+            owner,
+        });
+        let mut events = self.inner.watch_jobs(request).await?.into_inner();
+        let mut stdout = std::io::stdout();
+        while let Some(event) = events.message().await? {
+            let job_id =
+                JobId::from_slice(&event.job_id).expect("server responded with invalid uuid");
+            let what = match event.event {
+                Some(job_event::Event::Started(attempt)) => {
+                    format!("started (attempt {})", attempt)
+                }
+                Some(job_event::Event::Paused(_)) => "paused".to_string(),
+                Some(job_event::Event::Resumed(_)) => "resumed".to_string(),
+                Some(job_event::Event::ExitCode(code)) => format!("exited ({})", code),
+                Some(job_event::Event::KillSignal(signal)) => format!("killed ({})", signal),
+                Some(job_event::Event::Cancelled(_)) => "cancelled".to_string(),
+                Some(job_event::Event::FailedToStart(_)) => "failed to start".to_string(),
+                Some(job_event::Event::Deleted(_)) => "deleted".to_string(),
+                None => continue,
+            };
+            let time = timestamp(event.unix_ms, SecondsFormat::Millis).unwrap_or_default();
+            let line = format!("{}  {}  {}  {}", time, job_id, event.owner, what);
+            if let Err(err) = writeln!(stdout, "{}", line) {
+                if err.kind() == std::io::ErrorKind::BrokenPipe {
+                    break;
+                }
+                let _ = writeln!(std::io::stderr(), "{}", err);
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn query_status(&mut self, job_id: JobId, show_env: bool) -> Result<(), Status> {
         let request = tonic::Request::new(StatusRequest {
             job_id: job_id.as_bytes().to_vec(),
         });
         let response = self.inner.query_status(request).await?;
-        let status = response
-            .into_inner()
-            .job_status
-            .expect("server responded with empty job status");
-        match status {
+        let status_response = response.into_inner();
+        let stop_reason = status_response.stop_reason();
+        let StatusResponse {
+            job_status,
+            isolation,
+            attempt,
+            previous_attempts,
+            stages,
+            environment,
+            name,
+            labels,
+            ..
+        } = status_response;
+        let job_status = job_status.expect("server responded with empty job status");
+        // the stages of a job that hasn't started haven't run
+        let started = !matches!(
+            job_status,
+            JobStatus::QueuePosition(_) | JobStatus::Cancelled(_) | JobStatus::FailedToStart(_)
+        );
+        match job_status {
             JobStatus::Running(_) => println!("Running"),
+            JobStatus::Paused(_) => println!("Paused"),
+            JobStatus::Restarting(_) => println!("Waiting to restart"),
+            JobStatus::QueuePosition(position) => println!("Queued at position: {}", position),
+            JobStatus::Cancelled(_) => println!("Cancelled before it started"),
+            JobStatus::FailedToStart(_) => println!("Failed to start"),
             JobStatus::ExitCode(code) => println!("Exited with code: {}", code),
             JobStatus::KillSignal(signal) => println!("Killed by signal: {}", signal),
         }
+        print_stop_reason(stop_reason, "");
+        if !name.is_empty() {
+            println!("Name: {}", name);
+        }
+        if !labels.is_empty() {
+            let mut labels = Vec::from_iter(labels);
+            labels.sort();
+            let labels: Vec<_> = labels
+                .into_iter()
+                .map(|(key, val)| format!("{}={}", key, val))
+                .collect();
+            println!("Labels: {}", labels.join(", "));
+        }
+        if attempt > 1 {
+            println!("Attempt: {}", attempt);
+            for (n, previous) in previous_attempts.iter().enumerate() {
+                match previous.outcome {
+                    Some(Outcome::ExitCode(code)) => {
+                        println!("  attempt {} exited with code: {}", n + 1, code)
+                    }
+                    Some(Outcome::KillSignal(signal)) => {
+                        println!("  attempt {} killed by signal: {}", n + 1, signal)
+                    }
+                    None => {}
+                }
+                print_stop_reason(previous.stop_reason(), "  ");
+            }
+        }
+        if started && stages.len() > 1 {
+            println!("Pipeline:");
+            for (n, stage) in stages.iter().enumerate() {
+                match stage.outcome {
+                    Some(stage_outcome::Outcome::ExitCode(code)) => {
+                        println!("  stage {} exited with code: {}", n + 1, code)
+                    }
+                    Some(stage_outcome::Outcome::KillSignal(signal)) => {
+                        println!("  stage {} killed by signal: {}", n + 1, signal)
+                    }
+                    None => println!("  stage {} is still running", n + 1),
+                }
+            }
+        }
+        if let Some(isolation) = isolation {
+            let namespaces: Vec<_> = [
+                ("pid", isolation.pid),
+                ("mount", isolation.mount),
+                ("network", isolation.network),
+                ("uts", isolation.uts),
+                ("ipc", isolation.ipc),
+            ]
+            .into_iter()
+            .filter_map(|(name, isolated)| isolated.then_some(name))
+            .collect();
+            if !namespaces.is_empty() {
+                println!("Isolated namespaces: {}", namespaces.join(", "));
+            }
+        }
+        if show_env {
+            println!("Environment:");
+            let mut environment = Vec::from_iter(environment);
+            environment.sort();
+            for (var, val) in environment {
+                println!("  {}={}", var, val);
+            }
+        }
+        Ok(())
+    }
+
+    /// Print who started a job with what, when it ran, and what it used, after its status.
+    pub async fn print_job_info(&mut self, job_id: JobId) -> Result<(), Status> {
+        let job = self.get_job(job_id).await?;
+        if !job.owner.is_empty() {
+            println!("Owner: {}", job.owner);
+        }
+        println!(
+            "Command: {}",
+            [&[job.cmd], &job.args[..]].concat().join(" ")
+        );
+        println!("Directory: {}", job.dir);
+        if job.pid != 0 {
+            println!("PID: {}", job.pid);
+        }
+        let times = [
+            ("Submitted", job.submitted_unix_ms),
+            ("Started", job.started_unix_ms),
+            ("Finished", job.finished_unix_ms),
+        ];
+        for (event, unix_ms) in times {
+            if let Some(time) = timestamp(unix_ms, SecondsFormat::Millis) {
+                println!("{}: {}", event, time);
+            }
+        }
+        if job.started_unix_ms != 0 {
+            println!("Duration: {:?}", Duration::from_millis(job.duration_ms));
+        }
+        if let Some(usage) = job.resource_usage {
+            println!(
+                "CPU time: {:?} user, {:?} system",
+                Duration::from_micros(usage.user_cpu_us),
+                Duration::from_micros(usage.system_cpu_us)
+            );
+            println!("Max RSS: {} KiB", usage.max_rss_bytes / 1024);
+            println!(
+                "Block IO: {} reads, {} writes",
+                usage.block_reads, usage.block_writes
+            );
+        }
+        Ok(())
+    }
+
+    /// Print everything about a job as a JSON object.
+    pub async fn print_job_json(&mut self, job_id: JobId) -> Result<(), Status> {
+        let job = self.get_job(job_id).await?;
+        let state = match job.state() {
+            JobState::Running => "running",
+            JobState::Paused => "paused",
+            JobState::Restarting => "restarting",
+            JobState::Queued => "queued",
+            JobState::Cancelled => "cancelled",
+            JobState::FailedToStart => "failed_to_start",
+            JobState::Exited => "exited",
+            JobState::Killed => "killed",
+        };
+        let (exit_code, kill_signal) = match job.outcome {
+            Some(job_info::Outcome::ExitCode(code)) => (Some(code), None),
+            Some(job_info::Outcome::KillSignal(signal)) => (None, Some(signal)),
+            None => (None, None),
+        };
+        let started = timestamp(job.started_unix_ms, SecondsFormat::Millis);
+        let json = JobInfoJson {
+            job_id: job_id.to_string(),
+            owner: job.owner,
+            name: job.name,
+            labels: job.labels.into_iter().collect(),
+            state: state.to_string(),
+            exit_code,
+            kill_signal,
+            cmd: job.cmd,
+            args: job.args,
+            dir: job.dir,
+            environment: job.environment.into_iter().collect(),
+            submitted: timestamp(job.submitted_unix_ms, SecondsFormat::Millis).unwrap_or_default(),
+            finished: timestamp(job.finished_unix_ms, SecondsFormat::Millis),
+            duration_ms: started.is_some().then_some(job.duration_ms),
+            started,
+            pid: (job.pid != 0).then_some(job.pid),
+            resource_usage: job.resource_usage.map(|usage| ResourceUsageJson {
+                user_cpu_us: usage.user_cpu_us,
+                system_cpu_us: usage.system_cpu_us,
+                max_rss_bytes: usage.max_rss_bytes,
+                block_reads: usage.block_reads,
+                block_writes: usage.block_writes,
+            }),
+        };
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&json).expect("job info is always valid json")
+        );
+        Ok(())
+    }
+
+    async fn get_job(&mut self, job_id: JobId) -> Result<JobInfo, Status> {
+        let request = Request::new(GetJobRequest {
+            job_id: job_id.as_bytes().to_vec(),
+        });
+        let response = self.inner.get_job(request).await?;
+        Ok(response
+            .into_inner()
+            .job
+            .expect("server responded without job info"))
+    }
+
+    pub async fn submit_workflow(&mut self, workflow: WorkflowRequest) -> Result<(), Status> {
+        let response = self.inner.submit_workflow(Request::new(workflow)).await?;
+        let WorkflowResponse { workflow_id, steps } = response.into_inner();
+        let uuid = JobId::from_slice(&workflow_id).expect("server responded with invalid uuid");
+        println!("{}", uuid);
+        for step in steps {
+            let job_id =
+                JobId::from_slice(&step.job_id).expect("server responded with invalid uuid");
+            println!("  {}: {}", step.name, job_id);
+        }
+        Ok(())
+    }
+
+    pub async fn create_schedule(&mut self, schedule: CreateScheduleRequest) -> Result<(), Status> {
+        let response = self.inner.create_schedule(Request::new(schedule)).await?;
+        let CreateScheduleResponse { schedule_id } = response.into_inner();
+        let uuid = JobId::from_slice(&schedule_id).expect("server responded with invalid uuid");
+        println!("{}", uuid);
+        Ok(())
+    }
+
+    pub async fn list_schedules(&mut self) -> Result<(), Status> {
+        let response = self
+            .inner
+            .list_schedules(Request::new(ListSchedulesRequest {}))
+            .await?;
+        for schedule in response.into_inner().schedules {
+            let schedule_id = JobId::from_slice(&schedule.schedule_id)
+                .expect("server responded with invalid uuid");
+            let overlap = match schedule.overlap() {
+                OverlapPolicy::Skip => "skip",
+                OverlapPolicy::Queue => "queue",
+                OverlapPolicy::Allow => "allow",
+            };
+            println!(
+                "{} \"{}\" {} (overlap: {})",
+                schedule_id, schedule.cron, schedule.timezone, overlap
+            );
+            if schedule.next_tick_unix_ms == 0 {
+                println!("  Next tick: never");
+            } else {
+                let next_tick = UNIX_EPOCH + Duration::from_millis(schedule.next_tick_unix_ms);
+                println!(
+                    "  Next tick: {}",
+                    DateTime::<Utc>::from(next_tick).to_rfc3339_opts(SecondsFormat::Secs, true)
+                );
+            }
+            println!(
+                "  Skipped ticks: {}, queued ticks: {}",
+                schedule.skipped, schedule.queued
+            );
+            for job_id in schedule.history {
+                let job_id =
+                    JobId::from_slice(&job_id).expect("server responded with invalid uuid");
+                println!("  {}", job_id);
+            }
+        }
         Ok(())
     }
 
-    /// Stream the requested output
+    /// Print the jobs that match the request as a table, fetching as many pages as it takes to list `limit` jobs.
+    pub async fn list_jobs(
+        &mut self,
+        mut request: ListJobsRequest,
+        limit: Option<usize>,
+    ) -> Result<(), Status> {
+        let mut jobs = Vec::new();
+        loop {
+            let response = self
+                .inner
+                .list_jobs(Request::new(request.clone()))
+                .await?
+                .into_inner();
+            jobs.extend(response.jobs);
+            if response.next_page_token.is_empty() || limit.is_some_and(|limit| jobs.len() >= limit)
+            {
+                break;
+            }
+            request.page_token = response.next_page_token;
+        }
+        jobs.truncate(limit.unwrap_or(jobs.len()));
+
+        let mut rows =
+            vec![["JOB ID", "NAME", "OWNER", "STATUS", "STARTED", "LABELS"].map(String::from)];
+        for job in jobs {
+            let job_id =
+                JobId::from_slice(&job.job_id).expect("server responded with invalid uuid");
+            let status = match (job.state(), job.outcome) {
+                (_, Some(job_summary::Outcome::ExitCode(code))) => format!("exited ({})", code),
+                (_, Some(job_summary::Outcome::KillSignal(signal))) => {
+                    format!("killed ({})", signal)
+                }
+                (JobState::Running, None) => "running".to_string(),
+                (JobState::Paused, None) => "paused".to_string(),
+                (JobState::Restarting, None) => "restarting".to_string(),
+                (JobState::Queued, None) => "queued".to_string(),
+                (JobState::Cancelled, None) => "cancelled".to_string(),
+                (JobState::FailedToStart, None) => "failed to start".to_string(),
+                (JobState::Exited | JobState::Killed, None) => unreachable!(), // they have an outcome
+            };
+            let started = timestamp(job.started_unix_ms, SecondsFormat::Secs)
+                .unwrap_or_else(|| "-".to_string());
+            let mut labels = Vec::from_iter(job.labels);
+            labels.sort();
+            let labels: Vec<_> = labels
+                .into_iter()
+                .map(|(key, val)| format!("{}={}", key, val))
+                .collect();
+            rows.push([
+                job_id.to_string(),
+                job.name,
+                job.owner,
+                status,
+                started,
+                labels.join(","),
+            ]);
+        }
+        print_table(&rows);
+        Ok(())
+    }
+
+    pub async fn delete_schedule(&mut self, schedule_id: JobId) -> Result<(), Status> {
+        let request = Request::new(DeleteScheduleRequest {
+            schedule_id: schedule_id.as_bytes().to_vec(),
+        });
+        self.inner.delete_schedule(request).await?;
+        Ok(())
+    }
+
+    pub async fn query_workflow(&mut self, workflow_id: JobId) -> Result<(), Status> {
+        let request = Request::new(WorkflowStatusRequest {
+            workflow_id: workflow_id.as_bytes().to_vec(),
+        });
+        let response = self.inner.query_workflow(request).await?.into_inner();
+        match response.state() {
+            workflow_status_response::State::Running => println!("Running"),
+            workflow_status_response::State::Succeeded => println!("Succeeded"),
+            workflow_status_response::State::Failed => println!("Failed"),
+        }
+        for step in response.steps {
+            let job_id =
+                JobId::from_slice(&step.job_id).expect("server responded with invalid uuid");
+            let state = match (step.state(), step.outcome) {
+                (_, Some(step_status::Outcome::ExitCode(code))) => {
+                    format!("exited with code: {}", code)
+                }
+                (_, Some(step_status::Outcome::KillSignal(signal))) => {
+                    format!("killed by signal: {}", signal)
+                }
+                (step_status::State::Waiting, None) => "waiting".to_string(),
+                (step_status::State::Running, None) => "running".to_string(),
+                (step_status::State::Skipped, None) => "skipped".to_string(),
+                (step_status::State::FailedToStart, None) => "failed to start".to_string(),
+                (step_status::State::Finished, None) => "finished".to_string(),
+                (step_status::State::Cancelled, None) => "cancelled".to_string(),
+            };
+            println!("  {} ({}): {}", step.name, job_id, state);
+        }
+        Ok(())
+    }
+
+    /// Stream the requested output, from byte `offset` of it. The job's stderr goes to stderr and the rest to stdout,
+    /// with each line started by the time it was captured if `timestamps` is set. If the stream breaks, say where it
+    /// got to, so it can be picked up from there.
     pub async fn stream_output(
         &mut self,
         job_id: JobId,
         output_type: OutputType,
+        offset: u64,
+        timestamps: bool,
     ) -> Result<(), Status> {
         let output_request = OutputRequest {
             job_id: job_id.as_bytes().to_vec(),
             output: output_type.into(),
+            offset,
         };
         let request = Request::new(output_request);
         let response = self.inner.stream_output(request).await?;
         let mut stream = response.into_inner();
         let mut stdout = std::io::stdout();
-        while let Some(OutputResponse { data }) = stream.message().await? {
-            if let Err(err) = write!(stdout, "{}", String::from_utf8_lossy(&data)) {
+        let mut stderr = std::io::stderr();
+        // whether the last output written to stdout and stderr ended a line
+        let (mut stdout_line_start, mut stderr_line_start) = (true, true);
+        let mut position = offset;
+        loop {
+            let event = match stream.message().await {
+                Ok(Some(response)) => response.event,
+                Ok(None) => break,
+                Err(status) => {
+                    let _ = writeln!(
+                        stderr,
+                        "[output stopped at byte {}, continue with --offset {}]",
+                        position, position
+                    );
+                    return Err(status);
+                }
+            };
+            // notices go on stderr, so they don't end up in the output when stdout is redirected
+            let chunk = match event {
+                Some(output_response::Event::Chunk(chunk)) => chunk,
+                Some(output_response::Event::Truncated(truncated)) => {
+                    let _ = writeln!(
+                        stderr,
+                        "[{} bytes of earlier output were discarded by the server]",
+                        truncated
+                    );
+                    continue;
+                }
+                Some(output_response::Event::Skipped(skipped)) => {
+                    let _ = writeln!(
+                        stderr,
+                        "[{} bytes of output were skipped while this client was behind]",
+                        skipped
+                    );
+                    continue;
+                }
+                None => continue,
+            };
+            position = chunk.offset + chunk.data.len() as u64;
+            let (out, line_start): (&mut dyn Write, _) = match chunk.source() {
+                output_chunk::Source::Stderr => (&mut stderr, &mut stderr_line_start),
+                output_chunk::Source::Stdout | output_chunk::Source::Attempt => {
+                    (&mut stdout, &mut stdout_line_start)
+                }
+            };
+            let data = match timestamp(chunk.captured_unix_ms, SecondsFormat::Millis) {
+                Some(time) if timestamps => prefix_lines(&chunk.data, &time, line_start),
+                _ => chunk.data,
+            };
+            if let Err(err) = write!(out, "{}", String::from_utf8_lossy(&data)) {
                 if err.kind() == std::io::ErrorKind::BrokenPipe {
                     break;
                 }
@@ -106,6 +600,121 @@ impl ClientCli {
         }
         Ok(())
     }
+
+    /// Stream local stdin to a job's stdin. Closes the job's stdin when local stdin ends, if `eof` is set.
+    pub async fn write_stdin(&mut self, job_id: JobId, eof: bool) -> Result<(), Status> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let stdin_request = |request| StdinRequest {
+            request: Some(request),
+        };
+        let _ = tx.send(stdin_request(stdin_request::Request::JobId(
+            job_id.as_bytes().to_vec(),
+        )));
+
+        let eof = eof.then(|| stdin_request(stdin_request::Request::Eof(true)));
+        forward_stdin(
+            tx,
+            move |data| stdin_request(stdin_request::Request::Data(data)),
+            eof,
+        );
+
+        self.inner
+            .write_stdin(Request::new(UnboundedReceiverStream::new(rx)))
+            .await?;
+        Ok(())
+    }
+
+    /// Attach the local terminal to a job's terminal until the job's output ends.
+    ///
+    /// Stdin is forwarded as keystrokes in raw mode, and local window size changes are forwarded as resizes.
+    pub async fn attach(&mut self, job_id: JobId) -> Result<(), Status> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let attach = |request| AttachRequest {
+            request: Some(request),
+        };
+        let _ = tx.send(attach(attach_request::Request::JobId(
+            job_id.as_bytes().to_vec(),
+        )));
+        if let Some(size) = terminal::window_size() {
+            let _ = tx.send(attach(attach_request::Request::Resize(size)));
+        }
+
+        let response = self
+            .inner
+            .attach(Request::new(UnboundedReceiverStream::new(rx)))
+            .await?;
+        let mut stream = response.into_inner();
+
+        // only switch to raw mode once the server has accepted the attach, so errors print normally
+        let raw_mode = RawMode::enter().map_err(|err| Status::internal(err.to_string()))?;
+
+        forward_stdin(
+            tx.clone(),
+            move |data| attach(attach_request::Request::Input(data)),
+            None,
+        );
+
+        let mut window_change =
+            signal(SignalKind::window_change()).map_err(|err| Status::internal(err.to_string()))?;
+        tokio::spawn(async move {
+            while window_change.recv().await.is_some() {
+                if let Some(size) = terminal::window_size() {
+                    if tx
+                        .send(attach(attach_request::Request::Resize(size)))
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            }
+        });
+
+        let mut stdout = std::io::stdout();
+        // a truncation notice would only garble the screen, which the job redraws anyway
+        while let Some(OutputResponse { event }) = stream.message().await? {
+            let data = match event {
+                Some(output_response::Event::Chunk(chunk)) => chunk.data,
+                _ => continue,
+            };
+            // terminal output is raw bytes with escape sequences, so write it as is
+            if stdout
+                .write_all(&data)
+                .and_then(|_| stdout.flush())
+                .is_err()
+            {
+                break;
+            }
+        }
+        drop(raw_mode);
+        Ok(())
+    }
+}
+
+/// Send local stdin as requests made by `data`, followed by `eof` once stdin ends.
+///
+/// Stdin is read on a plain thread - a blocking read in tokio's pool would keep the runtime from exiting.
+fn forward_stdin<T: Send + 'static>(
+    tx: mpsc::UnboundedSender<T>,
+    data: impl Fn(Vec<u8>) -> T + Send + 'static,
+    eof: Option<T>,
+) {
+    std::thread::spawn(move || {
+        let mut stdin = std::io::stdin();
+        let mut buf = [0u8; 4096];
+        loop {
+            match stdin.read(&mut buf) {
+                Ok(n) if n > 0 => {
+                    if tx.send(data(buf[..n].to_vec())).is_err() {
+                        return;
+                    }
+                }
+                _ => break,
+            }
+        }
+        if let Some(eof) = eof {
+            let _ = tx.send(eof);
+        }
+    });
 }
 
 async fn build_tls_config(user: &str) -> ClientTlsConfig {
@@ -146,3 +755,90 @@ async fn build_tls_config(user: &str) -> ClientTlsConfig {
         .ca_certificate(server_root_ca_cert)
         .identity(client_identity)
 }
+
+/// `status --json` output. Times are RFC 3339, and are left out along with the pid and duration until they're known.
+#[derive(Serialize)]
+struct JobInfoJson {
+    job_id: String,
+    owner: String,
+    name: String,
+    labels: BTreeMap<String, String>,
+    state: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kill_signal: Option<i32>,
+    cmd: String,
+    args: Vec<String>,
+    dir: String,
+    environment: BTreeMap<String, String>,
+    submitted: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    started: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    finished: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pid: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resource_usage: Option<ResourceUsageJson>,
+}
+
+#[derive(Serialize)]
+struct ResourceUsageJson {
+    user_cpu_us: u64,
+    system_cpu_us: u64,
+    max_rss_bytes: u64,
+    block_reads: u64,
+    block_writes: u64,
+}
+
+/// Format a time the server sent as unix milliseconds, which are zero for a time that hasn't happened.
+fn timestamp(unix_ms: u64, precision: SecondsFormat) -> Option<String> {
+    (unix_ms != 0).then(|| {
+        let time = UNIX_EPOCH + Duration::from_millis(unix_ms);
+        DateTime::<Utc>::from(time).to_rfc3339_opts(precision, true)
+    })
+}
+
+/// Start each line in `data` with `time`. `line_start` says whether the data before it ended a line, and is updated
+/// for the data after it.
+fn prefix_lines(data: &[u8], time: &str, line_start: &mut bool) -> Vec<u8> {
+    let mut prefixed = Vec::with_capacity(data.len());
+    for line in data.split_inclusive(|&byte| byte == b'\n') {
+        if *line_start {
+            prefixed.extend_from_slice(time.as_bytes());
+            prefixed.push(b' ');
+        }
+        prefixed.extend_from_slice(line);
+        *line_start = line.ends_with(b"\n");
+    }
+    prefixed
+}
+
+/// Print rows with their columns aligned, the first row being the header.
+fn print_table<const N: usize>(rows: &[[String; N]]) {
+    let mut widths = [0; N];
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for row in rows {
+        let line: Vec<_> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+fn print_stop_reason(stop_reason: StopReason, indent: &str) {
+    match stop_reason {
+        StopReason::NotStopped => {}
+        StopReason::User => println!("{}Stopped by a user", indent),
+        StopReason::Timeout => println!("{}Stopped after timing out", indent),
+    }
+}
@@ -1,12 +1,18 @@
 mod arg_parser;
 mod client_cli;
+mod terminal;
+mod workflow;
 
-use arg_parser::{ArgParser, SubCommand};
+use arg_parser::{ArgParser, Namespace, Overlap, RestartMode, State, StdinMode, SubCommand};
 use client_cli::ClientCli;
-use protobuf::output_request;
+use protobuf::{
+    output_request, restart_policy, stdin_source, CreateScheduleRequest, GracePeriod, Isolation,
+    JobState, ListJobsRequest, OverlapPolicy, PipelineStage, ResourceLimits, RestartPolicy,
+    StartRequest, StdinSource, StopPolicy,
+};
 
 use clap::Parser;
-use std::error;
+use std::{collections::HashMap, error, io::Read, time::Duration};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn error::Error>> {
@@ -18,28 +24,307 @@ async fn main() -> Result<(), Box<dyn error::Error>> {
             command,
             dir,
             envs,
+            cpu_max,
+            memory_max,
+            io,
+            isolate,
+            tty,
+            stdin,
+            timeout,
+            stop_signal,
+            grace,
+            restart,
+            max_attempts,
+            backoff,
+            max_backoff,
+            pipefail,
+            priority,
+            name,
+            labels,
             args,
         } => {
-            client.start_job(&command, &args, &dir, &envs).await?;
+            let (args, pipeline) = pipeline(args)?;
+            let limits = if cpu_max.is_none() && memory_max.is_none() && io.is_empty() {
+                None
+            } else {
+                Some(ResourceLimits {
+                    cpu: cpu_max.map(|cpu| protobuf::CpuMax {
+                        quota_us: cpu.quota_us,
+                        period_us: cpu.period_us,
+                    }),
+                    memory_max: memory_max.unwrap_or_default(),
+                    io: io
+                        .into_iter()
+                        .map(|io| protobuf::IoLimit {
+                            major: io.major,
+                            minor: io.minor,
+                            weight: io.weight,
+                            rbps: io.rbps,
+                            wbps: io.wbps,
+                            riops: io.riops,
+                            wiops: io.wiops,
+                        })
+                        .collect(),
+                })
+            };
+            let isolation = if isolate.is_empty() {
+                None
+            } else {
+                let all = isolate.contains(&Namespace::All);
+                let isolated = |namespace| all || isolate.contains(&namespace);
+                Some(Isolation {
+                    pid: isolated(Namespace::Pid),
+                    mount: isolated(Namespace::Mount),
+                    network: isolated(Namespace::Network),
+                    uts: isolated(Namespace::Uts),
+                    ipc: isolated(Namespace::Ipc),
+                })
+            };
+            let stdin = match stdin {
+                StdinMode::Null => None,
+                StdinMode::Bytes => {
+                    let mut data = vec![];
+                    std::io::stdin().read_to_end(&mut data)?;
+                    Some(StdinSource {
+                        mode: stdin_source::Mode::Bytes.into(),
+                        data,
+                    })
+                }
+                StdinMode::Stream => Some(StdinSource {
+                    mode: stdin_source::Mode::Stream.into(),
+                    data: vec![],
+                }),
+            };
+            let stop_policy = if stop_signal.is_none() && grace.is_none() {
+                None
+            } else {
+                Some(StopPolicy {
+                    signal: stop_signal.unwrap_or_default(),
+                    grace_period: grace.map(grace_period),
+                })
+            };
+            let restart_policy = match restart {
+                RestartMode::Never => None,
+                RestartMode::OnFailure | RestartMode::Always => {
+                    let mode = match restart {
+                        RestartMode::OnFailure => restart_policy::Mode::OnFailure,
+                        _ => restart_policy::Mode::Always,
+                    };
+                    let millis = |duration: Option<Duration>| {
+                        duration.map_or(0, |duration| duration.as_millis() as u64)
+                    };
+                    Some(RestartPolicy {
+                        mode: mode.into(),
+                        max_attempts: max_attempts.unwrap_or_default(),
+                        backoff_ms: millis(backoff),
+                        max_backoff_ms: millis(max_backoff),
+                    })
+                }
+            };
+            client
+                .start_job(StartRequest {
+                    cmd: command,
+                    args,
+                    dir,
+                    envs: HashMap::from_iter(envs),
+                    limits,
+                    isolation,
+                    tty: tty
+                        .then(|| terminal::window_size().unwrap_or(terminal::DEFAULT_WINDOW_SIZE)),
+                    stdin,
+                    timeout_ms: timeout.map_or(0, |timeout| timeout.as_millis() as u64),
+                    stop_policy,
+                    restart_policy,
+                    pipeline,
+                    pipefail,
+                    priority,
+                    name: name.unwrap_or_default(),
+                    labels: HashMap::from_iter(labels),
+                })
+                .await?;
+        }
+        SubCommand::Stop { job, grace } => {
+            let job_id = client.resolve_job(job).await?;
+            client.stop_job(job_id, grace.map(grace_period)).await?;
+        }
+        SubCommand::Kill { job, signal } => {
+            let job_id = client.resolve_job(job).await?;
+            client.signal_job(job_id, signal).await?;
+        }
+        SubCommand::Attach { job } => {
+            let job_id = client.resolve_job(job).await?;
+            client.attach(job_id).await?;
+        }
+        SubCommand::WriteStdin { job, no_eof } => {
+            let job_id = client.resolve_job(job).await?;
+            client.write_stdin(job_id, !no_eof).await?;
+        }
+        SubCommand::Pause { job } => {
+            let job_id = client.resolve_job(job).await?;
+            client.pause_job(job_id).await?;
+        }
+        SubCommand::Resume { job } => {
+            let job_id = client.resolve_job(job).await?;
+            client.resume_job(job_id).await?;
+        }
+        SubCommand::SubmitWorkflow { file } => {
+            client.submit_workflow(workflow::read(&file)?).await?;
+        }
+        SubCommand::WorkflowStatus { workflow_id } => {
+            client.query_workflow(workflow_id).await?;
+        }
+        SubCommand::Schedule {
+            cron,
+            timezone,
+            overlap,
+            command,
+            dir,
+            envs,
+            timeout,
+            pipefail,
+            priority,
+            args,
+        } => {
+            let (args, pipeline) = pipeline(args)?;
+            let overlap = match overlap {
+                Overlap::Skip => OverlapPolicy::Skip,
+                Overlap::Queue => OverlapPolicy::Queue,
+                Overlap::Allow => OverlapPolicy::Allow,
+            };
+            client
+                .create_schedule(CreateScheduleRequest {
+                    cron,
+                    timezone: timezone.unwrap_or_default(),
+                    job: Some(StartRequest {
+                        cmd: command,
+                        args,
+                        dir,
+                        envs: HashMap::from_iter(envs),
+                        limits: None,
+                        isolation: None,
+                        tty: None,
+                        stdin: None,
+                        timeout_ms: timeout.map_or(0, |timeout| timeout.as_millis() as u64),
+                        stop_policy: None,
+                        restart_policy: None,
+                        pipeline,
+                        pipefail,
+                        priority,
+                        name: String::new(),
+                        labels: HashMap::new(),
+                    }),
+                    overlap: overlap.into(),
+                })
+                .await?;
+        }
+        SubCommand::Schedules => {
+            client.list_schedules().await?;
+        }
+        SubCommand::DeleteSchedule { schedule_id } => {
+            client.delete_schedule(schedule_id).await?;
+        }
+        SubCommand::Delete { job } => {
+            let job_id = client.resolve_job(job).await?;
+            client.delete_job(job_id).await?;
+        }
+        SubCommand::Watch { owner, job } => {
+            let job_id = match job {
+                Some(job) => Some(client.resolve_job(job).await?),
+                None => None,
+            };
+            client.watch_jobs(job_id, owner.unwrap_or_default()).await?;
         }
-        SubCommand::Stop { job_id } => {
-            client.stop_job(job_id).await?;
+        SubCommand::List {
+            owner,
+            status,
+            labels,
+            started_after,
+            started_before,
+            limit,
+        } => {
+            let states = status
+                .into_iter()
+                .map(|state| {
+                    match state {
+                        State::Running => JobState::Running,
+                        State::Paused => JobState::Paused,
+                        State::Restarting => JobState::Restarting,
+                        State::Queued => JobState::Queued,
+                        State::Cancelled => JobState::Cancelled,
+                        State::FailedToStart => JobState::FailedToStart,
+                        State::Exited => JobState::Exited,
+                        State::Killed => JobState::Killed,
+                    }
+                    .into()
+                })
+                .collect();
+            let unix_ms = |time: Option<chrono::DateTime<_>>| {
+                // zero means no bound, so times up to the epoch itself are rounded up
+                time.map_or(0, |time| time.timestamp_millis().max(1) as u64)
+            };
+            client
+                .list_jobs(
+                    ListJobsRequest {
+                        owner: owner.unwrap_or_default(),
+                        states,
+                        labels: HashMap::from_iter(labels),
+                        started_after_unix_ms: unix_ms(started_after),
+                        started_before_unix_ms: unix_ms(started_before),
+                        page_size: 0,
+                        page_token: String::new(),
+                    },
+                    limit,
+                )
+                .await?;
         }
-        SubCommand::Status { job_id } => {
-            client.query_status(job_id).await?;
+        SubCommand::Status { job, env, json } => {
+            let job_id = client.resolve_job(job).await?;
+            if json {
+                client.print_job_json(job_id).await?;
+            } else {
+                client.query_status(job_id, env).await?;
+                client.print_job_info(job_id).await?;
+            }
         }
         SubCommand::Output {
-            job_id,
+            job,
             output_type,
+            offset,
+            timestamps,
         } => {
+            let job_id = client.resolve_job(job).await?;
             let output_type = match output_type {
                 arg_parser::OutputType::Stdout => output_request::OutputType::Stdout,
                 arg_parser::OutputType::Stderr => output_request::OutputType::Stderr,
                 arg_parser::OutputType::All => output_request::OutputType::All,
             };
-            client.stream_output(job_id, output_type).await?
+            client
+                .stream_output(job_id, output_type, offset, timestamps)
+                .await?
         }
     }
 
     Ok(())
 }
+
+fn grace_period(grace: Duration) -> GracePeriod {
+    GracePeriod {
+        millis: grace.as_millis() as u64,
+    }
+}
+
+/// Split the args of `start` into the command's own args, and the rest of the pipeline at each lone |.
+fn pipeline(args: Vec<String>) -> Result<(Vec<String>, Vec<PipelineStage>), String> {
+    let mut stages = args.split(|arg| arg == "|");
+    let args = stages.next().unwrap_or_default().to_vec();
+    let pipeline = stages
+        .map(|stage| match stage.split_first() {
+            Some((cmd, args)) => Ok(PipelineStage {
+                cmd: cmd.clone(),
+                args: args.to_vec(),
+            }),
+            None => Err("Every | must be followed by a command".to_string()),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((args, pipeline))
+}
@@ -0,0 +1,55 @@
+use protobuf::WindowSize;
+use std::{io, mem};
+
+/// Window size used when the local terminal's size can't be read, e.g. when stdout isn't a terminal.
+pub const DEFAULT_WINDOW_SIZE: WindowSize = WindowSize { rows: 24, cols: 80 };
+
+/// Puts the local terminal into raw mode, and restores the original mode on drop.
+///
+/// In raw mode keystrokes like ctrl-c are passed through as input instead of being handled locally,
+/// so they reach the remote job's terminal.
+pub struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    /// Enter raw mode on stdin. Returns `None` if stdin is not a terminal.
+    pub fn enter() -> io::Result<Option<Self>> {
+        if unsafe { libc::isatty(libc::STDIN_FILENO) } != 1 {
+            return Ok(None);
+        }
+        let mut original: libc::termios = unsafe { mem::zeroed() };
+        check(unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) })?;
+        let mut raw = original;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        check(unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) })?;
+        Ok(Some(Self { original }))
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+    }
+}
+
+/// Get the size of the local terminal, if stdout is one.
+pub fn window_size() -> Option<WindowSize> {
+    let mut winsize: libc::winsize = unsafe { mem::zeroed() };
+    let ret = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut winsize) };
+    if ret == -1 || winsize.ws_row == 0 || winsize.ws_col == 0 {
+        return None;
+    }
+    Some(WindowSize {
+        rows: winsize.ws_row.into(),
+        cols: winsize.ws_col.into(),
+    })
+}
+
+fn check(ret: libc::c_int) -> io::Result<()> {
+    if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,90 @@
+use crate::arg_parser;
+use protobuf::{StartRequest, WorkflowRequest, WorkflowStep};
+
+use serde::Deserialize;
+use std::{collections::HashMap, error, fs, path::Path};
+
+/// A workflow file, e.g.
+///
+/// ```yaml
+/// steps:
+///   - name: build
+///     command: make
+///     dir: /src
+///   - name: test
+///     command: make
+///     args: [test]
+///     dir: /src
+///     after_success: [build]
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WorkflowFile {
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Step {
+    name: String,
+    command: String,
+    #[serde(default)]
+    args: Vec<String>,
+    dir: String,
+    #[serde(default)]
+    envs: HashMap<String, String>,
+    /// e.g. 30s, in the same format as start --timeout
+    timeout: Option<String>,
+    /// steps that must finish first, however they end
+    #[serde(default)]
+    after: Vec<String>,
+    /// steps that must exit with code 0 first, or this step is skipped
+    #[serde(default)]
+    after_success: Vec<String>,
+    /// in the same way as start --priority
+    #[serde(default)]
+    priority: i32,
+    /// in the same way as start --label
+    #[serde(default)]
+    labels: HashMap<String, String>,
+}
+
+/// Read a YAML workflow file into a request.
+pub fn read(path: &Path) -> Result<WorkflowRequest, Box<dyn error::Error>> {
+    let file: WorkflowFile = serde_yaml::from_str(&fs::read_to_string(path)?)?;
+    let steps = file
+        .steps
+        .into_iter()
+        .map(|step| {
+            let timeout = match step.timeout {
+                Some(timeout) => arg_parser::duration(&timeout)
+                    .map_err(|err| format!("step {}: {}", step.name, err))?,
+                None => Default::default(),
+            };
+            Ok(WorkflowStep {
+                name: step.name,
+                job: Some(StartRequest {
+                    cmd: step.command,
+                    args: step.args,
+                    dir: step.dir,
+                    envs: step.envs,
+                    limits: None,
+                    isolation: None,
+                    tty: None,
+                    stdin: None,
+                    timeout_ms: timeout.as_millis() as u64,
+                    stop_policy: None,
+                    restart_policy: None,
+                    pipeline: vec![],
+                    pipefail: false,
+                    priority: step.priority,
+                    name: String::new(),
+                    labels: step.labels,
+                }),
+                after: step.after,
+                after_success: step.after_success,
+            })
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(WorkflowRequest { steps })
+}
@@ -1,3 +1,4 @@
 mod broadcaster;
 pub mod coordinator;
+pub mod scheduler;
 mod worker;
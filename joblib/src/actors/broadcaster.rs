@@ -1,10 +1,17 @@
 mod actor;
 mod messages;
-use crate::{events::OutputBlob, types::Output};
+mod spool;
+mod stream;
+use crate::{
+    events::OutputEvent,
+    types::{JobId, Output, SpoolConfig, SubscriberQueue},
+};
 use actor::Actor;
 use messages::StreamRequest;
+use spool::Spool;
+use std::io;
 
-use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::mpsc::{self, Receiver, UnboundedReceiver};
 
 /// A `Broadcaster` which can add subscribers, receive output, and broadcast the output to all subscribers.
 ///
@@ -12,24 +19,55 @@ use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 #[derive(Clone)]
 pub struct BroadcasterHandle {
     sender: mpsc::UnboundedSender<StreamRequest>,
+    queue_capacity: usize,
 }
 
 impl BroadcasterHandle {
-    pub fn spawn(output_rx: UnboundedReceiver<Output>) -> Self {
+    /// Spawn a broadcaster for a job's output. With a spool config the output is written to the job's own spool
+    /// directory, which is created here and removed once the broadcaster exits. Otherwise it is kept in memory, and
+    /// once there is more than `budget` bytes of it the oldest is dropped. Each subscriber gets its own queue of
+    /// output, and subscribers that let it fill up are dealt with by the queue's policy.
+    pub fn spawn(
+        job_id: JobId,
+        output_rx: UnboundedReceiver<Output>,
+        spool: Option<&SpoolConfig>,
+        budget: Option<usize>,
+        queue: SubscriberQueue,
+    ) -> io::Result<Self> {
+        let (spool, hot_tail) = match spool {
+            Some(config) => (Some(Spool::create(job_id, config)?), Some(config.hot_tail)),
+            None => (None, budget),
+        };
         let (sender, inbox) = mpsc::unbounded_channel();
-        Actor::spawn(inbox, output_rx);
-        Self { sender }
+        Actor::spawn(inbox, output_rx, spool, hot_tail, queue);
+        Ok(Self {
+            sender,
+            queue_capacity: queue.capacity,
+        })
     }
 
-    pub fn stream_stdout(&self, subscriber: UnboundedSender<OutputBlob>) {
-        let _ = self.sender.send(StreamRequest::Stdout { subscriber });
+    /// Stream stdout from byte `offset` of it, so a subscriber that was cut off can pick up where it stopped.
+    pub fn stream_stdout(&self, offset: u64) -> Receiver<OutputEvent> {
+        let (subscriber, rx) = mpsc::channel(self.queue_capacity);
+        let _ = self
+            .sender
+            .send(StreamRequest::Stdout { subscriber, offset });
+        rx
     }
 
-    pub fn stream_stderr(&self, subscriber: UnboundedSender<OutputBlob>) {
-        let _ = self.sender.send(StreamRequest::Stderr { subscriber });
+    /// Stream stderr from byte `offset` of it.
+    pub fn stream_stderr(&self, offset: u64) -> Receiver<OutputEvent> {
+        let (subscriber, rx) = mpsc::channel(self.queue_capacity);
+        let _ = self
+            .sender
+            .send(StreamRequest::Stderr { subscriber, offset });
+        rx
     }
 
-    pub fn stream_all(&self, subscriber: UnboundedSender<OutputBlob>) {
-        let _ = self.sender.send(StreamRequest::All { subscriber });
+    /// Stream stdout and stderr as they were written, from byte `offset` of the two together.
+    pub fn stream_all(&self, offset: u64) -> Receiver<OutputEvent> {
+        let (subscriber, rx) = mpsc::channel(self.queue_capacity);
+        let _ = self.sender.send(StreamRequest::All { subscriber, offset });
+        rx
     }
 }
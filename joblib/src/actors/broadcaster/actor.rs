@@ -1,16 +1,50 @@
 use super::messages::StreamRequest;
-use crate::events::OutputBlob;
-use crate::types::Output;
-
+use super::spool::{Spool, SpoolReader};
+use super::stream::{Offsets, Record, Stream};
+use crate::events::{OutputChunk, OutputEvent};
+use crate::types::{Output, SlowSubscriberPolicy, SubscriberQueue};
+use std::collections::VecDeque;
+use std::time::SystemTime;
 use tokio::select;
-use tokio::sync::mpsc;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::task;
+
+/// records read from the spool at a time for a subscriber catching up
+const REPLAY_BATCH: usize = 64;
+
+/// A subscriber, and how far it has got through its stream.
+pub struct Subscriber {
+    stream: Stream,
+    tx: mpsc::Sender<OutputEvent>,
+    /// the byte offset in its stream of the next output it gets. Everything before it was sent, or it was told it
+    /// missed it.
+    position: u64,
+    /// bytes it missed since it was last told, when it skips ahead
+    skipped: u64,
+}
+
+/// What became of live output sent to a subscriber.
+enum Delivery {
+    Sent,
+    Full,
+    Closed,
+}
 
 pub struct Actor {
     inbox: mpsc::UnboundedReceiver<StreamRequest>,
     output_rx: mpsc::UnboundedReceiver<Output>, // channel broadcaster gets Output events from
-    output_buffer: Vec<Output>, // remember all Output events we received in the same order we got them
-    stdout_subscribers: Vec<mpsc::UnboundedSender<OutputBlob>>,
-    stderr_subscribers: Vec<mpsc::UnboundedSender<OutputBlob>>,
+    spool: Option<Spool>,
+    tail: VecDeque<Record>, // the latest Output events we recorded, in the same order we got them
+    tail_bytes: usize,
+    hot_tail: Option<usize>, // bytes of output to keep in tail. None to keep it all.
+    recorded: Offsets,       // where the output we recorded ends
+    written: Offsets,        // where the output we got ends, recorded or not
+    next_seq: u64,           // the sequence number of the next Output we get
+    subscribers: Vec<Subscriber>, // subscribers that are caught up, and get output as it comes
+    slow: SlowSubscriberPolicy,
+    // subscribers come back here once they have room for more, or have read what they could from the spool
+    catch_up_tx: mpsc::UnboundedSender<Subscriber>,
+    catch_up_rx: mpsc::UnboundedReceiver<Subscriber>,
     output_pending: bool,
 }
 
@@ -18,13 +52,25 @@ impl Actor {
     pub fn spawn(
         inbox: mpsc::UnboundedReceiver<StreamRequest>,
         output_rx: mpsc::UnboundedReceiver<Output>,
+        spool: Option<Spool>,
+        hot_tail: Option<usize>,
+        queue: SubscriberQueue,
     ) {
+        let (catch_up_tx, catch_up_rx) = mpsc::unbounded_channel();
         let actor = Actor {
             inbox,
             output_rx,
-            output_buffer: Vec::new(),
-            stdout_subscribers: Vec::new(),
-            stderr_subscribers: Vec::new(),
+            spool,
+            tail: VecDeque::new(),
+            tail_bytes: 0,
+            hot_tail,
+            recorded: Offsets::default(),
+            written: Offsets::default(),
+            next_seq: 0,
+            subscribers: Vec::new(),
+            slow: queue.slow,
+            catch_up_tx,
+            catch_up_rx,
             output_pending: true, // keep listening for output? keep adding stream subscribers?
         };
         tokio::spawn(async move { actor.run().await });
@@ -36,11 +82,13 @@ impl Actor {
             select! {
                 maybe_stream_req = self.inbox.recv() => {
                     if let Some(req) = maybe_stream_req {
-                        match req {
-                            Stdout { subscriber } => self.stream_stdout(subscriber),
-                            Stderr { subscriber  } => self.stream_stderr(subscriber),
-                            All { subscriber } => self.stream_all(subscriber),
-                        }
+                        let (stream, tx, offset) = match req {
+                            Stdout { subscriber, offset } => (Stream::Stdout, subscriber, offset),
+                            Stderr { subscriber, offset } => (Stream::Stderr, subscriber, offset),
+                            All { subscriber, offset } => (Stream::All, subscriber, offset),
+                        };
+                        // a new subscriber catches up from where it asked to start
+                        self.catch_up(Subscriber { stream, tx, position: offset, skipped: 0 });
                     } else {
                         // actor handle dropped, broadcaster actor can exit now
                         return;
@@ -53,97 +101,242 @@ impl Actor {
                         }
                         None => {
                             // output_tx closed/dropped
-                            // clear the subscribers so they are notified that no more output is coming.
-                            self.stdout_subscribers.clear();
-                            self.stderr_subscribers.clear();
+                            // drop the subscribers so they are notified that no more output is coming, once they
+                            // know what they skipped
+                            for subscriber in self.subscribers.drain(..) {
+                                if subscriber.skipped > 0 {
+                                    tokio::spawn(async move {
+                                        let _ = subscriber.tx.send(OutputEvent::Gap(subscriber.skipped)).await;
+                                    });
+                                }
+                            }
                             // we can stop listening for output
                             self.output_pending = false;
                         }
                     }
                 }
+                Some(subscriber) = self.catch_up_rx.recv() => self.catch_up(subscriber),
             }
         }
     }
 
     fn broadcast(&mut self, output: Output) {
-        // record the event
-        self.output_buffer.push(output.clone());
-
-        use self::Output::*;
-        match output {
-            Stdout(blob) => {
-                // only retain subscribers who have not dropped
-                self.stdout_subscribers
-                    .retain(|sub| sub.send(blob.clone()).is_ok());
+        let record = Record {
+            seq: self.next_seq,
+            timestamp: SystemTime::now(),
+            offsets: self.written,
+            output,
+        };
+        self.next_seq += 1;
+        self.written.advance(&record.output);
+        let skip = self.slow == SlowSubscriberPolicy::Skip;
+        let mut lagging = vec![];
+        // only retain subscribers who have not dropped, and are keeping up
+        for mut subscriber in std::mem::take(&mut self.subscribers) {
+            match subscriber.send_live(&record, skip) {
+                Delivery::Sent => self.subscribers.push(subscriber),
+                Delivery::Full => lagging.push(subscriber),
+                Delivery::Closed => {}
             }
-            Stderr(blob) => {
-                // only retain subscribers who have not dropped
-                self.stderr_subscribers
-                    .retain(|sub| sub.send(blob.clone()).is_ok());
+        }
+        self.record(record);
+
+        for subscriber in lagging {
+            if self.slow == SlowSubscriberPolicy::Disconnect {
+                tokio::spawn(async move {
+                    let _ = subscriber.tx.send(OutputEvent::Lagged).await;
+                });
+            } else {
+                // it picks up from this output once it has room
+                self.park(subscriber);
             }
         }
     }
 
-    fn stream_stdout(&mut self, output_tx: mpsc::UnboundedSender<OutputBlob>) {
-        for blob in self
-            .output_buffer
-            .iter()
-            .filter_map(|output| match output {
-                Output::Stdout(blob) => Some(blob),
-                _ => None,
-            })
-            .cloned()
-        {
-            if output_tx.send(blob).is_err() {
-                // if receiver drops, that's fine, just ignore the error and stop sending
-                // skip adding the subscriber too
-                return;
+    /// Remember output for later subscribers. Only what the spool kept is remembered, so the tail is always the end of
+    /// what's on disk.
+    fn record(&mut self, record: Record) {
+        let record = match &mut self.spool {
+            Some(spool) => match spool.append(record) {
+                Some(spooled) => spooled,
+                None => return,
+            },
+            None => record,
+        };
+        self.recorded.advance(&record.output);
+        self.tail_bytes += data_len(&record.output);
+        self.tail.push_back(record);
+        if let Some(hot_tail) = self.hot_tail {
+            while self.tail_bytes > hot_tail {
+                let mut oldest = match self.tail.pop_front() {
+                    Some(oldest) => oldest,
+                    None => break,
+                };
+                // only the start of the oldest blob goes if that's enough
+                let excess = self.tail_bytes - hot_tail;
+                let start = match &mut oldest.output {
+                    Output::Stdout(blob) if blob.len() > excess => {
+                        Output::Stdout(blob.split_to(excess))
+                    }
+                    Output::Stderr(blob) if blob.len() > excess => {
+                        Output::Stderr(blob.split_to(excess))
+                    }
+                    evicted => {
+                        self.tail_bytes -= data_len(evicted);
+                        continue;
+                    }
+                };
+                self.tail_bytes -= data_len(&start);
+                oldest.offsets.advance(&start);
+                self.tail.push_front(oldest);
             }
         }
-        if self.output_pending {
-            self.stdout_subscribers.push(output_tx);
-        }
     }
 
-    fn stream_stderr(&mut self, output_tx: mpsc::UnboundedSender<OutputBlob>) {
-        for blob in self
-            .output_buffer
-            .iter()
-            .filter_map(|output| match output {
-                Output::Stderr(blob) => Some(blob),
-                _ => None,
-            })
-            .cloned()
-        {
-            if output_tx.send(blob).is_err() {
-                // if receiver drops, that's fine, just ignore the error and stop sending
-                // skip adding the subscriber too
-                return;
+    /// Send a subscriber the recorded output it hasn't had yet, as fast as it takes it, and have it follow live output
+    /// once it has caught up.
+    fn catch_up(&mut self, mut subscriber: Subscriber) {
+        let stream = subscriber.stream;
+        let tail_start = match self.tail.front() {
+            Some(oldest) => oldest.offsets.of(stream),
+            None => self.recorded.of(stream),
+        };
+        if subscriber.position < tail_start {
+            match &self.spool {
+                // the rest of what it needs starts on disk
+                Some(spool) => {
+                    let reader = spool.reader(stream, subscriber.position);
+                    return self.replay_from_disk(subscriber, reader);
+                }
+                // tell it about the output that was dropped before it got to it
+                None => {
+                    let missed = tail_start - subscriber.position;
+                    let marker = if subscriber.position == 0 {
+                        OutputEvent::Truncated(missed)
+                    } else {
+                        OutputEvent::Gap(missed)
+                    };
+                    match subscriber.tx.try_send(marker) {
+                        Ok(()) => subscriber.position = tail_start,
+                        Err(TrySendError::Full(_)) => return self.park(subscriber),
+                        Err(TrySendError::Closed(_)) => return,
+                    }
+                }
+            }
+        }
+
+        // skip the output it already has
+        let start = self.tail.partition_point(|record| {
+            let mut end = record.offsets;
+            end.advance(&record.output);
+            end.of(stream) <= subscriber.position
+        });
+        for record in self.tail.range(start..) {
+            if let Some(chunk) = record.chunk(stream, subscriber.position) {
+                let end = chunk_end(&chunk);
+                match subscriber.tx.try_send(OutputEvent::Data(chunk)) {
+                    Ok(()) => subscriber.position = end,
+                    Err(TrySendError::Full(_)) => return self.park(subscriber),
+                    // if receiver drops, that's fine, just stop sending
+                    Err(TrySendError::Closed(_)) => return,
+                }
             }
         }
         if self.output_pending {
-            self.stderr_subscribers.push(output_tx);
+            self.subscribers.push(subscriber);
         }
     }
 
-    fn stream_all(&mut self, output_tx: mpsc::UnboundedSender<OutputBlob>) {
-        for blob in self
-            .output_buffer
-            .iter()
-            .map(|output| match output {
-                Output::Stdout(blob) | Output::Stderr(blob) => blob,
-            })
-            .cloned()
-        {
-            if output_tx.send(blob).is_err() {
-                // if receiver drops, that's fine, just ignore the error and stop sending
-                // skip adding the subscriber too
-                return;
+    /// Wait for a subscriber to make room, off the actor, then carry on catching it up.
+    fn park(&self, subscriber: Subscriber) {
+        let catch_up_tx = self.catch_up_tx.clone();
+        tokio::spawn(async move {
+            if subscriber.tx.reserve().await.is_ok() {
+                let _ = catch_up_tx.send(subscriber);
+            }
+        });
+    }
+
+    /// Send a subscriber what it needs from the spool, off the actor and at its own pace, then carry on catching it
+    /// up.
+    fn replay_from_disk(&self, mut subscriber: Subscriber, mut reader: SpoolReader) {
+        let catch_up_tx = self.catch_up_tx.clone();
+        tokio::spawn(async move {
+            loop {
+                let read = task::spawn_blocking(move || {
+                    let batch = reader.next_batch(REPLAY_BATCH);
+                    (reader, batch)
+                })
+                .await;
+                let batch = match read {
+                    Ok((unfinished, Ok(batch))) if !batch.is_empty() => {
+                        reader = unfinished;
+                        batch
+                    }
+                    Ok((_, Ok(_))) => break,
+                    // end the stream rather than leave a hole in it
+                    _ => return,
+                };
+                for record in batch {
+                    if let Some(chunk) = record.chunk(subscriber.stream, subscriber.position) {
+                        let end = chunk_end(&chunk);
+                        if subscriber.tx.send(OutputEvent::Data(chunk)).await.is_err() {
+                            return;
+                        }
+                        subscriber.position = end;
+                    }
+                }
+            }
+            let _ = catch_up_tx.send(subscriber);
+        });
+    }
+}
+
+impl Subscriber {
+    /// Send live output to a subscriber that has caught up. One that skips ahead drops what it has no room for.
+    fn send_live(&mut self, record: &Record, skip: bool) -> Delivery {
+        let chunk = match record.chunk(self.stream, self.position) {
+            Some(chunk) => chunk,
+            None => return Delivery::Sent,
+        };
+        let len = chunk.data.len() as u64;
+        let end = chunk_end(&chunk);
+        if self.skipped > 0 {
+            match self.tx.try_send(OutputEvent::Gap(self.skipped)) {
+                Ok(()) => self.skipped = 0,
+                Err(TrySendError::Full(_)) => {
+                    self.skipped += len;
+                    self.position = end;
+                    return Delivery::Sent;
+                }
+                Err(TrySendError::Closed(_)) => return Delivery::Closed,
             }
         }
-        if self.output_pending {
-            self.stdout_subscribers.push(output_tx.clone());
-            self.stderr_subscribers.push(output_tx);
+        match self.tx.try_send(OutputEvent::Data(chunk)) {
+            Ok(()) => {
+                self.position = end;
+                Delivery::Sent
+            }
+            Err(TrySendError::Full(_)) if skip => {
+                self.skipped += len;
+                self.position = end;
+                Delivery::Sent
+            }
+            Err(TrySendError::Full(_)) => Delivery::Full,
+            Err(TrySendError::Closed(_)) => Delivery::Closed,
         }
     }
 }
+
+/// The offset just past a chunk, in its stream.
+fn chunk_end(chunk: &OutputChunk) -> u64 {
+    chunk.offset + chunk.data.len() as u64
+}
+
+/// Bytes of the job's own output in some output. Attempt markers aren't part of it.
+fn data_len(output: &Output) -> usize {
+    match output {
+        Output::Stdout(blob) | Output::Stderr(blob) => blob.len(),
+        Output::Attempt(_) => 0,
+    }
+}
@@ -1,15 +1,18 @@
-use crate::events::OutputBlob;
+use crate::events::OutputEvent;
 use tokio::sync::mpsc;
 
 #[derive(Debug)]
 pub enum StreamRequest {
     Stdout {
-        subscriber: mpsc::UnboundedSender<OutputBlob>,
+        subscriber: mpsc::Sender<OutputEvent>,
+        offset: u64,
     },
     Stderr {
-        subscriber: mpsc::UnboundedSender<OutputBlob>,
+        subscriber: mpsc::Sender<OutputEvent>,
+        offset: u64,
     },
     All {
-        subscriber: mpsc::UnboundedSender<OutputBlob>,
+        subscriber: mpsc::Sender<OutputEvent>,
+        offset: u64,
     },
 }
@@ -0,0 +1,316 @@
+use super::stream::{Offsets, Record, Stream};
+use crate::events::OutputBlob;
+use crate::types::{JobId, Output, SpoolConfig};
+
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::oneshot;
+
+const STDOUT: u8 = 0;
+const STDERR: u8 = 1;
+const ATTEMPT: u8 = 2;
+/// a record's kind, timestamp and length
+const HEADER_LEN: u64 = 13;
+
+/// A job's output on disk: a directory of append-only segment files, each holding records of a one byte kind, a
+/// little endian u64 timestamp in microseconds since the epoch, a little endian u32 length, and the data. A new segment is started once the last one is full, and the directory
+/// is removed when the spool is dropped.
+///
+/// The spool keeps track of what's in it as records are appended, but the files are written by a thread of its own, so
+/// a slow disk never holds up the broadcaster.
+pub struct Spool {
+    dir: PathBuf,
+    segment_size: u64,
+    max_bytes: Option<u64>,
+    /// the writes for the spool's thread, in order
+    writes: mpsc::Sender<SpoolWrite>,
+    /// a write failed, so the files end short of what the spool says it holds
+    failed: Arc<AtomicBool>,
+    /// the sequence number of the first record in each segment, and where in the output it starts. The last segment
+    /// is the one being written to.
+    segment_starts: Vec<(u64, Offsets)>,
+    segment_len: u64,
+    total: u64,
+    /// output stopped being spooled, because it went over the cap or couldn't be written
+    full: bool,
+}
+
+/// Reads a spool back, one batch of records at a time, from the start of a segment up to the end of the spool when the
+/// reader was made.
+///
+/// This blocks on the disk, so it's meant to be run with `spawn_blocking`.
+pub struct SpoolReader {
+    dir: PathBuf,
+    /// whether everything appended before the reader was made got written, once it has been
+    written: Option<oneshot::Receiver<bool>>,
+    segment_starts: Vec<(u64, Offsets)>,
+    /// how much of the last segment there was
+    last_len: u64,
+    /// the segment being read, and where in it
+    segment: usize,
+    reader: Option<io::Take<BufReader<File>>>,
+    seq: u64,
+    offsets: Offsets,
+}
+
+impl Spool {
+    pub fn create(job_id: JobId, config: &SpoolConfig) -> io::Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+        let dir = config.dir.join(job_id.to_hyphenated().to_string());
+        fs::create_dir(&dir)?;
+        let segment = File::create(segment_path(&dir, 0))?;
+        let (writes, queued) = mpsc::channel();
+        let failed = Arc::new(AtomicBool::new(false));
+        let writer = Writer {
+            dir: dir.clone(),
+            segment,
+            failed: failed.clone(),
+        };
+        thread::Builder::new()
+            .name(format!("spool-{}", job_id))
+            .spawn(move || writer.run(queued))?;
+        Ok(Self {
+            dir,
+            segment_size: config.segment_size,
+            max_bytes: config.max_job_bytes,
+            writes,
+            failed,
+            segment_starts: vec![(0, Offsets::default())],
+            segment_len: 0,
+            total: 0,
+            full: false,
+        })
+    }
+
+    /// Read back the records spooled so far, starting from the segment that byte `offset` of `stream` is in. Records
+    /// before `offset` are read too, and it's up to the caller to skip them. The reader waits for them to be written
+    /// before it reads any.
+    pub fn reader(&self, stream: Stream, offset: u64) -> SpoolReader {
+        // the last segment that starts at or before `offset`
+        let segment = self
+            .segment_starts
+            .partition_point(|(_, start)| start.of(stream) <= offset)
+            - 1;
+        let (seq, offsets) = self.segment_starts[segment];
+        let (written_tx, written) = oneshot::channel();
+        let _ = self.writes.send(SpoolWrite::Flush(written_tx));
+        SpoolReader {
+            dir: self.dir.clone(),
+            written: Some(written),
+            segment_starts: self.segment_starts.clone(),
+            last_len: self.segment_len,
+            segment,
+            reader: None,
+            seq,
+            offsets,
+        }
+    }
+
+    /// Append a record to the spool, and get back what was spooled. Output that would go over the cap is cut short, and
+    /// once a job's output reaches the cap, or a write fails, nothing more is spooled.
+    pub fn append(&mut self, mut record: Record) -> Option<Record> {
+        if self.failed.load(Ordering::Relaxed) {
+            self.full = true;
+        }
+        if self.full {
+            return None;
+        }
+        match self.max_bytes.map(|max| max - self.total) {
+            Some(room) if record_len(&record.output) > room => {
+                self.full = true;
+                record.output = match record.output {
+                    Output::Stdout(blob) if room > HEADER_LEN => {
+                        Output::Stdout(blob.slice(..(room - HEADER_LEN) as usize))
+                    }
+                    Output::Stderr(blob) if room > HEADER_LEN => {
+                        Output::Stderr(blob.slice(..(room - HEADER_LEN) as usize))
+                    }
+                    _ => return None,
+                };
+            }
+            _ => {}
+        }
+        let encoded = encode(&record);
+        let len = encoded.len() as u64;
+        if self.segment_len > 0 && self.segment_len + len > self.segment_size {
+            self.rotate(&record);
+        }
+        if self.writes.send(SpoolWrite::Append(encoded)).is_err() {
+            self.full = true;
+            return None;
+        }
+        self.segment_len += len;
+        self.total += len;
+        Some(record)
+    }
+
+    /// Start a new segment with `record`.
+    fn rotate(&mut self, record: &Record) {
+        let path = segment_path(&self.dir, self.segment_starts.len());
+        let _ = self.writes.send(SpoolWrite::Rotate(path));
+        self.segment_starts.push((record.seq, record.offsets));
+        self.segment_len = 0;
+    }
+}
+
+/// What the spool's thread does next.
+enum SpoolWrite {
+    /// write an encoded record to the current segment
+    Append(Vec<u8>),
+    /// start writing to a new segment
+    Rotate(PathBuf),
+    /// report whether everything before this was written
+    Flush(oneshot::Sender<bool>),
+}
+
+/// Writes a spool's files on a thread of its own, and removes them once the spool is dropped.
+struct Writer {
+    dir: PathBuf,
+    segment: File,
+    failed: Arc<AtomicBool>,
+}
+
+impl Writer {
+    fn run(mut self, writes: mpsc::Receiver<SpoolWrite>) {
+        let mut ok = true;
+        // the writes end when the spool is dropped
+        for write in writes {
+            match write {
+                // a single write per record, which lands in the page cache rather than waiting on the disk
+                SpoolWrite::Append(encoded) if ok => ok = self.segment.write_all(&encoded).is_ok(),
+                SpoolWrite::Rotate(path) if ok => match File::create(path) {
+                    Ok(segment) => self.segment = segment,
+                    Err(_) => ok = false,
+                },
+                SpoolWrite::Flush(written) => {
+                    let _ = written.send(ok);
+                }
+                // nothing after a failed write is written, so the files don't have holes in them
+                _ => {}
+            }
+            if !ok {
+                self.failed.store(true, Ordering::Relaxed);
+            }
+        }
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+impl SpoolReader {
+    /// Read up to `max` more records. An empty batch means the reader got to the end.
+    pub fn next_batch(&mut self, max: usize) -> io::Result<Vec<Record>> {
+        if let Some(written) = self.written.take() {
+            if !written.blocking_recv().unwrap_or(false) {
+                return Err(io::Error::other("the spool couldn't be written"));
+            }
+        }
+        let mut batch = vec![];
+        while batch.len() < max {
+            let reader = match &mut self.reader {
+                Some(reader) => reader,
+                None if self.segment < self.segment_starts.len() => {
+                    let file = File::open(segment_path(&self.dir, self.segment))?;
+                    let len = if self.segment + 1 == self.segment_starts.len() {
+                        self.last_len
+                    } else {
+                        u64::MAX
+                    };
+                    (self.seq, self.offsets) = self.segment_starts[self.segment];
+                    self.reader.insert(BufReader::new(file).take(len))
+                }
+                None => break,
+            };
+            match decode(reader)? {
+                Some((timestamp, output)) => {
+                    let offsets = self.offsets;
+                    self.offsets.advance(&output);
+                    batch.push(Record {
+                        seq: self.seq,
+                        timestamp,
+                        offsets,
+                        output,
+                    });
+                    self.seq += 1;
+                }
+                None => {
+                    self.reader = None;
+                    self.segment += 1;
+                }
+            }
+        }
+        Ok(batch)
+    }
+}
+
+fn segment_path(dir: &Path, segment: usize) -> PathBuf {
+    dir.join(format!("{:08}.spool", segment))
+}
+
+fn record_len(output: &Output) -> u64 {
+    HEADER_LEN
+        + match output {
+            Output::Stdout(blob) | Output::Stderr(blob) => blob.len() as u64,
+            Output::Attempt(_) => 4,
+        }
+}
+
+fn encode(record: &Record) -> Vec<u8> {
+    let attempt;
+    let (kind, data) = match &record.output {
+        Output::Stdout(blob) => (STDOUT, &blob[..]),
+        Output::Stderr(blob) => (STDERR, &blob[..]),
+        Output::Attempt(n) => {
+            attempt = n.to_le_bytes();
+            (ATTEMPT, &attempt[..])
+        }
+    };
+    let timestamp = record
+        .timestamp
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64;
+    let mut encoded = Vec::with_capacity(record_len(&record.output) as usize);
+    encoded.push(kind);
+    encoded.extend_from_slice(&timestamp.to_le_bytes());
+    encoded.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    encoded.extend_from_slice(data);
+    encoded
+}
+
+/// Read the next record's timestamp and output, or `None` at the end of the segment.
+fn decode(reader: &mut impl Read) -> io::Result<Option<(SystemTime, Output)>> {
+    let mut header = [0; HEADER_LEN as usize];
+    match reader.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let timestamp = u64::from_le_bytes(header[1..9].try_into().unwrap());
+    let timestamp = UNIX_EPOCH + Duration::from_micros(timestamp);
+    let len = u32::from_le_bytes(header[9..13].try_into().unwrap()) as usize;
+    let mut data = vec![0; len];
+    reader.read_exact(&mut data)?;
+    let output = match header[0] {
+        STDOUT => Output::Stdout(OutputBlob::from(data)),
+        STDERR => Output::Stderr(OutputBlob::from(data)),
+        ATTEMPT if len == 4 => {
+            Output::Attempt(u32::from_le_bytes([data[0], data[1], data[2], data[3]]))
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "corrupt spool record",
+            ))
+        }
+    };
+    Ok(Some((timestamp, output)))
+}
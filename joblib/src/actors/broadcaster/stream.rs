@@ -0,0 +1,85 @@
+use crate::events::{OutputBlob, OutputChunk, OutputSource};
+use crate::types::Output;
+use std::time::SystemTime;
+
+/// Which part of a job's output a subscriber gets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+    All,
+}
+
+/// A point in a job's output, as how far into each stream it is. Every stream has the attempt markers in it, so they
+/// count towards every stream's offsets.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Offsets {
+    stdout: u64,
+    stderr: u64,
+    markers: u64,
+}
+
+impl Offsets {
+    /// The byte offset in `stream`.
+    pub fn of(&self, stream: Stream) -> u64 {
+        match stream {
+            Stream::Stdout => self.stdout + self.markers,
+            Stream::Stderr => self.stderr + self.markers,
+            Stream::All => self.stdout + self.stderr + self.markers,
+        }
+    }
+
+    /// Move past some output.
+    pub fn advance(&mut self, output: &Output) {
+        match output {
+            Output::Stdout(blob) => self.stdout += blob.len() as u64,
+            Output::Stderr(blob) => self.stderr += blob.len() as u64,
+            Output::Attempt(attempt) => self.markers += attempt_marker(*attempt).len() as u64,
+        }
+    }
+}
+
+/// Output as the broadcaster got it: the order it came in, when it came, and where it starts.
+#[derive(Clone)]
+pub struct Record {
+    pub seq: u64,
+    pub timestamp: SystemTime,
+    pub offsets: Offsets,
+    pub output: Output,
+}
+
+impl Record {
+    /// What a subscriber to `stream` that has had everything before byte `position` of it sees of this record, if
+    /// anything.
+    pub fn chunk(&self, stream: Stream, position: u64) -> Option<OutputChunk> {
+        let blob = view(stream, &self.output)?;
+        let offset = self.offsets.of(stream);
+        let seen = position.saturating_sub(offset) as usize;
+        (seen < blob.len()).then(|| OutputChunk {
+            offset: offset + seen as u64,
+            seq: self.seq,
+            source: match self.output {
+                Output::Stdout(_) => OutputSource::Stdout,
+                Output::Stderr(_) => OutputSource::Stderr,
+                Output::Attempt(_) => OutputSource::Attempt,
+            },
+            timestamp: self.timestamp,
+            data: blob.slice(seen..),
+        })
+    }
+}
+
+/// What a subscriber to `stream` sees of some output, if anything. Every stream shows where each attempt starts.
+fn view(stream: Stream, output: &Output) -> Option<OutputBlob> {
+    match (stream, output) {
+        (Stream::Stdout | Stream::All, Output::Stdout(blob))
+        | (Stream::Stderr | Stream::All, Output::Stderr(blob)) => Some(blob.clone()),
+        (_, Output::Attempt(attempt)) => Some(attempt_marker(*attempt)),
+        _ => None,
+    }
+}
+
+/// The line written to a job's output where its next attempt starts.
+fn attempt_marker(attempt: u32) -> OutputBlob {
+    OutputBlob::from(format!("--- attempt {} ---\n", attempt))
+}
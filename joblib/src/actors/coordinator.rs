@@ -4,13 +4,24 @@ mod messages;
 use self::{
     actor::JobCoordinator,
     messages::CoordinatorMessage::{
-        self, GetStatus, StartJob, StopJob, StreamAll, StreamStderr, StreamStdout,
+        self, DeleteJob, FindByIdPrefix, FindByName, GetAttempts, GetDetails, GetEnvironment,
+        GetInfo, GetIsolation, GetMetadata, GetStages, GetStatus, GetStdin, GetTerminal,
+        GetWorkflowStatus, ListJobs, PauseJob, ResumeJob, SignalJob, StartJob, StopJob, StreamAll,
+        StreamStderr, StreamStdout, SubmitWorkflow, SubscribeEvents, WaitJob,
     },
 };
 use crate::error;
-use crate::events::{JobStatus, OutputBlob};
-use crate::types::{Args, Dir, Envs, JobId, Program};
-use std::io;
+use crate::events::{
+    Attempts, JobDetails, JobInfo, JobListing, JobMetadata, JobStatus, LifecycleEvent, OutputEvent,
+    WorkflowStatus,
+};
+use crate::pty::Terminal;
+use crate::stdin::StdinWriter;
+use crate::types::{
+    CoordinatorConfig, Envs, EventFilter, Isolation, JobFilter, JobId, JobPage, JobRegistry,
+    JobSpec, QueueLimits, WorkflowId, WorkflowSpec,
+};
+use std::{io, sync::Arc, time::Duration};
 use tokio::sync::{mpsc, oneshot};
 
 /// A `JobCoordinator` which provides functionality for managing jobs and querying job state.
@@ -35,38 +46,115 @@ impl JobCoordinatorHandle {
     ///
     /// Specify the capacity for the coordinator's message queue. This limits the build-up of inbound messages.
     pub fn spawn(message_capacity: usize) -> Self {
+        Self::spawn_with_limits(message_capacity, QueueLimits::default())
+    }
+
+    /// Spawn a new coordinator that runs at most as many jobs at once as the limits allow.
+    ///
+    /// Jobs started past a limit are queued. Queued jobs are started by priority, then owners take turns, so one
+    /// owner can't crowd out the others, then jobs that were queued first. An owner at their own limit waits for
+    /// one of their jobs to finish, and the other owners' jobs go ahead of theirs.
+    pub fn spawn_with_limits(message_capacity: usize, limits: QueueLimits) -> Self {
+        let config = CoordinatorConfig {
+            queue_limits: limits,
+            ..Default::default()
+        };
+        Self::spawn_with_config(message_capacity, config, Arc::new(()))
+    }
+
+    /// Spawn a new coordinator configured by `config`: its queue limits, as in `spawn_with_limits`, how long finished
+    /// jobs are kept, where job output is spooled to disk, how much of each job's output is kept in memory without a
+    /// spool, and how much output is queued for each subscriber and what happens to one that falls behind.
+    ///
+    /// `registry` is told about every job and workflow the coordinator takes on, and every one it forgets, whether it
+    /// was deleted or its retention ran out, so the caller can keep track of them in step with the coordinator.
+    pub fn spawn_with_config(
+        message_capacity: usize,
+        config: CoordinatorConfig,
+        registry: Arc<dyn JobRegistry>,
+    ) -> Self {
         let (sender, receiver) = mpsc::channel(message_capacity);
-        JobCoordinator::spawn(receiver);
+        JobCoordinator::spawn(receiver, config, registry);
         Self { sender }
     }
 
     /// start a new job.
-    // TODO: make these args more generic
-    pub async fn start_job(
-        &self,
-        cmd: Program,
-        args: Args,
-        dir: Dir,
-        envs: Envs,
-    ) -> io::Result<JobId> {
+    ///
+    /// If the spec has resource limits, the job runs in its own cgroup v2 leaf which is removed when the job is reaped.
+    /// If the spec requests isolation, the job is cloned into new linux namespaces.
+    /// If the spec has a tty, the job's stdin/stdout/stderr are a new pseudo-terminal.
+    /// Otherwise the job's stdin is set up by the spec's `StdinSource`.
+    /// The job's environment is built from the spec's env policy, and a job that asks for a denied variable fails
+    /// to start with `PermissionDenied`.
+    /// If the spec has a pipeline, its stages run in the job's process group, each reading the stdout of the last.
+    /// If the spec has a restart policy, the job is started again under the same id when it exits, and its
+    /// output carries on across attempts.
+    ///
+    /// If the spec has a name, it has to be valid and not taken by another job of the same owner, or the job fails to
+    /// start with `InvalidInput` or `AlreadyExists`.
+    /// If the coordinator is already running as many jobs as its limits allow, the job is queued instead, and its
+    /// output can be streamed while it waits. Only a job that is spawned right away fails here if it can't be
+    /// spawned, a queued job that can't be spawned ends with `JobStatus::FailedToStart`.
+    pub async fn start_job(&self, spec: JobSpec) -> io::Result<JobId> {
         let (tx, rx) = oneshot::channel();
         let msg = StartJob {
-            cmd,
-            args,
-            dir,
-            envs,
+            spec: Box::new(spec),
             response: tx,
         };
         self.sender.send(msg).await.expect("JobCoordinator exited");
         rx.await.expect("JobCoordinator exited")
     }
 
+    /// Submit a workflow. Each step is started as a job once the steps it depends on have finished, and skipped
+    /// if a step it depends on with `after_success` didn't succeed.
+    ///
+    /// Fails if step names aren't unique, a step depends on an unknown step, or the dependencies have a cycle.
+    /// Steps that can't be spawned count as failed.
+    pub async fn submit_workflow(&self, spec: WorkflowSpec) -> error::Result<WorkflowId> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(SubmitWorkflow { spec, response: tx })
+            .await
+            .expect("JobCoordinator exited");
+        rx.await.expect("JobCoordinator exited")
+    }
+
+    /// Get the state of a workflow and each of its steps, including the job id each step runs as.
+    pub async fn get_workflow_status(
+        &self,
+        workflow_id: WorkflowId,
+    ) -> error::Result<WorkflowStatus> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(GetWorkflowStatus {
+                workflow_id,
+                response: tx,
+            })
+            .await
+            .expect("JobCoordinator exited");
+        rx.await.expect("JobCoordinator exited")
+    }
+
     /// Stop a job. Returns a joblib::error::Result which will be Error
+    ///
+    /// The job is sent the signal from its stop policy, and SIGKILLed if it is still running after the grace period.
+    /// A stopped job is never restarted. Stopping a job that is waiting to be restarted just calls off the restart.
+    /// Stopping a queued job takes it out of the queue, and it ends with `JobStatus::Cancelled`.
     pub async fn stop_job(&self, job_id: JobId) -> error::Result<()> {
+        self.stop_job_with_grace(job_id, None).await
+    }
+
+    /// Stop a job like `stop_job`, overriding the grace period of its stop policy if `grace` is set.
+    pub async fn stop_job_with_grace(
+        &self,
+        job_id: JobId,
+        grace: Option<Duration>,
+    ) -> error::Result<()> {
         let (tx, rx) = oneshot::channel();
         self.sender
             .send(StopJob {
                 job_id,
+                grace,
                 response: tx,
             })
             .await
@@ -74,6 +162,64 @@ impl JobCoordinatorHandle {
         rx.await.expect("JobCoordinator exited")
     }
 
+    /// Pause a job, suspending all of its processes until it is resumed.
+    ///
+    /// Stopping a paused job resumes it, so it can act on its stop signal.
+    pub async fn pause_job(&self, job_id: JobId) -> error::Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(PauseJob {
+                job_id,
+                response: tx,
+            })
+            .await
+            .expect("JobCoordinator exited");
+        rx.await.expect("JobCoordinator exited")
+    }
+
+    /// Resume a paused job.
+    pub async fn resume_job(&self, job_id: JobId) -> error::Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(ResumeJob {
+                job_id,
+                response: tx,
+            })
+            .await
+            .expect("JobCoordinator exited");
+        rx.await.expect("JobCoordinator exited")
+    }
+
+    /// Send a signal to every process of a job.
+    ///
+    /// SIGSTOP and SIGCONT aren't allowed, use `pause_job` and `resume_job` instead.
+    pub async fn signal_job(&self, job_id: JobId, signal: i32) -> error::Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(SignalJob {
+                job_id,
+                signal,
+                response: tx,
+            })
+            .await
+            .expect("JobCoordinator exited");
+        rx.await.expect("JobCoordinator exited")
+    }
+
+    /// Get the job's current attempt number, and how its previous attempts ended.
+    pub async fn get_job_attempts(&self, job_id: JobId) -> error::Result<Attempts> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(GetAttempts {
+                job_id,
+                response: tx,
+            })
+            .await
+            .expect("JobCoordinator exited");
+        rx.await.expect("JobCoordinator exited")
+    }
+
+    /// Get the job's status. A queued job reports its position in the queue.
     pub async fn get_job_status(&self, job_id: JobId) -> error::Result<JobStatus> {
         let (tx, rx) = oneshot::channel();
         self.sender
@@ -86,14 +232,215 @@ impl JobCoordinatorHandle {
         rx.await.expect("JobCoordinator exited")
     }
 
+    /// Wait for a job to finish for good, after its last attempt, and get its final status.
+    pub async fn wait_for_job(&self, job_id: JobId) -> error::Result<JobStatus> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(WaitJob {
+                job_id,
+                response: tx,
+            })
+            .await
+            .expect("JobCoordinator exited");
+        rx.await.expect("JobCoordinator exited")
+    }
+
+    /// Get the status of each stage of the job's pipeline, starting with the job's own command. Stages that
+    /// haven't exited have the job's status. A restarted job reports the stages of its current attempt.
+    pub async fn get_job_stages(&self, job_id: JobId) -> error::Result<Vec<JobStatus>> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(GetStages {
+                job_id,
+                response: tx,
+            })
+            .await
+            .expect("JobCoordinator exited");
+        rx.await.expect("JobCoordinator exited")
+    }
+
+    /// Get the namespaces a job was isolated in.
+    pub async fn get_job_isolation(&self, job_id: JobId) -> error::Result<Isolation> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(GetIsolation {
+                job_id,
+                response: tx,
+            })
+            .await
+            .expect("JobCoordinator exited");
+        rx.await.expect("JobCoordinator exited")
+    }
+
+    /// Get everything there is to know about a job: who started it, with what, when it ran, and what it used.
+    pub async fn get_job_info(&self, job_id: JobId) -> error::Result<JobInfo> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(GetInfo {
+                job_id,
+                response: tx,
+            })
+            .await
+            .expect("JobCoordinator exited");
+        rx.await.expect("JobCoordinator exited")
+    }
+
+    /// Get the name and labels a job was started with.
+    pub async fn get_job_metadata(&self, job_id: JobId) -> error::Result<JobMetadata> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(GetMetadata {
+                job_id,
+                response: tx,
+            })
+            .await
+            .expect("JobCoordinator exited");
+        rx.await.expect("JobCoordinator exited")
+    }
+
+    /// Get a job's status along with its attempts, stages, isolation, environment, name, and labels, all as they
+    /// were at the same moment.
+    pub async fn get_job_details(&self, job_id: JobId) -> error::Result<JobDetails> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(GetDetails {
+                job_id,
+                response: tx,
+            })
+            .await
+            .expect("JobCoordinator exited");
+        rx.await.expect("JobCoordinator exited")
+    }
+
+    /// Find the job an owner gave this name.
+    pub async fn find_job_by_name(
+        &self,
+        owner: Option<String>,
+        name: String,
+    ) -> error::Result<JobId> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(FindByName {
+                owner,
+                name,
+                response: tx,
+            })
+            .await
+            .expect("JobCoordinator exited");
+        rx.await.expect("JobCoordinator exited")
+    }
+
+    /// Find every job whose id starts with `prefix`, written as a lowercase hyphenated uuid.
+    pub async fn find_jobs_by_id_prefix(&self, prefix: String) -> Vec<JobId> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(FindByIdPrefix {
+                prefix,
+                response: tx,
+            })
+            .await
+            .expect("JobCoordinator exited");
+        rx.await.expect("JobCoordinator exited")
+    }
+
+    /// Forget a job that has finished for good, along with its output.
+    ///
+    /// Fails with `NotFinished` if the job is still queued, running, or going to be restarted.
+    pub async fn delete_job(&self, job_id: JobId) -> error::Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(DeleteJob {
+                job_id,
+                response: tx,
+            })
+            .await
+            .expect("JobCoordinator exited");
+        rx.await.expect("JobCoordinator exited")
+    }
+
+    /// List a page of the jobs that match the filter, oldest first.
+    pub async fn list_jobs(&self, filter: JobFilter, page: JobPage) -> JobListing {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(ListJobs {
+                filter,
+                page,
+                response: tx,
+            })
+            .await
+            .expect("JobCoordinator exited");
+        rx.await.expect("JobCoordinator exited")
+    }
+
+    /// Subscribe to the lifecycle events of every job that matches the filter, from now on, in the order they happen.
+    pub async fn subscribe_events(
+        &self,
+        filter: EventFilter,
+    ) -> mpsc::UnboundedReceiver<LifecycleEvent> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(SubscribeEvents {
+                filter,
+                response: tx,
+            })
+            .await
+            .expect("JobCoordinator exited");
+        rx.await.expect("JobCoordinator exited")
+    }
+
+    /// Get the environment variables a job runs with, after its env policy, sorted by name.
+    pub async fn get_job_environment(&self, job_id: JobId) -> error::Result<Envs> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(GetEnvironment {
+                job_id,
+                response: tx,
+            })
+            .await
+            .expect("JobCoordinator exited");
+        rx.await.expect("JobCoordinator exited")
+    }
+
+    /// Get a handle to a job's terminal, for jobs started with a tty.
+    ///
+    /// Terminal output is streamed with `stream_stdout`.
+    pub async fn get_job_terminal(&self, job_id: JobId) -> error::Result<Terminal> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(GetTerminal {
+                job_id,
+                response: tx,
+            })
+            .await
+            .expect("JobCoordinator exited");
+        rx.await.expect("JobCoordinator exited")
+    }
+
+    /// Get a handle to a job's stdin, for jobs started with `StdinSource::Stream`.
+    pub async fn get_job_stdin(&self, job_id: JobId) -> error::Result<StdinWriter> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(GetStdin {
+                job_id,
+                response: tx,
+            })
+            .await
+            .expect("JobCoordinator exited");
+        rx.await.expect("JobCoordinator exited")
+    }
+
+    /// Stream a job's stdout from byte `offset` of it. Each chunk says where in the stream it starts, so a subscriber
+    /// that was cut off can pick up where it stopped.
     pub async fn stream_stdout(
         &self,
         job_id: JobId,
-    ) -> error::Result<mpsc::UnboundedReceiver<OutputBlob>> {
+        offset: u64,
+    ) -> error::Result<mpsc::Receiver<OutputEvent>> {
         let (tx, rx) = oneshot::channel();
         self.sender
             .send(StreamStdout {
                 job_id,
+                offset,
                 response: tx,
             })
             .await
@@ -101,14 +448,17 @@ impl JobCoordinatorHandle {
         rx.await.expect("JobCoordinator exited")
     }
 
+    /// Stream a job's stderr from byte `offset` of it.
     pub async fn stream_stderr(
         &self,
         job_id: JobId,
-    ) -> error::Result<mpsc::UnboundedReceiver<OutputBlob>> {
+        offset: u64,
+    ) -> error::Result<mpsc::Receiver<OutputEvent>> {
         let (tx, rx) = oneshot::channel();
         self.sender
             .send(StreamStderr {
                 job_id,
+                offset,
                 response: tx,
             })
             .await
@@ -116,14 +466,17 @@ impl JobCoordinatorHandle {
         rx.await.expect("JobCoordinator exited")
     }
 
+    /// Stream a job's stdout and stderr, merged in the order they were written, from byte `offset` of the two together.
     pub async fn stream_all(
         &self,
         job_id: JobId,
-    ) -> error::Result<mpsc::UnboundedReceiver<OutputBlob>> {
+        offset: u64,
+    ) -> error::Result<mpsc::Receiver<OutputEvent>> {
         let (tx, rx) = oneshot::channel();
         self.sender
             .send(StreamAll {
                 job_id,
+                offset,
                 response: tx,
             })
             .await
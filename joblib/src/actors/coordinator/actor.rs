@@ -1,117 +1,1193 @@
 use super::messages::CoordinatorMessage;
-use crate::actors::{broadcaster::BroadcasterHandle, worker::WorkerHandle};
+use crate::actors::{
+    broadcaster::BroadcasterHandle,
+    worker::{WorkerEvent, WorkerHandle},
+};
+use crate::cgroup;
 use crate::error::{self, Error as JobError};
-use crate::events::{JobStatus, OutputBlob};
-use crate::types::{Args, Dir, Envs, JobId, Program};
-use std::{collections::HashMap, io};
-use tokio::sync::{mpsc, oneshot};
+use crate::events::{
+    Attempts, JobDetails, JobInfo, JobListing, JobMetadata, JobStatus, JobSummary, LifecycleEvent,
+    LifecycleEventKind, OutputEvent, WorkflowState, WorkflowStatus,
+};
+use crate::pty::Terminal;
+use crate::stdin::StdinWriter;
+use crate::types::{
+    Args, CoordinatorConfig, Dir, Envs, EventFilter, Isolation, JobFilter, JobId, JobPage,
+    JobRegistry, JobSpec, Output, Program, QueueLimits, RestartPolicy, RetentionPolicy,
+    SpoolConfig, SubscriberQueue, WorkflowId, WorkflowSpec,
+};
+use crate::workflow::Workflow;
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    io,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::{
+    select,
+    sync::{mpsc, oneshot},
+    time,
+};
 
 pub struct JobCoordinator {
     inbox: mpsc::Receiver<CoordinatorMessage>,
     workers: HashMap<JobId, WorkerHandle>,
     broadcasters: HashMap<JobId, BroadcasterHandle>,
+    restarts: HashMap<JobId, Restarts>,
+    limits: QueueLimits,
+    retention: RetentionPolicy,
+    /// where job output is kept on disk, if anywhere
+    spool: Option<SpoolConfig>,
+    /// bytes of each job's output kept in memory when there's no spool
+    output_budget: Option<usize>,
+    subscriber_queue: SubscriberQueue,
+    /// told about every job and workflow as it is taken on and forgotten
+    registry: Arc<dyn JobRegistry>,
+    /// jobs waiting for a free slot, in the order they were queued
+    queue: Vec<QueuedJob>,
+    /// the owner of every spawned job that hasn't finished for good
+    running: HashMap<JobId, Option<String>>,
+    /// counts every job spawned, to order the owners by when they last had a job spawned
+    spawned: u64,
+    last_spawned: HashMap<Option<String>, u64>,
+    /// the specs of jobs that left the queue without ever running
+    unstarted: HashMap<JobId, JobSpec>,
+    /// every job, spawned or not
+    jobs: HashMap<JobId, JobRecord>,
+    /// every named job, by its owner and name
+    names: HashMap<(Option<String>, String), JobId>,
+    workflows: HashMap<WorkflowId, Workflow>,
+    /// the workflow each step's job belongs to
+    workflow_steps: HashMap<JobId, WorkflowId>,
+    /// the final status of every job that has finished for good
+    finished: HashMap<JobId, JobStatus>,
+    /// the jobs whose workers last reported them paused
+    paused: HashSet<JobId>,
+    /// get the lifecycle events of the jobs they're interested in
+    subscribers: Vec<Subscriber>,
+    /// waiting for jobs to finish for good
+    job_waiters: HashMap<JobId, Vec<oneshot::Sender<error::Result<JobStatus>>>>,
+    // the coordinator sends these to itself, from the tasks that wait on jobs
+    job_events_tx: mpsc::UnboundedSender<JobEvent>,
+    job_events_rx: mpsc::UnboundedReceiver<JobEvent>,
+}
+
+/// Bookkeeping for a job with a restart policy.
+struct Restarts {
+    spec: JobSpec,
+    /// the current attempt, starting from 1
+    attempt: u32,
+    previous: Vec<JobStatus>,
+    /// how the current attempt ended, if it has
+    last_status: Option<JobStatus>,
+    /// held between attempts so the job's output carries on across them. `None` once there are no more attempts.
+    output_tx: Option<mpsc::UnboundedSender<Output>>,
+    /// waiting out the backoff before the next attempt
+    pending: bool,
+    /// the job was told to stop, so it is never restarted again
+    stopped: bool,
+}
+
+/// What the coordinator remembers about a job besides its status.
+#[derive(Clone)]
+struct JobRecord {
+    owner: Option<String>,
+    metadata: JobMetadata,
+    cmd: Program,
+    args: Args,
+    dir: Dir,
+    /// when `start_job` was called for it
+    submitted: SystemTime,
+    /// when its first attempt was spawned
+    started: Option<SystemTime>,
+    /// when it finished for good
+    finished: Option<SystemTime>,
+}
+
+struct Subscriber {
+    filter: EventFilter,
+    events_tx: mpsc::UnboundedSender<LifecycleEvent>,
+}
+
+struct QueuedJob {
+    job_id: JobId,
+    spec: JobSpec,
+    /// the job's broadcaster is already listening on the other end
+    output_tx: mpsc::UnboundedSender<Output>,
+}
+
+enum JobEvent {
+    /// an attempt finished
+    Exited {
+        job_id: JobId,
+        attempt: u32,
+        status: JobStatus,
+    },
+    /// the backoff after an attempt is over
+    BackoffElapsed { job_id: JobId, attempt: u32 },
+    /// the job finished long enough ago that it is forgotten
+    Expired { job_id: JobId },
+    /// the workflow, none of whose steps ran, finished long enough ago that it is forgotten
+    WorkflowExpired { workflow_id: WorkflowId },
+    /// a worker did something to the job that subscribers should hear about, like pausing it
+    Lifecycle {
+        job_id: JobId,
+        kind: LifecycleEventKind,
+    },
 }
 
 impl JobCoordinator {
-    pub fn spawn(inbox: mpsc::Receiver<CoordinatorMessage>) {
+    pub fn spawn(
+        inbox: mpsc::Receiver<CoordinatorMessage>,
+        config: CoordinatorConfig,
+        registry: Arc<dyn JobRegistry>,
+    ) {
+        let (job_events_tx, job_events_rx) = mpsc::unbounded_channel();
         let actor = Self {
             inbox,
             workers: HashMap::new(),
             broadcasters: HashMap::new(),
+            restarts: HashMap::new(),
+            limits: config.queue_limits,
+            retention: config.retention,
+            spool: config.spool,
+            output_budget: config.output_budget,
+            subscriber_queue: config.subscriber_queue,
+            registry,
+            queue: Vec::new(),
+            running: HashMap::new(),
+            spawned: 0,
+            last_spawned: HashMap::new(),
+            unstarted: HashMap::new(),
+            jobs: HashMap::new(),
+            names: HashMap::new(),
+            workflows: HashMap::new(),
+            workflow_steps: HashMap::new(),
+            finished: HashMap::new(),
+            paused: HashSet::new(),
+            subscribers: Vec::new(),
+            job_waiters: HashMap::new(),
+            job_events_tx,
+            job_events_rx,
         };
         tokio::spawn(async move { actor.run().await });
     }
 
     async fn run(mut self) {
         use self::CoordinatorMessage::*;
-        while let Some(msg) = self.inbox.recv().await {
+        loop {
+            let msg = select! {
+                // events come first, so a request made after a worker answered one sees what the worker reported
+                biased;
+                Some(event) = self.job_events_rx.recv() => {
+                    self.handle_job_event(event);
+                    continue;
+                }
+                maybe_msg = self.inbox.recv() => match maybe_msg {
+                    Some(msg) => msg,
+                    None => return, // every handle was dropped
+                },
+            };
             match msg {
-                StartJob {
-                    cmd,
-                    args,
-                    dir,
-                    envs,
+                StartJob { spec, response } => {
+                    self.start_job(*spec, response);
+                }
+                StopJob {
+                    job_id,
+                    grace,
                     response,
                 } => {
-                    self.start_job(cmd, args, dir, envs, response);
+                    self.stop_job(job_id, grace, response);
+                }
+                PauseJob { job_id, response } => {
+                    self.pause_job(job_id, response);
+                }
+                ResumeJob { job_id, response } => {
+                    self.resume_job(job_id, response);
                 }
-                StopJob { job_id, response } => {
-                    self.stop_job(job_id, response);
+                SignalJob {
+                    job_id,
+                    signal,
+                    response,
+                } => {
+                    self.signal_job(job_id, signal, response);
                 }
                 GetStatus { job_id, response } => {
                     self.get_job_status(job_id, response);
                 }
-                StreamStdout { job_id, response } => {
-                    self.stream_stdout(job_id, response);
+                WaitJob { job_id, response } => {
+                    self.wait_job(job_id, response);
+                }
+                GetInfo { job_id, response } => {
+                    self.get_job_info(job_id, response);
+                }
+                GetMetadata { job_id, response } => {
+                    let metadata = self.jobs.get(&job_id).map(|job| job.metadata.clone());
+                    let _ = response.send(metadata.ok_or(JobError::DoesNotExist));
+                }
+                GetDetails { job_id, response } => {
+                    self.get_job_details(job_id, response);
+                }
+                FindByName {
+                    owner,
+                    name,
+                    response,
+                } => {
+                    let job_id = self.names.get(&(owner, name)).copied();
+                    let _ = response.send(job_id.ok_or(JobError::DoesNotExist));
+                }
+                FindByIdPrefix { prefix, response } => {
+                    self.find_by_id_prefix(&prefix, response);
+                }
+                DeleteJob { job_id, response } => {
+                    let _ = response.send(self.delete_job(job_id));
+                }
+                ListJobs {
+                    filter,
+                    page,
+                    response,
+                } => {
+                    self.list_jobs(filter, page, response);
+                }
+                SubscribeEvents { filter, response } => {
+                    let (events_tx, events_rx) = mpsc::unbounded_channel();
+                    self.subscribers.push(Subscriber { filter, events_tx });
+                    let _ = response.send(events_rx);
+                }
+                GetStages { job_id, response } => {
+                    self.get_job_stages(job_id, response);
+                }
+                GetIsolation { job_id, response } => {
+                    self.get_job_isolation(job_id, response);
+                }
+                GetEnvironment { job_id, response } => {
+                    self.get_job_environment(job_id, response);
                 }
-                StreamStderr { job_id, response } => {
-                    self.stream_stderr(job_id, response);
+                GetTerminal { job_id, response } => {
+                    self.get_job_terminal(job_id, response);
                 }
-                StreamAll { job_id, response } => {
-                    self.stream_all(job_id, response);
+                GetStdin { job_id, response } => {
+                    self.get_job_stdin(job_id, response);
+                }
+                StreamStdout {
+                    job_id,
+                    offset,
+                    response,
+                } => {
+                    self.stream_stdout(job_id, offset, response);
+                }
+                StreamStderr {
+                    job_id,
+                    offset,
+                    response,
+                } => {
+                    self.stream_stderr(job_id, offset, response);
+                }
+                StreamAll {
+                    job_id,
+                    offset,
+                    response,
+                } => {
+                    self.stream_all(job_id, offset, response);
+                }
+                GetAttempts { job_id, response } => {
+                    self.get_job_attempts(job_id, response);
+                }
+                SubmitWorkflow { spec, response } => {
+                    self.submit_workflow(spec, response);
+                }
+                GetWorkflowStatus {
+                    workflow_id,
+                    response,
+                } => {
+                    self.get_workflow_status(workflow_id, response);
                 }
             }
         }
     }
 
-    fn start_job(
+    fn start_job(&mut self, spec: JobSpec, response: oneshot::Sender<io::Result<JobId>>) {
+        // the job id is needed up front to name the job's cgroup
+        let job_id = uuid::Uuid::new_v4();
+        let _ = response.send(self.start_or_queue(job_id, spec).map(|_| job_id));
+    }
+
+    /// Spawn a job if there is a free slot for it, or queue it. Only a job spawned right away can fail here.
+    fn start_or_queue(&mut self, job_id: JobId, spec: JobSpec) -> io::Result<()> {
+        let name = match &spec.name {
+            Some(name) => Some(self.check_name(&spec.owner, name)?),
+            None => None,
+        };
+        let submitted = SystemTime::now();
+        let mut job = JobRecord {
+            owner: spec.owner.clone(),
+            metadata: JobMetadata {
+                name: spec.name.clone(),
+                labels: spec.labels.clone(),
+            },
+            cmd: spec.cmd.clone(),
+            args: spec.args.clone(),
+            dir: spec.dir.clone(),
+            submitted,
+            started: None,
+            finished: None,
+        };
+
+        let (output_tx, output_rx) = mpsc::unbounded_channel(); // channel for piping child process output
+                                                                // broadcaster will receive events from the child process via this receiver channel
+        let broadcaster = BroadcasterHandle::spawn(
+            job_id,
+            output_rx,
+            self.spool.as_ref(),
+            self.output_budget,
+            self.subscriber_queue,
+        )?;
+
+        // no queued job is waiting for a free slot that this job could take, they'd have been started already
+        if self.has_free_slot(&spec.owner) {
+            self.spawn_job(job_id, spec, output_tx)?;
+            job.started = Some(submitted);
+        } else {
+            // refuse a denied variable or limits the kernel wouldn't take now, rather than once the job leaves the queue
+            spec.env_policy.environment(&spec.envs)?;
+            cgroup::validate(&spec.limits)?;
+            self.queue.push(QueuedJob {
+                job_id,
+                spec,
+                output_tx,
+            });
+        }
+
+        // a workflow's steps were added along with the workflow
+        if !self.workflow_steps.contains_key(&job_id) {
+            self.registry.job_added(job_id, job.owner.as_deref());
+        }
+        self.broadcasters.insert(job_id, broadcaster);
+        self.jobs.insert(job_id, job);
+        if let Some(name) = name {
+            self.names.insert(name, job_id);
+        }
+        Ok(())
+    }
+
+    /// Check that a name is valid and the owner hasn't given it to another job, and get its key in `names`.
+    fn check_name(
+        &self,
+        owner: &Option<String>,
+        name: &str,
+    ) -> io::Result<(Option<String>, String)> {
+        let mut chars = name.chars();
+        let valid = chars.next().is_some_and(|c| c.is_ascii_alphanumeric())
+            && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+        if !valid {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid job name {:?}", name),
+            ));
+        }
+        let key = (owner.clone(), name.to_string());
+        if self.names.contains_key(&key) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("job name {} is taken", name),
+            ));
+        }
+        Ok(key)
+    }
+
+    fn find_by_id_prefix(&self, prefix: &str, response: oneshot::Sender<Vec<JobId>>) {
+        let mut job_ids: Vec<_> = self
+            .jobs
+            .keys()
+            .filter(|job_id| job_id.to_hyphenated().to_string().starts_with(prefix))
+            .copied()
+            .collect();
+        job_ids.sort();
+        let _ = response.send(job_ids);
+    }
+
+    fn delete_job(&mut self, job_id: JobId) -> error::Result<()> {
+        if !self.jobs.contains_key(&job_id) {
+            return Err(JobError::DoesNotExist);
+        }
+        if !self.finished.contains_key(&job_id) {
+            return Err(JobError::NotFinished);
+        }
+        self.forget(job_id);
+        Ok(())
+    }
+
+    /// Drop everything the coordinator knows about a finished job. Dropping its worker and broadcaster handles lets
+    /// their actors exit, which ends the streams of anyone still reading its output.
+    fn forget(&mut self, job_id: JobId) {
+        let job = match self.jobs.remove(&job_id) {
+            Some(job) => job,
+            None => return, // already forgotten
+        };
+        self.publish(job_id, job.owner.clone(), LifecycleEventKind::Deleted);
+        if let Some(name) = job.metadata.name {
+            self.names.remove(&(job.owner, name));
+        }
+        self.workers.remove(&job_id);
+        self.broadcasters.remove(&job_id);
+        self.restarts.remove(&job_id);
+        self.unstarted.remove(&job_id);
+        self.finished.remove(&job_id);
+        self.paused.remove(&job_id);
+        self.registry.job_forgotten(job_id);
+        if let Some(workflow_id) = self.workflow_steps.remove(&job_id) {
+            self.forget_workflow_if_done(workflow_id);
+        }
+    }
+
+    /// Forget a finished workflow once none of its steps' jobs are remembered anymore. Steps that never ran were
+    /// never remembered as jobs, but they were registered along with the workflow.
+    fn forget_workflow_if_done(&mut self, workflow_id: WorkflowId) {
+        let done = self.workflows.get(&workflow_id).is_some_and(|workflow| {
+            workflow.status().state != WorkflowState::Running
+                && workflow
+                    .job_ids()
+                    .all(|job_id| !self.jobs.contains_key(&job_id))
+        });
+        if !done {
+            return;
+        }
+        if let Some(workflow) = self.workflows.remove(&workflow_id) {
+            // the steps that ran were forgotten along with their jobs
+            for job_id in workflow.job_ids() {
+                if self.workflow_steps.remove(&job_id).is_some() {
+                    self.registry.job_forgotten(job_id);
+                }
+            }
+        }
+        self.registry.workflow_forgotten(workflow_id);
+    }
+
+    /// Apply the retention policy to a workflow that may have finished without any of its steps running, so there are
+    /// no jobs for it to be forgotten along with. It is kept as long as a finished job would be, or not at all if only
+    /// the number of finished jobs is limited.
+    fn retain_workflow(&mut self, workflow_id: WorkflowId) {
+        let never_ran = self.workflows.get(&workflow_id).is_some_and(|workflow| {
+            workflow.status().state != WorkflowState::Running
+                && workflow
+                    .job_ids()
+                    .all(|job_id| !self.jobs.contains_key(&job_id))
+        });
+        if !never_ran {
+            return;
+        }
+        match self.retention {
+            RetentionPolicy {
+                max_age: Some(max_age),
+                ..
+            } => {
+                let events_tx = self.job_events_tx.clone();
+                tokio::spawn(async move {
+                    time::sleep(max_age).await;
+                    let _ = events_tx.send(JobEvent::WorkflowExpired { workflow_id });
+                });
+            }
+            RetentionPolicy {
+                max_finished_per_owner: Some(_),
+                ..
+            } => self.forget_workflow_if_done(workflow_id),
+            // nothing is forgotten
+            _ => {}
+        }
+    }
+
+    /// Apply the retention policy to a job that just finished.
+    fn retain(&mut self, job_id: JobId) {
+        if let Some(max_age) = self.retention.max_age {
+            let events_tx = self.job_events_tx.clone();
+            tokio::spawn(async move {
+                time::sleep(max_age).await;
+                let _ = events_tx.send(JobEvent::Expired { job_id });
+            });
+        }
+        if let Some(max_finished) = self.retention.max_finished_per_owner {
+            let owner = match self.jobs.get(&job_id) {
+                Some(job) => job.owner.clone(),
+                None => return,
+            };
+            let mut finished: Vec<_> = self
+                .jobs
+                .iter()
+                .filter(|(_, job)| job.owner == owner)
+                .filter_map(|(&job_id, job)| job.finished.map(|finished| (finished, job_id)))
+                .collect();
+            finished.sort();
+            let excess = finished.len().saturating_sub(max_finished);
+            for (_, job_id) in finished.into_iter().take(excess) {
+                self.forget(job_id);
+            }
+        }
+    }
+
+    /// A page of the jobs that match the filter, oldest first. Only the jobs that may end up on the page are
+    /// summarized, and the statuses of spawned jobs are gathered from their workers in a separate task, so the
+    /// coordinator doesn't wait on them.
+    fn list_jobs(
         &mut self,
-        cmd: Program,
-        args: Args,
-        dir: Dir,
-        envs: Envs,
-        response: oneshot::Sender<io::Result<JobId>>,
+        filter: JobFilter,
+        page: JobPage,
+        response: oneshot::Sender<JobListing>,
     ) {
-        let (output_tx, output_rx) = mpsc::unbounded_channel(); // channel for piping child process output
+        let mut matches: Vec<_> = self
+            .jobs
+            .iter()
+            .filter(|(_, job)| filter.owner.is_none() || job.owner == filter.owner)
+            .filter(|(_, job)| {
+                filter
+                    .labels
+                    .iter()
+                    .all(|(key, val)| job.metadata.labels.get(key) == Some(val))
+            })
+            .filter(|(_, job)| {
+                filter
+                    .started_after
+                    .is_none_or(|after| job.started.is_some_and(|started| started > after))
+            })
+            .filter(|(_, job)| {
+                filter
+                    .started_before
+                    .is_none_or(|before| job.started.is_some_and(|started| started < before))
+            })
+            .map(|(&job_id, job)| (job.submitted, job_id))
+            .filter(|&key| page.after.is_none_or(|after| key > after))
+            .collect();
+        matches.sort_unstable();
+
+        // the coordinator keeps track of every job's state, so the page is filled without asking any workers
+        let limit = page.limit.unwrap_or(usize::MAX);
+        let positions = self.queue_positions();
+        let mut listing = JobListing::default();
+        for (_, job_id) in matches {
+            let status = match self.tracked_status(job_id, &positions) {
+                Some(status) => status,
+                None => continue,
+            };
+            if !filter.states.is_empty() && !filter.states.contains(&status.state()) {
+                continue;
+            }
+            if listing.jobs.len() == limit {
+                listing.more = true;
+                break;
+            }
+            let job = &self.jobs[&job_id];
+            listing.jobs.push(JobSummary {
+                job_id,
+                owner: job.owner.clone(),
+                name: job.metadata.name.clone(),
+                labels: job.metadata.labels.clone(),
+                status,
+                submitted: job.submitted,
+                started: job.started,
+            });
+        }
+        let _ = response.send(listing);
+    }
+
+    /// Everything known about a job. Its status and resource usage are gathered from its worker in a separate task.
+    fn get_job_info(&mut self, job_id: JobId, response: oneshot::Sender<error::Result<JobInfo>>) {
+        let job = match self.jobs.get(&job_id) {
+            Some(job) => job.clone(),
+            None => {
+                let _ = response.send(Err(JobError::DoesNotExist));
+                return;
+            }
+        };
+        let (environment_tx, environment_rx) = oneshot::channel();
+        self.get_job_environment(job_id, environment_tx);
+        let (status_tx, status_rx) = oneshot::channel();
+        self.get_job_status(job_id, status_tx);
+        let worker = self.workers.get(&job_id);
+        let pid = worker.map(WorkerHandle::pid);
+        let usage_rx = worker.map(|worker| {
+            let (usage_tx, usage_rx) = oneshot::channel();
+            worker.get_resource_usage(usage_tx);
+            usage_rx
+        });
+        tokio::spawn(async move {
+            let status = match status_rx.await {
+                Ok(Ok(status)) => status,
+                _ => {
+                    let _ = response.send(Err(JobError::DoesNotExist));
+                    return;
+                }
+            };
+            let environment = match environment_rx.await {
+                Ok(Ok(environment)) => environment,
+                _ => Envs::new(),
+            };
+            let resource_usage = match usage_rx {
+                Some(usage_rx) => usage_rx.await.ok().flatten(),
+                None => None,
+            };
+            let duration = job.started.map(|started| {
+                job.finished
+                    .unwrap_or_else(SystemTime::now)
+                    .duration_since(started)
+                    .unwrap_or_default()
+            });
+            let _ = response.send(Ok(JobInfo {
+                job_id,
+                owner: job.owner,
+                name: job.metadata.name,
+                labels: job.metadata.labels,
+                status,
+                cmd: job.cmd,
+                args: job.args,
+                dir: job.dir,
+                environment,
+                submitted: job.submitted,
+                started: job.started,
+                finished: job.finished,
+                duration,
+                pid,
+                resource_usage,
+            }));
+        });
+    }
+
+    /// Everything a job's status is reported with, gathered while handling a single message so it all describes the
+    /// same moment. Only the stages of a spawned job come from its worker.
+    fn get_job_details(
+        &mut self,
+        job_id: JobId,
+        response: oneshot::Sender<error::Result<JobDetails>>,
+    ) {
+        let details = self
+            .tracked_status(job_id, &self.queue_positions())
+            .ok_or(JobError::DoesNotExist)
+            .and_then(|status| {
+                let metadata = self.jobs.get(&job_id).ok_or(JobError::DoesNotExist)?;
+                Ok(JobDetails {
+                    status,
+                    attempts: self.attempts(job_id)?,
+                    stages: Vec::new(),
+                    isolation: self.isolation(job_id)?,
+                    environment: self.environment(job_id)?,
+                    metadata: metadata.metadata.clone(),
+                })
+            });
+        let mut details = match details {
+            Ok(details) => details,
+            Err(err) => {
+                let _ = response.send(Err(err));
+                return;
+            }
+        };
+        match self.workers.get(&job_id) {
+            Some(worker) => {
+                let (stages_tx, stages_rx) = oneshot::channel();
+                worker.get_stages(stages_tx);
+                tokio::spawn(async move {
+                    let _ = response.send(match stages_rx.await {
+                        Ok(Ok(stages)) => Ok(JobDetails { stages, ..details }),
+                        _ => Err(JobError::DoesNotExist),
+                    });
+                });
+            }
+            None => {
+                // none of the stages have run
+                let stage_count = self
+                    .unstarted_spec(job_id)
+                    .map_or(1, |spec| 1 + spec.pipeline.len());
+                details.stages = vec![details.status; stage_count];
+                let _ = response.send(Ok(details));
+            }
+        }
+    }
+
+    fn spawn_job(
+        &mut self,
+        job_id: JobId,
+        spec: JobSpec,
+        output_tx: mpsc::UnboundedSender<Output>,
+    ) -> io::Result<()> {
+        // a job that may be restarted needs its spec and output channel for the next attempt
+        let restart = (spec.restart_policy != RestartPolicy::Never)
+            .then(|| (spec.clone(), output_tx.clone()));
+        let owner = spec.owner.clone();
 
         // spawn the worker with the sending end of the output channel.
-        match WorkerHandle::spawn(output_tx, cmd, args, dir, envs) {
-            Ok(worker) => {
-                // broadcaster will receive events from the child process via this receiver channel
-                let broadcaster = BroadcasterHandle::spawn(output_rx);
+        // if spawning child process fails, we don't insert the worker handle in our map. no actors spawn in this case.
+        let worker = WorkerHandle::spawn(job_id, output_tx, spec)?;
+        self.wait_for_attempt(job_id, 1, &worker);
+        self.publish(
+            job_id,
+            owner.clone(),
+            LifecycleEventKind::Started { attempt: 1 },
+        );
+        if let Some((spec, output_tx)) = restart {
+            self.restarts.insert(
+                job_id,
+                Restarts {
+                    spec,
+                    attempt: 1,
+                    previous: Vec::new(),
+                    last_status: None,
+                    output_tx: Some(output_tx),
+                    pending: false,
+                    stopped: false,
+                },
+            );
+        }
+
+        self.workers.insert(job_id, worker);
+        if let Some(job) = self.jobs.get_mut(&job_id) {
+            job.started.get_or_insert_with(SystemTime::now);
+        }
+        self.spawned += 1;
+        self.last_spawned.insert(owner.clone(), self.spawned);
+        self.running.insert(job_id, owner);
+        Ok(())
+    }
+
+    /// Whether a job of this owner can be spawned without going over the limits.
+    fn has_free_slot(&self, owner: &Option<String>) -> bool {
+        let running = self
+            .limits
+            .max_running
+            .is_none_or(|max| self.running.len() < max);
+        let running_for_owner = self
+            .limits
+            .max_running_per_owner
+            .is_none_or(|max| self.running_for(owner) < max);
+        running && running_for_owner
+    }
+
+    fn running_for(&self, owner: &Option<String>) -> usize {
+        self.running
+            .values()
+            .filter(|&running| running == owner)
+            .count()
+    }
+
+    /// Spawn queued jobs while there are free slots for them.
+    fn start_queued_jobs(&mut self) {
+        while let Some(index) = self.next_queued() {
+            let QueuedJob {
+                job_id,
+                spec,
+                output_tx,
+            } = self.queue.remove(index);
+            let unstarted = spec.clone();
+            if self.spawn_job(job_id, spec, output_tx).is_err() {
+                // the job's output channel is gone, so its output just ends
+                self.unstarted.insert(job_id, unstarted);
+                self.job_finished(job_id, JobStatus::FailedToStart);
+            }
+        }
+    }
+
+    /// The index of the queued job to spawn next, if there is a free slot for it.
+    fn next_queued(&self) -> Option<usize> {
+        let running = self.running_by_owner();
+        let last_spawned = self.last_spawned_by_owner();
+        let index = (0..self.queue.len())
+            .max_by_key(|&index| self.queue_key(index, &running, &last_spawned))?;
+        self.has_free_slot(&self.queue[index].spec.owner)
+            .then_some(index)
+    }
+
+    /// Where each queued job stands in the queue, from 1 for the job that is spawned next. Found by playing out the
+    /// order the queue would spawn its jobs in, which only changes if more jobs are queued, or an owner's limit
+    /// holds their jobs back.
+    ///
+    /// An owner's jobs always leave the queue in the order of their own keys, and spawning one only changes the keys
+    /// of that owner's jobs, so only the next job of each owner needs to be weighed against the others.
+    fn queue_positions(&self) -> HashMap<JobId, u32> {
+        let mut by_owner: HashMap<&Option<String>, Vec<usize>> = HashMap::new();
+        for (index, job) in self.queue.iter().enumerate() {
+            by_owner.entry(&job.spec.owner).or_default().push(index);
+        }
+        for indices in by_owner.values_mut() {
+            // an owner's next job goes last, so it can be popped
+            indices.sort_by_key(|&index| (self.queue[index].spec.priority, Reverse(index)));
+        }
+        let mut running = self.running_by_owner();
+        let mut last_spawned = self.last_spawned_by_owner();
+        let mut next = BinaryHeap::new();
+        for indices in by_owner.values_mut() {
+            if let Some(index) = indices.pop() {
+                next.push((self.queue_key(index, &running, &last_spawned), index));
+            }
+        }
+
+        let mut spawned = self.spawned;
+        let mut positions = HashMap::new();
+        while let Some((_, index)) = next.pop() {
+            let job = &self.queue[index];
+            positions.insert(job.job_id, positions.len() as u32 + 1);
+            spawned += 1;
+            *running.entry(&job.spec.owner).or_default() += 1;
+            last_spawned.insert(&job.spec.owner, spawned);
+            if let Some(index) = by_owner.get_mut(&job.spec.owner).and_then(Vec::pop) {
+                next.push((self.queue_key(index, &running, &last_spawned), index));
+            }
+        }
+        positions
+    }
+
+    /// How a queued job weighs against the others; the highest is spawned first. Jobs whose owner is at its limit go
+    /// last, then jobs with a higher priority go first, then jobs whose owner had a job spawned least recently, so
+    /// owners take turns, then jobs that were queued first.
+    fn queue_key(
+        &self,
+        index: usize,
+        running: &HashMap<&Option<String>, usize>,
+        last_spawned: &HashMap<&Option<String>, u64>,
+    ) -> (bool, i32, Reverse<u64>, Reverse<usize>) {
+        let job = &self.queue[index];
+        let owner = &job.spec.owner;
+        let at_limit = self
+            .limits
+            .max_running_per_owner
+            .is_some_and(|max| running.get(owner).copied().unwrap_or_default() >= max);
+        let last_spawned = last_spawned.get(owner).copied().unwrap_or_default();
+        (
+            !at_limit,
+            job.spec.priority,
+            Reverse(last_spawned),
+            Reverse(index),
+        )
+    }
+
+    fn running_by_owner(&self) -> HashMap<&Option<String>, usize> {
+        let mut running = HashMap::new();
+        for owner in self.running.values() {
+            *running.entry(owner).or_default() += 1;
+        }
+        running
+    }
+
+    fn last_spawned_by_owner(&self) -> HashMap<&Option<String>, u64> {
+        self.last_spawned
+            .iter()
+            .map(|(owner, &spawned)| (owner, spawned))
+            .collect()
+    }
+
+    /// The spec of a job that has no worker, because it is queued or left the queue without ever running.
+    fn unstarted_spec(&self, job_id: JobId) -> Option<&JobSpec> {
+        self.queue
+            .iter()
+            .find(|job| job.job_id == job_id)
+            .map(|job| &job.spec)
+            .or_else(|| self.unstarted.get(&job_id))
+    }
+
+    /// The status of a job that has no worker, because it is queued or left the queue without ever running.
+    fn unstarted_status(
+        &self,
+        job_id: JobId,
+        positions: &HashMap<JobId, u32>,
+    ) -> Option<JobStatus> {
+        match positions.get(&job_id) {
+            Some(&position) => Some(JobStatus::Queued { position }),
+            None if self.unstarted.contains_key(&job_id) => self.finished.get(&job_id).copied(),
+            None => None,
+        }
+    }
+
+    /// A job's status as the coordinator knows it from what its worker has reported, without asking the worker.
+    fn tracked_status(&self, job_id: JobId, positions: &HashMap<JobId, u32>) -> Option<JobStatus> {
+        if self
+            .restarts
+            .get(&job_id)
+            .is_some_and(|restarts| restarts.pending)
+        {
+            Some(JobStatus::Restarting)
+        } else if let Some(&status) = self.finished.get(&job_id) {
+            Some(status)
+        } else if self.workers.contains_key(&job_id) {
+            Some(match self.paused.contains(&job_id) {
+                true => JobStatus::Paused,
+                false => JobStatus::Running,
+            })
+        } else {
+            positions
+                .get(&job_id)
+                .map(|&position| JobStatus::Queued { position })
+        }
+    }
 
-                let job_id = uuid::Uuid::new_v4();
-                self.workers.insert(job_id, worker);
-                self.broadcasters.insert(job_id, broadcaster);
-                let _ = response.send(Ok(job_id));
+    /// The error for a job that has no worker to act on.
+    fn no_worker(&self, job_id: JobId) -> JobError {
+        match self.unstarted_spec(job_id) {
+            Some(_) => JobError::NotStarted,
+            None => JobError::DoesNotExist,
+        }
+    }
+
+    fn submit_workflow(
+        &mut self,
+        spec: WorkflowSpec,
+        response: oneshot::Sender<error::Result<WorkflowId>>,
+    ) {
+        let owner = spec.steps.first().and_then(|step| step.spec.owner.clone());
+        let workflow = match Workflow::new(spec) {
+            Ok(workflow) => workflow,
+            Err(err) => {
+                let _ = response.send(Err(err));
+                return;
+            }
+        };
+        let workflow_id = uuid::Uuid::new_v4();
+        // the steps' jobs belong to whoever submitted the workflow, even before they start
+        self.registry.workflow_added(workflow_id, owner.as_deref());
+        for job_id in workflow.job_ids() {
+            self.workflow_steps.insert(job_id, workflow_id);
+            self.registry.job_added(job_id, owner.as_deref());
+        }
+        self.workflows.insert(workflow_id, workflow);
+        self.advance_workflow(workflow_id);
+        let _ = response.send(Ok(workflow_id));
+    }
+
+    /// Start every step of the workflow that is ready to run.
+    fn advance_workflow(&mut self, workflow_id: WorkflowId) {
+        loop {
+            let ready = match self.workflows.get_mut(&workflow_id) {
+                Some(workflow) => workflow.ready_steps(),
+                None => return,
+            };
+            if ready.is_empty() {
+                return self.retain_workflow(workflow_id);
             }
-            Err(e) => {
-                // if spawning child process fails, we don't insert the worker broadcaster handles in our map.
-                // no actors spawn in this case.
-                let _ = response.send(Err(e));
+            for (job_id, spec) in ready {
+                if self.start_or_queue(job_id, spec).is_err() {
+                    // which may let other steps go ahead, or be skipped
+                    if let Some(workflow) = self.workflows.get_mut(&workflow_id) {
+                        workflow.step_failed_to_start(job_id);
+                    }
+                }
             }
         }
     }
 
-    fn stop_job(&mut self, job_id: JobId, response: oneshot::Sender<error::Result<()>>) {
+    fn get_workflow_status(
+        &mut self,
+        workflow_id: WorkflowId,
+        response: oneshot::Sender<error::Result<WorkflowStatus>>,
+    ) {
+        let status = self
+            .workflows
+            .get(&workflow_id)
+            .map(Workflow::status)
+            .ok_or(JobError::WorkflowDoesNotExist);
+        let _ = response.send(status);
+    }
+
+    fn stop_job(
+        &mut self,
+        job_id: JobId,
+        grace: Option<Duration>,
+        response: oneshot::Sender<error::Result<()>>,
+    ) {
+        if let Some(restarts) = self.restarts.get_mut(&job_id) {
+            restarts.stopped = true;
+            if restarts.pending {
+                // the last attempt already ended, calling off the next one is all that's left to do
+                restarts.pending = false;
+                restarts.output_tx = None;
+                let _ = response.send(Ok(()));
+                if let Some(status) = restarts.last_status {
+                    self.job_finished(job_id, status);
+                }
+                return;
+            }
+        }
+        if let Some(index) = self.queue.iter().position(|job| job.job_id == job_id) {
+            // dropping the job's output channel ends its output
+            let QueuedJob { spec, .. } = self.queue.remove(index);
+            self.unstarted.insert(job_id, spec);
+            let _ = response.send(Ok(()));
+            self.job_finished(job_id, JobStatus::Cancelled);
+            return;
+        }
         if let Some(worker) = self.workers.get(&job_id) {
-            worker.stop(response);
+            worker.stop(grace, response);
+        } else if self.unstarted.contains_key(&job_id) {
+            let _ = response.send(Err(JobError::AlreadyStopped));
         } else {
             let _ = response.send(Err(JobError::DoesNotExist));
         }
     }
 
+    fn pause_job(&mut self, job_id: JobId, response: oneshot::Sender<error::Result<()>>) {
+        if let Some(worker) = self.workers.get(&job_id) {
+            worker.pause(response);
+        } else {
+            let _ = response.send(Err(self.no_worker(job_id)));
+        }
+    }
+
+    fn resume_job(&mut self, job_id: JobId, response: oneshot::Sender<error::Result<()>>) {
+        if let Some(worker) = self.workers.get(&job_id) {
+            worker.resume(response);
+        } else {
+            let _ = response.send(Err(self.no_worker(job_id)));
+        }
+    }
+
+    fn signal_job(
+        &mut self,
+        job_id: JobId,
+        signal: i32,
+        response: oneshot::Sender<error::Result<()>>,
+    ) {
+        if let Some(worker) = self.workers.get(&job_id) {
+            worker.signal(signal, response);
+        } else {
+            let _ = response.send(Err(self.no_worker(job_id)));
+        }
+    }
+
     fn get_job_status(
         &mut self,
         job_id: JobId,
         response: oneshot::Sender<error::Result<JobStatus>>,
     ) {
+        if self
+            .restarts
+            .get(&job_id)
+            .is_some_and(|restarts| restarts.pending)
+        {
+            let _ = response.send(Ok(JobStatus::Restarting));
+            return;
+        }
         if let Some(worker) = self.workers.get(&job_id) {
             worker.get_status(response);
+        } else {
+            let status = self.unstarted_status(job_id, &self.queue_positions());
+            let _ = response.send(status.ok_or(JobError::DoesNotExist));
+        }
+    }
+
+    fn get_job_attempts(
+        &mut self,
+        job_id: JobId,
+        response: oneshot::Sender<error::Result<Attempts>>,
+    ) {
+        let _ = response.send(self.attempts(job_id));
+    }
+
+    fn attempts(&self, job_id: JobId) -> error::Result<Attempts> {
+        match self.restarts.get(&job_id) {
+            Some(restarts) => Ok(Attempts {
+                attempt: restarts.attempt,
+                previous: restarts.previous.clone(),
+            }),
+            // a job without a restart policy only ever has one attempt, and a queued job is waiting for its first
+            None if self.workers.contains_key(&job_id) || self.unstarted_spec(job_id).is_some() => {
+                Ok(Attempts {
+                    attempt: 1,
+                    previous: Vec::new(),
+                })
+            }
+            None => Err(JobError::DoesNotExist),
+        }
+    }
+
+    fn wait_job(&mut self, job_id: JobId, response: oneshot::Sender<error::Result<JobStatus>>) {
+        if let Some(status) = self.finished.get(&job_id) {
+            let _ = response.send(Ok(*status));
+        } else if self.workers.contains_key(&job_id) || self.unstarted_spec(job_id).is_some() {
+            self.job_waiters.entry(job_id).or_default().push(response);
         } else {
             let _ = response.send(Err(JobError::DoesNotExist));
         }
     }
 
+    fn get_job_stages(
+        &mut self,
+        job_id: JobId,
+        response: oneshot::Sender<error::Result<Vec<JobStatus>>>,
+    ) {
+        if let Some(worker) = self.workers.get(&job_id) {
+            worker.get_stages(response);
+        } else {
+            // none of the stages have run
+            let status = self.unstarted_status(job_id, &self.queue_positions());
+            let stages = match (self.unstarted_spec(job_id), status) {
+                (Some(spec), Some(status)) => Ok(vec![status; 1 + spec.pipeline.len()]),
+                _ => Err(JobError::DoesNotExist),
+            };
+            let _ = response.send(stages);
+        }
+    }
+
+    fn get_job_isolation(
+        &mut self,
+        job_id: JobId,
+        response: oneshot::Sender<error::Result<Isolation>>,
+    ) {
+        let _ = response.send(self.isolation(job_id));
+    }
+
+    fn isolation(&self, job_id: JobId) -> error::Result<Isolation> {
+        self.workers
+            .get(&job_id)
+            .map(WorkerHandle::isolation)
+            .or_else(|| self.unstarted_spec(job_id).map(|spec| spec.isolation))
+            .ok_or(JobError::DoesNotExist)
+    }
+
+    fn get_job_environment(
+        &mut self,
+        job_id: JobId,
+        response: oneshot::Sender<error::Result<Envs>>,
+    ) {
+        let _ = response.send(self.environment(job_id));
+    }
+
+    fn environment(&self, job_id: JobId) -> error::Result<Envs> {
+        match self.workers.get(&job_id) {
+            Some(worker) => Ok(worker.environment()),
+            // what the job would run with if it started now
+            None => self
+                .unstarted_spec(job_id)
+                .map(|spec| spec.env_policy.environment(&spec.envs).unwrap_or_default())
+                .ok_or(JobError::DoesNotExist),
+        }
+    }
+
+    fn get_job_terminal(
+        &mut self,
+        job_id: JobId,
+        response: oneshot::Sender<error::Result<Terminal>>,
+    ) {
+        let terminal = match self.workers.get(&job_id) {
+            Some(worker) => worker.terminal().ok_or(JobError::NoTerminal),
+            None => Err(self.no_worker(job_id)),
+        };
+        let _ = response.send(terminal);
+    }
+
+    fn get_job_stdin(
+        &mut self,
+        job_id: JobId,
+        response: oneshot::Sender<error::Result<StdinWriter>>,
+    ) {
+        let stdin = match self.workers.get(&job_id) {
+            Some(worker) => worker.stdin().ok_or(JobError::NoStdinStream),
+            None => Err(self.no_worker(job_id)),
+        };
+        let _ = response.send(stdin);
+    }
+
     fn stream_stdout(
         &mut self,
         job_id: JobId,
-        response: oneshot::Sender<error::Result<mpsc::UnboundedReceiver<OutputBlob>>>,
+        offset: u64,
+        response: oneshot::Sender<error::Result<mpsc::Receiver<OutputEvent>>>,
     ) {
-        let (subscriber_tx, subscriber_rx) = mpsc::unbounded_channel();
         if let Some(broadcaster) = self.broadcasters.get(&job_id) {
-            broadcaster.stream_stdout(subscriber_tx);
-            let _ = response.send(Ok(subscriber_rx));
+            let _ = response.send(Ok(broadcaster.stream_stdout(offset)));
         } else {
             let _ = response.send(Err(JobError::DoesNotExist));
         }
@@ -120,12 +1196,11 @@ impl JobCoordinator {
     fn stream_stderr(
         &mut self,
         job_id: JobId,
-        response: oneshot::Sender<error::Result<mpsc::UnboundedReceiver<OutputBlob>>>,
+        offset: u64,
+        response: oneshot::Sender<error::Result<mpsc::Receiver<OutputEvent>>>,
     ) {
-        let (subscriber_tx, subscriber_rx) = mpsc::unbounded_channel();
         if let Some(broadcaster) = self.broadcasters.get(&job_id) {
-            broadcaster.stream_stderr(subscriber_tx);
-            let _ = response.send(Ok(subscriber_rx));
+            let _ = response.send(Ok(broadcaster.stream_stderr(offset)));
         } else {
             let _ = response.send(Err(JobError::DoesNotExist));
         }
@@ -134,14 +1209,179 @@ impl JobCoordinator {
     fn stream_all(
         &mut self,
         job_id: JobId,
-        response: oneshot::Sender<error::Result<mpsc::UnboundedReceiver<OutputBlob>>>,
+        offset: u64,
+        response: oneshot::Sender<error::Result<mpsc::Receiver<OutputEvent>>>,
     ) {
-        let (subscriber_tx, subscriber_rx) = mpsc::unbounded_channel();
         if let Some(broadcaster) = self.broadcasters.get(&job_id) {
-            broadcaster.stream_all(subscriber_tx);
-            let _ = response.send(Ok(subscriber_rx));
+            let _ = response.send(Ok(broadcaster.stream_all(offset)));
         } else {
             let _ = response.send(Err(JobError::DoesNotExist));
         }
     }
+
+    /// Report back to the coordinator when an attempt of a job is paused, resumed, or finishes. The worker reports
+    /// them straight onto the coordinator's events, in the order they happened, and before it answers the request
+    /// that paused or resumed the job.
+    fn wait_for_attempt(&self, job_id: JobId, attempt: u32, worker: &WorkerHandle) {
+        let events_tx = self.job_events_tx.clone();
+        worker.watch(Box::new(move |event| {
+            let event = match event {
+                WorkerEvent::Paused => JobEvent::Lifecycle {
+                    job_id,
+                    kind: LifecycleEventKind::Paused,
+                },
+                WorkerEvent::Resumed => JobEvent::Lifecycle {
+                    job_id,
+                    kind: LifecycleEventKind::Resumed,
+                },
+                WorkerEvent::Exited(status) => JobEvent::Exited {
+                    job_id,
+                    attempt,
+                    status,
+                },
+            };
+            let _ = events_tx.send(event);
+        }));
+    }
+
+    fn handle_job_event(&mut self, event: JobEvent) {
+        match event {
+            JobEvent::Exited {
+                job_id,
+                attempt,
+                status,
+            } => {
+                if self
+                    .restarts
+                    .get(&job_id)
+                    .is_some_and(|restarts| restarts.attempt != attempt)
+                {
+                    return;
+                }
+                let kind = match status {
+                    JobStatus::Exited { code, .. } => LifecycleEventKind::Exited { code },
+                    JobStatus::Killed { signal, .. } => LifecycleEventKind::Killed { signal },
+                    _ => unreachable!(), // workers only report how an attempt ended
+                };
+                self.paused.remove(&job_id);
+                self.publish(job_id, self.owner_of(job_id), kind);
+                let restarts = match self.restarts.get_mut(&job_id) {
+                    Some(restarts) => restarts,
+                    // a job without a restart policy is done after its first attempt
+                    None => return self.job_finished(job_id, status),
+                };
+                restarts.last_status = Some(status);
+                if restarts.stopped || !restarts.spec.restart_policy.should_restart(attempt, status)
+                {
+                    // that was the last attempt, the job's output ends once its pipes are closed
+                    restarts.output_tx = None;
+                    return self.job_finished(job_id, status);
+                }
+                restarts.pending = true;
+                let backoff = restarts.spec.restart_backoff.delay(attempt);
+                let events_tx = self.job_events_tx.clone();
+                tokio::spawn(async move {
+                    time::sleep(backoff).await;
+                    let _ = events_tx.send(JobEvent::BackoffElapsed { job_id, attempt });
+                });
+            }
+            JobEvent::Expired { job_id } => self.forget(job_id),
+            JobEvent::WorkflowExpired { workflow_id } => self.forget_workflow_if_done(workflow_id),
+            JobEvent::Lifecycle { job_id, kind } => {
+                match kind {
+                    LifecycleEventKind::Paused => self.paused.insert(job_id),
+                    _ => self.paused.remove(&job_id),
+                };
+                self.publish(job_id, self.owner_of(job_id), kind)
+            }
+            JobEvent::BackoffElapsed { job_id, attempt } => {
+                let restarts = match self.restarts.get_mut(&job_id) {
+                    // the job may have been stopped during the backoff
+                    Some(restarts) if restarts.attempt == attempt && restarts.pending => restarts,
+                    _ => return,
+                };
+                restarts.pending = false;
+                let output_tx = match &restarts.output_tx {
+                    Some(output_tx) => output_tx.clone(),
+                    None => return,
+                };
+                let _ = output_tx.send(Output::Attempt(attempt + 1));
+                match WorkerHandle::spawn(job_id, output_tx, restarts.spec.clone()) {
+                    Ok(worker) => {
+                        restarts.previous.extend(restarts.last_status.take());
+                        restarts.attempt += 1;
+                        let owner = restarts.spec.owner.clone();
+                        self.wait_for_attempt(job_id, attempt + 1, &worker);
+                        let started = LifecycleEventKind::Started {
+                            attempt: attempt + 1,
+                        };
+                        self.publish(job_id, owner, started);
+                        // replacing the last attempt's worker lets its actor exit
+                        self.workers.insert(job_id, worker);
+                    }
+                    Err(_) => {
+                        // the job can't be started anymore, so the last attempt is its final one
+                        restarts.output_tx = None;
+                        if let Some(status) = restarts.last_status {
+                            self.job_finished(job_id, status);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// A job has finished for good, it won't be restarted.
+    fn job_finished(&mut self, job_id: JobId, status: JobStatus) {
+        // how the job's last attempt ended was already published, unless it never ran
+        let never_ran = match status {
+            JobStatus::Cancelled => Some(LifecycleEventKind::Cancelled),
+            JobStatus::FailedToStart => Some(LifecycleEventKind::FailedToStart),
+            _ => None,
+        };
+        if let Some(kind) = never_ran {
+            self.publish(job_id, self.owner_of(job_id), kind);
+        }
+        self.running.remove(&job_id);
+        self.finished.insert(job_id, status);
+        if let Some(job) = self.jobs.get_mut(&job_id) {
+            job.finished = Some(SystemTime::now());
+        }
+        for waiter in self.job_waiters.remove(&job_id).unwrap_or_default() {
+            let _ = waiter.send(Ok(status));
+        }
+        // the freed slot goes to the queue before any workflow steps that were waiting on this job
+        self.start_queued_jobs();
+        if let Some(workflow_id) = self.workflow_steps.get(&job_id).copied() {
+            if let Some(workflow) = self.workflows.get_mut(&workflow_id) {
+                match status {
+                    JobStatus::FailedToStart => workflow.step_failed_to_start(job_id),
+                    status => workflow.step_finished(job_id, status),
+                };
+            }
+            self.advance_workflow(workflow_id);
+        }
+        self.retain(job_id);
+    }
+
+    fn owner_of(&self, job_id: JobId) -> Option<String> {
+        self.jobs.get(&job_id).and_then(|job| job.owner.clone())
+    }
+
+    /// Send a lifecycle event to every subscriber interested in it.
+    fn publish(&mut self, job_id: JobId, owner: Option<String>, kind: LifecycleEventKind) {
+        let event = LifecycleEvent {
+            job_id,
+            owner,
+            kind,
+            time: SystemTime::now(),
+        };
+        // subscribers that went away are dropped along the way
+        self.subscribers.retain(|subscriber| {
+            let EventFilter { job_id, owner } = &subscriber.filter;
+            let matches = job_id.is_none_or(|job_id| job_id == event.job_id)
+                && (owner.is_none() || *owner == event.owner);
+            !matches || subscriber.events_tx.send(event.clone()).is_ok()
+        });
+    }
 }
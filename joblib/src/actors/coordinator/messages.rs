@@ -1,36 +1,128 @@
 use crate::error;
-use crate::events::{JobStatus, OutputBlob};
-use crate::types::{Args, Dir, Envs, JobId, Program};
-use std::io;
+use crate::events::{
+    Attempts, JobDetails, JobInfo, JobListing, JobMetadata, JobStatus, LifecycleEvent, OutputEvent,
+    WorkflowStatus,
+};
+use crate::pty::Terminal;
+use crate::stdin::StdinWriter;
+use crate::types::{
+    Envs, EventFilter, Isolation, JobFilter, JobId, JobPage, JobSpec, WorkflowId, WorkflowSpec,
+};
+use std::{io, time::Duration};
 use tokio::sync::{mpsc, oneshot};
 
 #[derive(Debug)]
 pub enum CoordinatorMessage {
     StartJob {
-        cmd: Program,
-        args: Args,
-        dir: Dir,
-        envs: Envs,
+        spec: Box<JobSpec>, // boxed, specs are much bigger than any other message
         response: oneshot::Sender<io::Result<JobId>>,
     },
     StopJob {
         job_id: JobId,
+        grace: Option<Duration>,
+        response: oneshot::Sender<error::Result<()>>,
+    },
+    PauseJob {
+        job_id: JobId,
+        response: oneshot::Sender<error::Result<()>>,
+    },
+    ResumeJob {
+        job_id: JobId,
+        response: oneshot::Sender<error::Result<()>>,
+    },
+    SignalJob {
+        job_id: JobId,
+        signal: i32,
         response: oneshot::Sender<error::Result<()>>,
     },
     GetStatus {
         job_id: JobId,
         response: oneshot::Sender<error::Result<JobStatus>>,
     },
+    /// Respond with the job's final status once it has finished for good, after its last attempt.
+    WaitJob {
+        job_id: JobId,
+        response: oneshot::Sender<error::Result<JobStatus>>,
+    },
+    GetInfo {
+        job_id: JobId,
+        response: oneshot::Sender<error::Result<JobInfo>>,
+    },
+    GetMetadata {
+        job_id: JobId,
+        response: oneshot::Sender<error::Result<JobMetadata>>,
+    },
+    GetDetails {
+        job_id: JobId,
+        response: oneshot::Sender<error::Result<JobDetails>>,
+    },
+    FindByName {
+        owner: Option<String>,
+        name: String,
+        response: oneshot::Sender<error::Result<JobId>>,
+    },
+    FindByIdPrefix {
+        prefix: String,
+        response: oneshot::Sender<Vec<JobId>>,
+    },
+    DeleteJob {
+        job_id: JobId,
+        response: oneshot::Sender<error::Result<()>>,
+    },
+    ListJobs {
+        filter: JobFilter,
+        page: JobPage,
+        response: oneshot::Sender<JobListing>,
+    },
+    SubscribeEvents {
+        filter: EventFilter,
+        response: oneshot::Sender<mpsc::UnboundedReceiver<LifecycleEvent>>,
+    },
+    GetStages {
+        job_id: JobId,
+        response: oneshot::Sender<error::Result<Vec<JobStatus>>>,
+    },
+    GetIsolation {
+        job_id: JobId,
+        response: oneshot::Sender<error::Result<Isolation>>,
+    },
+    GetEnvironment {
+        job_id: JobId,
+        response: oneshot::Sender<error::Result<Envs>>,
+    },
+    GetTerminal {
+        job_id: JobId,
+        response: oneshot::Sender<error::Result<Terminal>>,
+    },
+    GetStdin {
+        job_id: JobId,
+        response: oneshot::Sender<error::Result<StdinWriter>>,
+    },
     StreamStdout {
         job_id: JobId,
-        response: oneshot::Sender<error::Result<mpsc::UnboundedReceiver<OutputBlob>>>,
+        offset: u64,
+        response: oneshot::Sender<error::Result<mpsc::Receiver<OutputEvent>>>,
     },
     StreamStderr {
         job_id: JobId,
-        response: oneshot::Sender<error::Result<mpsc::UnboundedReceiver<OutputBlob>>>,
+        offset: u64,
+        response: oneshot::Sender<error::Result<mpsc::Receiver<OutputEvent>>>,
     },
     StreamAll {
         job_id: JobId,
-        response: oneshot::Sender<error::Result<mpsc::UnboundedReceiver<OutputBlob>>>,
+        offset: u64,
+        response: oneshot::Sender<error::Result<mpsc::Receiver<OutputEvent>>>,
+    },
+    GetAttempts {
+        job_id: JobId,
+        response: oneshot::Sender<error::Result<Attempts>>,
+    },
+    SubmitWorkflow {
+        spec: WorkflowSpec,
+        response: oneshot::Sender<error::Result<WorkflowId>>,
+    },
+    GetWorkflowStatus {
+        workflow_id: WorkflowId,
+        response: oneshot::Sender<error::Result<WorkflowStatus>>,
     },
 }
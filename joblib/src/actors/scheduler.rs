@@ -0,0 +1,96 @@
+mod actor;
+mod messages;
+
+use self::{
+    actor::JobScheduler,
+    messages::SchedulerMessage::{self, CreateSchedule, DeleteSchedule, ListSchedules},
+};
+use crate::actors::coordinator::JobCoordinatorHandle;
+use crate::error;
+use crate::events::ScheduleInfo;
+use crate::types::{JobRegistry, ScheduleId, ScheduleSpec};
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot};
+
+/// How many of the jobs each schedule started most recently are kept in its history, by default.
+const DEFAULT_HISTORY_LEN: usize = 100;
+
+/// A `JobScheduler` which starts jobs on cron schedules, through a `JobCoordinator`.
+///
+/// Like the `JobCoordinator`, this struct is an actor handle that can be cloned freely in a multi-thread async
+/// context. The actor keeps every schedule and the jobs each one has started most recently.
+#[derive(Clone)]
+pub struct JobSchedulerHandle {
+    sender: mpsc::Sender<SchedulerMessage>,
+}
+
+impl JobSchedulerHandle {
+    /// Spawn a new scheduler which starts jobs with `coordinator`. Each job is started with its schedule's job spec,
+    /// so the coordinator's registry knows it, and its owner, before the scheduler does.
+    ///
+    /// Specify the capacity for the scheduler's message queue. This limits the build-up of inbound messages.
+    pub fn spawn(coordinator: JobCoordinatorHandle, message_capacity: usize) -> Self {
+        Self::spawn_with_history(coordinator, message_capacity, DEFAULT_HISTORY_LEN)
+    }
+
+    /// Spawn a new scheduler, like `spawn`, that keeps the last `history_len` jobs each schedule started.
+    pub fn spawn_with_history(
+        coordinator: JobCoordinatorHandle,
+        message_capacity: usize,
+        history_len: usize,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel(message_capacity);
+        JobScheduler::spawn(receiver, coordinator, history_len, Arc::new(()));
+        Self { sender }
+    }
+
+    /// Spawn a new scheduler, like `spawn`, that tells `registry` about every schedule as it is created and deleted,
+    /// before `create_schedule` or `delete_schedule` returns, so the caller can keep track of who owns each one.
+    pub fn spawn_with_registry(
+        coordinator: JobCoordinatorHandle,
+        message_capacity: usize,
+        registry: Arc<dyn JobRegistry>,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel(message_capacity);
+        JobScheduler::spawn(receiver, coordinator, DEFAULT_HISTORY_LEN, registry);
+        Self { sender }
+    }
+
+    /// Create a schedule. A job is started from the schedule's job spec on every tick, until it is deleted.
+    ///
+    /// Fails if the cron expression or the time zone is invalid.
+    pub async fn create_schedule(&self, spec: ScheduleSpec) -> error::Result<ScheduleId> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(CreateSchedule {
+                spec: Box::new(spec),
+                response: tx,
+            })
+            .await
+            .expect("JobScheduler exited");
+        rx.await.expect("JobScheduler exited")
+    }
+
+    /// Get every schedule, and the jobs each one has started most recently.
+    pub async fn list_schedules(&self) -> Vec<ScheduleInfo> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(ListSchedules { response: tx })
+            .await
+            .expect("JobScheduler exited");
+        rx.await.expect("JobScheduler exited")
+    }
+
+    /// Delete a schedule. Jobs it already started are left running.
+    pub async fn delete_schedule(&self, schedule_id: ScheduleId) -> error::Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(DeleteSchedule {
+                schedule_id,
+                response: tx,
+            })
+            .await
+            .expect("JobScheduler exited");
+        rx.await.expect("JobScheduler exited")
+    }
+}
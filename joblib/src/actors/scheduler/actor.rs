@@ -0,0 +1,320 @@
+use super::messages::SchedulerMessage;
+use crate::actors::coordinator::JobCoordinatorHandle;
+use crate::error::{self, Error as JobError};
+use crate::events::ScheduleInfo;
+use crate::types::{JobId, JobRegistry, OverlapPolicy, ScheduleId, ScheduleSpec};
+use chrono::Utc;
+use chrono_tz::Tz;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io,
+    str::FromStr,
+    sync::Arc,
+    time::SystemTime,
+};
+use tokio::{
+    select,
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+    time,
+};
+
+pub struct JobScheduler {
+    inbox: mpsc::Receiver<SchedulerMessage>,
+    coordinator: JobCoordinatorHandle,
+    schedules: HashMap<ScheduleId, Schedule>,
+    /// how many of the jobs each schedule started are kept in its history
+    history_len: usize,
+    /// told about every schedule as it is created and deleted
+    registry: Arc<dyn JobRegistry>,
+    // the scheduler sends these to itself, from the tasks that wait on ticks and jobs
+    schedule_events_tx: mpsc::UnboundedSender<ScheduleEvent>,
+    schedule_events_rx: mpsc::UnboundedReceiver<ScheduleEvent>,
+}
+
+struct Schedule {
+    spec: ScheduleSpec,
+    cron: cron::Schedule,
+    timezone: Tz,
+    /// jobs started by the schedule that haven't finished for good
+    running: HashSet<JobId>,
+    /// ticks whose jobs the coordinator hasn't answered for yet. They count as running for the overlap policy.
+    starting: u32,
+    /// the jobs the schedule started most recently, oldest first
+    history: VecDeque<JobId>,
+    skipped: u32,
+    queued: u32,
+    /// sleeps until each tick
+    timer: JoinHandle<()>,
+}
+
+enum ScheduleEvent {
+    Tick {
+        schedule_id: ScheduleId,
+    },
+    /// the coordinator answered a request to start a job for one of the schedule's ticks
+    JobStarted {
+        schedule_id: ScheduleId,
+        result: io::Result<JobId>,
+    },
+    JobFinished {
+        schedule_id: ScheduleId,
+        job_id: JobId,
+    },
+}
+
+impl JobScheduler {
+    pub fn spawn(
+        inbox: mpsc::Receiver<SchedulerMessage>,
+        coordinator: JobCoordinatorHandle,
+        history_len: usize,
+        registry: Arc<dyn JobRegistry>,
+    ) {
+        let (schedule_events_tx, schedule_events_rx) = mpsc::unbounded_channel();
+        let actor = Self {
+            inbox,
+            coordinator,
+            schedules: HashMap::new(),
+            history_len,
+            registry,
+            schedule_events_tx,
+            schedule_events_rx,
+        };
+        tokio::spawn(async move { actor.run().await });
+    }
+
+    async fn run(mut self) {
+        use self::SchedulerMessage::*;
+        loop {
+            let msg = select! {
+                maybe_msg = self.inbox.recv() => match maybe_msg {
+                    Some(msg) => msg,
+                    None => break, // every handle was dropped
+                },
+                Some(event) = self.schedule_events_rx.recv() => {
+                    self.handle_schedule_event(event);
+                    continue;
+                }
+            };
+            match msg {
+                CreateSchedule { spec, response } => {
+                    self.create_schedule(*spec, response);
+                }
+                ListSchedules { response } => {
+                    self.list_schedules(response);
+                }
+                DeleteSchedule {
+                    schedule_id,
+                    response,
+                } => {
+                    self.delete_schedule(schedule_id, response);
+                }
+            }
+        }
+        // schedules don't outlive the scheduler
+        for schedule in self.schedules.values() {
+            schedule.timer.abort();
+        }
+    }
+
+    fn create_schedule(
+        &mut self,
+        spec: ScheduleSpec,
+        response: oneshot::Sender<error::Result<ScheduleId>>,
+    ) {
+        let parsed = parse_cron(&spec.cron).and_then(|cron| {
+            let timezone = Tz::from_str(&spec.timezone).map_err(|_| {
+                JobError::InvalidSchedule(format!("unknown time zone {}", spec.timezone))
+            })?;
+            Ok((cron, timezone))
+        });
+        let (cron, timezone) = match parsed {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                let _ = response.send(Err(err));
+                return;
+            }
+        };
+
+        let schedule_id = uuid::Uuid::new_v4();
+        let ticks = cron.upcoming_owned(timezone);
+        let events_tx = self.schedule_events_tx.clone();
+        let timer = tokio::spawn(async move {
+            for tick in ticks {
+                // a tick that is already due fires right away
+                let delay = (tick.with_timezone(&Utc) - Utc::now())
+                    .to_std()
+                    .unwrap_or_default();
+                time::sleep(delay).await;
+                if events_tx.send(ScheduleEvent::Tick { schedule_id }).is_err() {
+                    break;
+                }
+            }
+        });
+        self.schedules.insert(
+            schedule_id,
+            Schedule {
+                spec,
+                cron,
+                timezone,
+                running: HashSet::new(),
+                starting: 0,
+                history: VecDeque::new(),
+                skipped: 0,
+                queued: 0,
+                timer,
+            },
+        );
+        let owner = self.schedules[&schedule_id].spec.job.owner.as_deref();
+        self.registry.schedule_added(schedule_id, owner);
+        let _ = response.send(Ok(schedule_id));
+    }
+
+    fn list_schedules(&self, response: oneshot::Sender<Vec<ScheduleInfo>>) {
+        let mut schedules: Vec<_> = self
+            .schedules
+            .iter()
+            .map(|(&schedule_id, schedule)| ScheduleInfo {
+                schedule_id,
+                cron: schedule.spec.cron.clone(),
+                timezone: schedule.spec.timezone.clone(),
+                overlap: schedule.spec.overlap,
+                next_tick: schedule
+                    .cron
+                    .upcoming(schedule.timezone)
+                    .next()
+                    .map(SystemTime::from),
+                history: schedule.history.iter().copied().collect(),
+                skipped: schedule.skipped,
+                queued: schedule.queued,
+            })
+            .collect();
+        schedules.sort_by_key(|schedule| schedule.schedule_id);
+        let _ = response.send(schedules);
+    }
+
+    fn delete_schedule(
+        &mut self,
+        schedule_id: ScheduleId,
+        response: oneshot::Sender<error::Result<()>>,
+    ) {
+        let result = match self.schedules.remove(&schedule_id) {
+            Some(schedule) => {
+                schedule.timer.abort();
+                self.registry.schedule_deleted(schedule_id);
+                Ok(())
+            }
+            None => Err(JobError::ScheduleDoesNotExist),
+        };
+        let _ = response.send(result);
+    }
+
+    fn handle_schedule_event(&mut self, event: ScheduleEvent) {
+        match event {
+            ScheduleEvent::Tick { schedule_id } => {
+                let schedule = match self.schedules.get_mut(&schedule_id) {
+                    Some(schedule) => schedule,
+                    None => return, // deleted
+                };
+                if !schedule.running.is_empty() || schedule.starting > 0 {
+                    match schedule.spec.overlap {
+                        OverlapPolicy::Skip => {
+                            schedule.skipped += 1;
+                            return;
+                        }
+                        OverlapPolicy::Queue => {
+                            schedule.queued += 1;
+                            return;
+                        }
+                        OverlapPolicy::Allow => {}
+                    }
+                }
+                self.start_job(schedule_id);
+            }
+            ScheduleEvent::JobStarted {
+                schedule_id,
+                result,
+            } => {
+                let schedule = match self.schedules.get_mut(&schedule_id) {
+                    Some(schedule) => schedule,
+                    None => return, // deleted, the job is left running
+                };
+                schedule.starting -= 1;
+                match result {
+                    Ok(job_id) => {
+                        schedule.running.insert(job_id);
+                        schedule.history.push_back(job_id);
+                        if schedule.history.len() > self.history_len {
+                            schedule.history.pop_front();
+                        }
+                    }
+                    Err(_) => {
+                        schedule.skipped += 1;
+                        self.start_queued_job(schedule_id);
+                    }
+                }
+            }
+            ScheduleEvent::JobFinished {
+                schedule_id,
+                job_id,
+            } => {
+                if let Some(schedule) = self.schedules.get_mut(&schedule_id) {
+                    schedule.running.remove(&job_id);
+                    self.start_queued_job(schedule_id);
+                }
+            }
+        }
+    }
+
+    /// Start the job of a tick that was queued behind the schedule's last job, once that one is done.
+    fn start_queued_job(&mut self, schedule_id: ScheduleId) {
+        let schedule = match self.schedules.get_mut(&schedule_id) {
+            Some(schedule) => schedule,
+            None => return,
+        };
+        if schedule.running.is_empty() && schedule.starting == 0 && schedule.queued > 0 {
+            schedule.queued -= 1;
+            self.start_job(schedule_id);
+        }
+    }
+
+    /// Start a job for one of the schedule's ticks. The coordinator is asked from a task of its own, so the scheduler
+    /// keeps handling messages while the coordinator is busy, and the job's id comes back as a `JobStarted` event.
+    fn start_job(&mut self, schedule_id: ScheduleId) {
+        let schedule = match self.schedules.get_mut(&schedule_id) {
+            Some(schedule) => schedule,
+            None => return,
+        };
+        schedule.starting += 1;
+
+        let spec = schedule.spec.job.clone();
+        let coordinator = self.coordinator.clone();
+        let events_tx = self.schedule_events_tx.clone();
+        tokio::spawn(async move {
+            let result = coordinator.start_job(spec).await;
+            let started = result.as_ref().ok().copied();
+            let _ = events_tx.send(ScheduleEvent::JobStarted {
+                schedule_id,
+                result,
+            });
+            if let Some(job_id) = started {
+                let _ = coordinator.wait_for_job(job_id).await;
+                let _ = events_tx.send(ScheduleEvent::JobFinished {
+                    schedule_id,
+                    job_id,
+                });
+            }
+        });
+    }
+}
+
+/// Parse a cron expression. The cron crate wants a seconds field first, so a standard 5 field expression runs at
+/// second 0.
+fn parse_cron(expression: &str) -> error::Result<cron::Schedule> {
+    let expression = if expression.split_whitespace().count() == 5 {
+        format!("0 {}", expression)
+    } else {
+        expression.to_string()
+    };
+    cron::Schedule::from_str(&expression).map_err(|err| JobError::InvalidSchedule(err.to_string()))
+}
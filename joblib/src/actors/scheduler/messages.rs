@@ -0,0 +1,19 @@
+use crate::error;
+use crate::events::ScheduleInfo;
+use crate::types::{ScheduleId, ScheduleSpec};
+use tokio::sync::oneshot;
+
+#[derive(Debug)]
+pub enum SchedulerMessage {
+    CreateSchedule {
+        spec: Box<ScheduleSpec>, // boxed, specs are much bigger than any other message
+        response: oneshot::Sender<error::Result<ScheduleId>>,
+    },
+    ListSchedules {
+        response: oneshot::Sender<Vec<ScheduleInfo>>,
+    },
+    DeleteSchedule {
+        schedule_id: ScheduleId,
+        response: oneshot::Sender<error::Result<()>>,
+    },
+}
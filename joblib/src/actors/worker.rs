@@ -1,44 +1,228 @@
 mod actor;
 mod messages;
 
+use crate::cgroup::Cgroup;
 use crate::error;
-use crate::events::JobStatus;
-use crate::types::{Args, Dir, Envs, Output, Program};
+use crate::events::{JobStatus, ResourceUsage};
+use crate::namespaces;
+use crate::privileges;
+use crate::process_group::{self, JobProcesses};
+use crate::pty::{self, Pty, Terminal};
+use crate::stdin::{self, StdinWriter};
+use crate::types::{
+    Credentials, Envs, Isolation, JobId, JobSpec, Output, PipelineStage, StdinSource, WindowSize,
+};
 use actor::Actor;
 use messages::WorkerMessage;
-use std::{io, process::Stdio};
+use std::{fs::File, io, iter, process::Stdio, time::Duration};
 use tokio::{
+    io::unix::AsyncFd,
     process,
     sync::{mpsc, oneshot},
+    time,
 };
 
+/// What a worker tells those watching it, in the order it happened to the job.
+#[derive(Clone, Copy, Debug)]
+pub enum WorkerEvent {
+    Paused,
+    Resumed,
+    /// every process of the job has exited, and this is how the job ended
+    Exited(JobStatus),
+}
+
+/// Called by the worker as things happen to the job. A watcher is told about a pause or resume before whoever asked
+/// for it gets an answer.
+pub type Watcher = Box<dyn Fn(WorkerEvent) + Send>;
+
 #[derive(Clone)]
 pub struct WorkerHandle {
     sender: mpsc::UnboundedSender<WorkerMessage>,
+    pid: u32,
+    isolation: Isolation,
+    environment: Envs,
+    terminal: Option<Terminal>,
+    stdin: Option<StdinWriter>,
 }
 
 impl WorkerHandle {
     pub fn spawn(
+        job_id: JobId,
         output_tx: mpsc::UnboundedSender<Output>,
-        cmd: Program,
-        args: Args,
-        dir: Dir,
-        envs: Envs,
+        spec: JobSpec,
     ) -> io::Result<Self> {
-        // spawn the child process but dont await it yet
-        let mut command = process::Command::new(cmd);
-        let child = command
-            .args(args)
-            .current_dir(dir)
-            .envs(envs)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .kill_on_drop(true)
-            .spawn()?;
+        let JobSpec {
+            cmd,
+            args,
+            dir,
+            envs,
+            env_policy,
+            pipeline,
+            pipefail,
+            limits,
+            isolation,
+            credentials,
+            tty,
+            stdin,
+            timeout,
+            stop_policy,
+            restart_policy: _, // restarts are up to the coordinator
+            restart_backoff: _,
+            owner: _, // so is queueing
+            priority: _,
+            name: _, // and keeping track of names and labels
+            labels: _,
+        } = spec;
+
+        // the stages of a pipeline share a process group, which a terminal's session or a supervisor can't lead
+        if !pipeline.is_empty() && (tty.is_some() || isolation.pid) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a pipeline can't have a terminal or run in a new pid namespace",
+            ));
+        }
+
+        let environment = env_policy.environment(&envs)?;
+
+        // the cgroup has to exist before the child does, so the child can join it before exec
+        let cgroup = if limits.is_empty() {
+            None
+        } else {
+            Some(Cgroup::create(job_id, &limits)?)
+        };
+
+        // spawn the child processes but dont await them yet
+        let single = pipeline.is_empty();
+        let stages = iter::once(PipelineStage { cmd, args }).chain(pipeline);
+        let mut children: Vec<process::Child> = Vec::new();
+        let mut master = None;
+        for PipelineStage { cmd, args } in stages {
+            let mut command = process::Command::new(cmd);
+            command
+                .args(args)
+                .current_dir(&dir)
+                .env_clear()
+                .envs(environment.iter().cloned())
+                .kill_on_drop(true);
+            // a single command leads its own session. The first stage of a pipeline leads a new process group in
+            // our session instead, and the rest join it.
+            let process_group = match children.first() {
+                None if single => None,
+                None => Some(0),
+                Some(leader) => {
+                    Some(leader.id().expect("child has not been reaped yet") as libc::pid_t)
+                }
+            };
+            // read the previous stage's stdout. It's taken so the broadcaster only gets the last stage's.
+            let pipe = children
+                .last_mut()
+                .map(|previous| -> io::Result<Stdio> {
+                    previous.stdout.take().expect("stdout is piped").try_into()
+                })
+                .transpose();
+            let spawned = pipe.and_then(|pipe| {
+                match pipe {
+                    Some(pipe) => {
+                        command.stdin(pipe);
+                    }
+                    None if tty.is_none() => {
+                        let stdio = match stdin {
+                            StdinSource::Null => Stdio::null(),
+                            StdinSource::Bytes(_) | StdinSource::Stream => Stdio::piped(),
+                        };
+                        command.stdin(stdio);
+                    }
+                    None => {}
+                }
+                spawn_child(
+                    &mut command,
+                    cgroup.as_ref(),
+                    isolation,
+                    tty,
+                    credentials.clone(),
+                    process_group,
+                )
+            });
+            let (child, stage_master) = match spawned {
+                Ok(spawned) => spawned,
+                Err(err) => {
+                    // stages that already started are still in the cgroup until they have exited
+                    match children.first() {
+                        Some(leader) => {
+                            let processes = job_processes(leader, isolation);
+                            tokio::spawn(abandon(children, processes, cgroup));
+                        }
+                        None => {
+                            if let Some(cgroup) = cgroup {
+                                let _ = cgroup.remove();
+                            }
+                        }
+                    }
+                    return Err(err);
+                }
+            };
+            // close our copies of the terminal slave, so the master sees EOF when the job closes its copies,
+            // and of the pipe from the previous stage, so it gets SIGPIPE if this one exits early
+            drop(command);
+            master = master.or(stage_master);
+            children.push(child);
+        }
+
+        let terminal = master.map(|master| pty::spawn_io(master, output_tx.clone()));
+
+        // the first stage's stdin is only piped for the byte and stream sources
+        let stdin = match (stdin, children[0].stdin.take()) {
+            (StdinSource::Bytes(data), Some(child_stdin)) => {
+                stdin::write_bytes(child_stdin, data);
+                None
+            }
+            (StdinSource::Stream, Some(child_stdin)) => Some(stdin::spawn_writer(child_stdin)),
+            _ => None,
+        };
+
+        let pid = children[0].id().expect("child has not been reaped yet");
+        let processes = job_processes(&children[0], isolation);
 
         let (sender, inbox) = mpsc::unbounded_channel();
-        Actor::spawn(inbox, output_tx, child);
-        Ok(Self { sender })
+        Actor::spawn(
+            inbox,
+            output_tx,
+            children,
+            pipefail,
+            processes,
+            cgroup,
+            timeout,
+            stop_policy,
+        );
+        Ok(Self {
+            sender,
+            pid,
+            isolation,
+            environment,
+            terminal,
+            stdin,
+        })
+    }
+
+    /// The pid of the job's first process, which is the supervisor for a job in a new pid namespace.
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    pub fn isolation(&self) -> Isolation {
+        self.isolation
+    }
+
+    pub fn environment(&self) -> Envs {
+        self.environment.clone()
+    }
+
+    pub fn terminal(&self) -> Option<Terminal> {
+        self.terminal.clone()
+    }
+
+    pub fn stdin(&self) -> Option<StdinWriter> {
+        self.stdin.clone()
     }
 
     pub fn get_status(&self, status_tx: oneshot::Sender<error::Result<JobStatus>>) {
@@ -47,7 +231,132 @@ impl WorkerHandle {
         });
     }
 
-    pub fn stop(&self, response: oneshot::Sender<error::Result<()>>) {
-        let _ = self.sender.send(WorkerMessage::Stop { response });
+    pub fn get_stages(&self, response: oneshot::Sender<error::Result<Vec<JobStatus>>>) {
+        let _ = self.sender.send(WorkerMessage::GetStages { response });
+    }
+
+    pub fn get_resource_usage(&self, response: oneshot::Sender<Option<ResourceUsage>>) {
+        let _ = self
+            .sender
+            .send(WorkerMessage::GetResourceUsage { response });
+    }
+
+    pub fn watch(&self, watcher: Watcher) {
+        let _ = self.sender.send(WorkerMessage::Watch { watcher });
+    }
+
+    pub fn pause(&self, response: oneshot::Sender<error::Result<()>>) {
+        let _ = self.sender.send(WorkerMessage::Pause { response });
+    }
+
+    pub fn resume(&self, response: oneshot::Sender<error::Result<()>>) {
+        let _ = self.sender.send(WorkerMessage::Resume { response });
+    }
+
+    pub fn signal(&self, signal: i32, response: oneshot::Sender<error::Result<()>>) {
+        let _ = self.sender.send(WorkerMessage::Signal { signal, response });
+    }
+
+    pub fn stop(&self, grace: Option<Duration>, response: oneshot::Sender<error::Result<()>>) {
+        let _ = self.sender.send(WorkerMessage::Stop { grace, response });
+    }
+}
+
+/// The processes of a job whose first process is `leader`. A job in a new pid namespace is signalled through the
+/// supervisor that is its direct child.
+fn job_processes(leader: &process::Child, isolation: Isolation) -> JobProcesses {
+    let pid = leader.id().expect("child has not been reaped yet") as libc::pid_t;
+    if isolation.pid {
+        JobProcesses::Supervisor(pid)
+    } else {
+        JobProcesses::Group(pid)
+    }
+}
+
+/// Kill the stages a pipeline that couldn't be spawned in full already started, along with anything they forked, and
+/// remove the cgroup once they have all exited. A cgroup can't be removed while there are processes in it.
+async fn abandon(children: Vec<process::Child>, processes: JobProcesses, cgroup: Option<Cgroup>) {
+    let _ = processes.signal(libc::SIGKILL);
+    for mut child in children {
+        let _ = child.wait().await;
+    }
+    while !processes.all_exited() {
+        time::sleep(actor::DESCENDANTS_POLL_INTERVAL).await;
+    }
+    if let Some(cgroup) = cgroup {
+        let _ = cgroup.remove();
+    }
+}
+
+/// Spawn the configured command, moving the child into `cgroup` and new namespaces, attaching its terminal,
+/// and dropping privileges before it execs.
+///
+/// The child leads a new session, unless it is given a `process_group` to join (0 for a new group it leads).
+///
+/// Returns the child, and the terminal master if the child was given a terminal.
+fn spawn_child(
+    command: &mut process::Command,
+    cgroup: Option<&Cgroup>,
+    isolation: Isolation,
+    tty: Option<WindowSize>,
+    credentials: Option<Credentials>,
+    process_group: Option<libc::pid_t>,
+) -> io::Result<(process::Child, Option<AsyncFd<File>>)> {
+    let master = match tty {
+        Some(size) => {
+            let Pty { master, slave } = Pty::open(size)?;
+            // the terminal is the child's stdin, stdout, and stderr
+            command
+                .stdin(slave.try_clone()?)
+                .stdout(slave.try_clone()?)
+                .stderr(slave);
+            Some(master)
+        }
+        None => {
+            command.stdout(Stdio::piped()).stderr(Stdio::piped());
+            None
+        }
+    };
+
+    // join the cgroup first, so any supervisor processes forked while entering namespaces are limited too
+    if let Some(cgroup) = cgroup {
+        let join_cgroup = cgroup.joiner()?;
+        // SAFETY: the hook only makes a single write(2) call
+        unsafe {
+            command.pre_exec(join_cgroup);
+        }
+    }
+    if !isolation.is_empty() {
+        // SAFETY: the hook only makes raw syscalls and does not allocate
+        unsafe {
+            command.pre_exec(namespaces::isolator(isolation));
+        }
+    }
+    // the job gets its own session after entering namespaces, so that supervisors left behind by a new pid
+    // namespace are not part of it. The terminal is attached to that session.
+    match process_group {
+        // SAFETY: the hook only makes a single setsid(2) call
+        None => unsafe {
+            command.pre_exec(process_group::session_leader());
+        },
+        // SAFETY: the hook only makes a single setpgid(2) call
+        Some(pgid) => unsafe {
+            command.pre_exec(process_group::group_member(pgid));
+        },
+    }
+    if master.is_some() {
+        // SAFETY: the hook only makes raw syscalls and does not allocate
+        unsafe {
+            command.pre_exec(pty::controlling_terminal());
+        }
+    }
+    // privileges are dropped last, joining the cgroup and unsharing namespaces both need them
+    if let Some(credentials) = credentials {
+        let drop_privileges = privileges::dropper(credentials)?;
+        // SAFETY: the hook only makes raw syscalls and does not allocate
+        unsafe {
+            command.pre_exec(drop_privileges);
+        }
     }
+    Ok((command.spawn()?, master))
 }
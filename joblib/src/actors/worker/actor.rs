@@ -1,129 +1,198 @@
 use super::messages::WorkerMessage;
+use super::{Watcher, WorkerEvent};
+use crate::cgroup::Cgroup;
 use crate::error::Error as JobError;
-use crate::events::JobStatus;
-use crate::types::Output;
+use crate::events::{JobStatus, OutputBlob, ResourceUsage, StopReason};
+use crate::process_group::JobProcesses;
+use crate::reaper;
+use crate::types::{Output, StopPolicy};
 
 use bytes::BytesMut;
-use futures::future::FutureExt;
-use std::os::unix::process::ExitStatusExt;
+use futures::future::{self, FutureExt};
+use std::{os::unix::process::ExitStatusExt, process::ExitStatus, time::Duration};
 use tokio::{
-    io::AsyncReadExt,
+    io::{AsyncRead, AsyncReadExt},
     process::Child,
     select,
     sync::{mpsc, oneshot},
+    time::{self, Instant},
 };
 
+/// How often to check whether the processes a job forked have exited, after the job's own process has.
+pub(super) const DESCENDANTS_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 pub struct Actor {
     inbox: mpsc::UnboundedReceiver<WorkerMessage>,
-    kill_tx: Option<oneshot::Sender<()>>,
+    /// taken once the job is told to stop
+    stop_tx: Option<oneshot::Sender<StopRequest>>,
+    stop_policy: StopPolicy,
+    /// when the job runs out of time and is stopped
+    deadline: Option<Instant>,
+    processes: JobProcesses,
     job_status: JobStatus,
+    /// the status of each stage of the job's pipeline that has exited
+    stages: Vec<Option<JobStatus>>,
+    /// told as the job is paused, resumed, and exits
+    watchers: Vec<Watcher>,
+    /// what the job's processes used, once they have all exited
+    resource_usage: Option<ResourceUsage>,
+}
+
+/// Sent to the actor as the job's processes exit.
+enum ProcessEvent {
+    /// a stage of the pipeline exited. The job isn't finished until everything it forked has exited too.
+    StageExited { stage: usize, status: JobStatus },
+    /// every process of the job has exited
+    JobExited(JobStatus, ResourceUsage),
+}
+
+/// A request to stop the child.
+struct StopRequest {
+    policy: StopPolicy,
+    reason: StopReason,
 }
 
 impl Actor {
+    #[allow(clippy::too_many_arguments)]
     pub fn spawn(
         inbox: mpsc::UnboundedReceiver<WorkerMessage>,
         broadcast_tx: mpsc::UnboundedSender<Output>,
-        child: Child,
+        children: Vec<Child>,
+        pipefail: bool,
+        processes: JobProcesses,
+        cgroup: Option<Cgroup>,
+        timeout: Option<Duration>,
+        stop_policy: StopPolicy,
     ) {
-        let (kill_tx, kill_rx) = oneshot::channel();
+        let (stop_tx, stop_rx) = oneshot::channel();
         tokio::spawn(async move {
             let actor = Self {
                 inbox,
-                kill_tx: Some(kill_tx),
+                stop_tx: Some(stop_tx),
+                stop_policy,
+                deadline: timeout.map(|timeout| Instant::now() + timeout),
+                processes,
                 job_status: JobStatus::Running,
+                stages: vec![None; children.len()],
+                watchers: Vec::new(),
+                resource_usage: None,
             };
-            actor.run(broadcast_tx, kill_rx, child).await;
+            actor
+                .run(broadcast_tx, stop_rx, children, pipefail, cgroup)
+                .await;
         });
     }
 
-    pub async fn run(
+    async fn run(
         mut self,
         broadcast_tx: mpsc::UnboundedSender<Output>,
-        kill_rx: oneshot::Receiver<()>,
-        mut child: tokio::process::Child,
+        stop_rx: oneshot::Receiver<StopRequest>,
+        mut children: Vec<Child>,
+        pipefail: bool,
+        cgroup: Option<Cgroup>,
     ) {
-        // set up a channel to report when the child exits to the actor
-        let (child_exit_tx, child_exit_rx) = oneshot::channel();
+        // set up a channel to report to the actor as the job's processes exit
+        let (process_events_tx, process_events_rx) = mpsc::unbounded_channel();
 
-        // grab stdout and stderr, if they've been piped
-        let maybe_stdout = child.stdout.take();
-        let maybe_stderr = child.stderr.take();
+        // grab the last stage's stdout and every stage's stderr, if they've been piped
+        let maybe_stdout = children.last_mut().and_then(|child| child.stdout.take());
+        let stderrs: Vec<_> = children
+            .iter_mut()
+            .filter_map(|child| child.stderr.take())
+            .collect();
+
+        // wait for each stage's pid to finish and cleanup its resources
+        let (stage_exit_tx, mut stage_exit_rx) = mpsc::unbounded_channel();
+        let stage_count = children.len();
+        for (stage, child) in children.into_iter().enumerate() {
+            let stage_exit_tx = stage_exit_tx.clone();
+            tokio::spawn(async move {
+                let (exit_status, usage) = reaper::wait(child).await.expect("child wait: io error"); // TODO: error handling
+                let _ = stage_exit_tx.send((stage, exit_status, usage));
+            });
+        }
+        drop(stage_exit_tx);
 
         // spawn the job
+        let processes = self.processes;
         tokio::spawn(async move {
-            // fuse the kill_rx so it doesnt panic when we select it multiple times
-            let mut kill_rx = kill_rx.fuse();
+            // fuse the stop_rx so it doesnt panic when we select it multiple times
+            let mut stop_rx = stop_rx.fuse();
+            // why the job was told to stop, and when it gets SIGKILLed if it hasn't exited by then
+            let mut stopped = None;
+            let mut kill_at = None;
+            // each stage's status. The job isn't finished until everything it forked has exited too.
+            let mut stage_statuses = vec![None; stage_count];
+            let mut running_stages = stage_count;
+            let mut resource_usage = ResourceUsage::default();
             loop {
                 select! {
-                    // listen for a kill signal
-                    _ = &mut kill_rx => {
-                        let _ = child.kill().await;
-                    }
-                    // wait for child pid to finish and cleanup its resources
-                    exit_status = child.wait() => {
-                        let exit_status = exit_status.expect("child wait: io error"); // TODO: error handling
-                        if let Some(code) = exit_status.code() {
-                            let _ = child_exit_tx.send(JobStatus::Exited { code });
-                        } else if let Some(signal) = exit_status.signal() {
-                            let _ = child_exit_tx.send(JobStatus::Killed { signal });
-                        } else {
-                            unreachable!()
+                    // listen for a stop request
+                    stop = &mut stop_rx, if stopped.is_none() => {
+                        if let Ok(StopRequest { policy, reason }) = stop {
+                            stopped = Some(reason);
+                            kill_at = begin_stop(processes, policy);
                         }
-                        break; // exit select loop
                     }
+                    // the grace period is up
+                    _ = sleep_until(kill_at) => {
+                        let _ = processes.signal(libc::SIGKILL);
+                        kill_at = None;
+                    }
+                    Some((stage, exit_status, usage)) = stage_exit_rx.recv(), if running_stages > 0 => {
+                        let status = job_status(exit_status, stopped);
+                        resource_usage.add(usage);
+                        stage_statuses[stage] = Some(status);
+                        running_stages -= 1;
+                        let _ = process_events_tx.send(ProcessEvent::StageExited { stage, status });
+                    }
+                    // there's no notification when the rest of the job exits, so poll for it
+                    _ = time::sleep(DESCENDANTS_POLL_INTERVAL), if running_stages == 0 => {}
+                }
+                if running_stages == 0 && processes.all_exited() {
+                    break;
                 }
             }
+
+            // every process of the job is gone, so its cgroup is empty and can be removed
+            if let Some(cgroup) = cgroup {
+                let _ = cgroup.remove();
+            }
+            let stage_statuses: Vec<_> = stage_statuses
+                .into_iter()
+                .map(|status| status.expect("stage exited without a status"))
+                .collect();
+            let status = match pipeline_status(&stage_statuses, pipefail) {
+                // the job was told to stop even if the stage it reports exited before that
+                JobStatus::Exited { code, .. } => JobStatus::Exited { code, stopped },
+                JobStatus::Killed { signal, .. } => JobStatus::Killed { signal, stopped },
+                _ => unreachable!(),
+            };
+            let _ = process_events_tx.send(ProcessEvent::JobExited(status, resource_usage));
         });
 
         // pipe stdout to the broadcaster
-        if let Some(mut stdout) = maybe_stdout {
-            let stdout_tx = broadcast_tx.clone();
-            tokio::spawn(async move {
-                let mut buf = BytesMut::with_capacity(4096);
-                loop {
-                    match stdout.read_buf(&mut buf).await {
-                        Ok(n) if n > 0 => {
-                            // move the bytes out of buf and into a message
-                            let msg = Output::Stdout(buf.split().freeze());
-                            let _ = stdout_tx.send(msg);
-                        }
-                        _ => {
-                            break;
-                        }
-                    }
-                }
-            });
+        if let Some(stdout) = maybe_stdout {
+            forward_output(stdout, broadcast_tx.clone(), Output::Stdout);
         }
 
-        // pipe stderr to the broadcaster
-        if let Some(mut stderr) = maybe_stderr {
-            let stderr_tx = broadcast_tx;
-            tokio::spawn(async move {
-                let mut buf = BytesMut::with_capacity(4096);
-                loop {
-                    match stderr.read_buf(&mut buf).await {
-                        Ok(n) if n > 0 => {
-                            // move the bytes out of buf and into a message
-                            let msg = Output::Stderr(buf.split().freeze());
-                            let _ = stderr_tx.send(msg);
-                        }
-                        _ => {
-                            break;
-                        }
-                    }
-                }
-            });
+        // pipe stderr to the broadcaster. Jobs with a terminal have neither pipe, their output comes from the pty.
+        // Either way the actor must not keep a sender alive, or the broadcaster never sees the end of the output.
+        for stderr in stderrs {
+            forward_output(stderr, broadcast_tx.clone(), Output::Stderr);
         }
+        drop(broadcast_tx);
 
         // start listening for messages to the actor
-        self.handle_messages(child_exit_rx).await;
+        self.handle_messages(process_events_rx).await;
     }
 
-    async fn handle_messages(&mut self, child_exit_rx: oneshot::Receiver<JobStatus>) {
+    async fn handle_messages(
+        &mut self,
+        mut process_events_rx: mpsc::UnboundedReceiver<ProcessEvent>,
+    ) {
         use WorkerMessage::*;
 
-        // fuse the child_exit_rx so we can select it in a loop
-        let mut child_exit_rx = child_exit_rx.fuse();
         loop {
             select! {
                 maybe_msg = self.inbox.recv() => {
@@ -132,30 +201,212 @@ impl Actor {
                             GetStatus { response } => {
                                 let _ = response.send(Ok(self.job_status));
                             }
-                            Stop { response } => {
-                                match (self.job_status, self.kill_tx.take()) {
-                                    (JobStatus::Running, Some(kill_tx)) => {
-                                        let _ = kill_tx.send(());
-                                        let _ = response.send(Ok(()));
+                            GetStages { response } => {
+                                // stages that haven't exited are running or paused along with the job
+                                let stages = self
+                                    .stages
+                                    .iter()
+                                    .map(|status| status.unwrap_or(self.job_status))
+                                    .collect();
+                                let _ = response.send(Ok(stages));
+                            }
+                            GetResourceUsage { response } => {
+                                let _ = response.send(self.resource_usage);
+                            }
+                            Watch { watcher } => match self.job_status {
+                                JobStatus::Exited { .. } | JobStatus::Killed { .. } => {
+                                    watcher(WorkerEvent::Exited(self.job_status));
+                                }
+                                _ => self.watchers.push(watcher),
+                            },
+                            Signal { signal, response } => {
+                                let result = if !is_job_signal(signal) {
+                                    Err(JobError::InvalidSignal(signal))
+                                } else {
+                                    match self.job_status {
+                                        JobStatus::Running | JobStatus::Paused => {
+                                            self.processes.signal(signal).map_err(|_| JobError::AlreadyStopped)
+                                        }
+                                        _ => Err(JobError::AlreadyStopped),
+                                    }
+                                };
+                                let _ = response.send(result);
+                            }
+                            Stop { grace, response } => {
+                                let mut policy = self.stop_policy;
+                                if let Some(grace) = grace {
+                                    policy.grace = grace;
+                                }
+                                // a job that ran out of time is already being stopped
+                                let result = if self.stop(policy, StopReason::User) {
+                                    Ok(())
+                                } else {
+                                    Err(JobError::AlreadyStopped)
+                                };
+                                let _ = response.send(result);
+                            }
+                            Pause { response } => {
+                                let result = match self.job_status {
+                                    // a job that is being stopped can't be paused
+                                    _ if self.stop_tx.is_none() => Err(JobError::AlreadyStopped),
+                                    JobStatus::Running => {
+                                        self.processes.pause().map_err(|_| JobError::AlreadyStopped)
                                     }
-                                    _ =>  {
-                                        let _ = response.send(Err(JobError::AlreadyStopped));
+                                    JobStatus::Paused => Err(JobError::AlreadyPaused),
+                                    _ => Err(JobError::AlreadyStopped),
+                                };
+                                if result.is_ok() {
+                                    self.job_status = JobStatus::Paused;
+                                    self.notify(WorkerEvent::Paused);
+                                }
+                                let _ = response.send(result);
+                            }
+                            Resume { response } => {
+                                let result = match self.job_status {
+                                    // stopping already resumed the job
+                                    _ if self.stop_tx.is_none() => Err(JobError::AlreadyStopped),
+                                    JobStatus::Paused => {
+                                        self.processes.resume().map_err(|_| JobError::AlreadyStopped)
                                     }
+                                    JobStatus::Running => Err(JobError::NotPaused),
+                                    _ => Err(JobError::AlreadyStopped),
+                                };
+                                if result.is_ok() {
+                                    self.job_status = JobStatus::Running;
+                                    self.notify(WorkerEvent::Resumed);
                                 }
+                                let _ = response.send(result);
                             }
                         }
                     } else {
                         // actor handle dropped, make sure we kill the child process before we exit
-                        if let Some(kill_tx) = self.kill_tx.take() {
-                            let _ = kill_tx.send(());
-                        }
+                        let kill = StopPolicy {
+                            signal: libc::SIGKILL,
+                            grace: Duration::ZERO,
+                        };
+                        self.stop(kill, StopReason::User);
                         return;
                     }
                 }
-                exit_status = &mut child_exit_rx => {
-                    let _ = exit_status.map(|exit_status| self.job_status = exit_status);
+                // stop the job once it runs out of time
+                _ = sleep_until(self.deadline), if self.stop_tx.is_some() => {
+                    self.stop(self.stop_policy, StopReason::Timeout);
+                }
+                Some(event) = process_events_rx.recv() => match event {
+                    ProcessEvent::StageExited { stage, status } => {
+                        self.stages[stage] = Some(status);
+                    }
+                    ProcessEvent::JobExited(exit_status, resource_usage) => {
+                        self.job_status = exit_status;
+                        self.resource_usage = Some(resource_usage);
+                        self.notify(WorkerEvent::Exited(exit_status));
+                        self.watchers.clear();
+                    }
+                },
+            }
+        }
+    }
+}
+
+impl Actor {
+    fn notify(&self, event: WorkerEvent) {
+        for watcher in &self.watchers {
+            watcher(event);
+        }
+    }
+
+    /// Tell the job to stop, unless it already has been or has exited. Stopping resumes a paused job, so it can act
+    /// on the stop signal.
+    fn stop(&mut self, policy: StopPolicy, reason: StopReason) -> bool {
+        match (self.job_status, self.stop_tx.take()) {
+            (status @ (JobStatus::Running | JobStatus::Paused), Some(stop_tx)) => {
+                let _ = stop_tx.send(StopRequest { policy, reason });
+                self.job_status = JobStatus::Running;
+                if status == JobStatus::Paused {
+                    self.notify(WorkerEvent::Resumed);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Send everything read from `output` to the broadcaster, as the kind of output made by `kind`.
+fn forward_output(
+    mut output: impl AsyncRead + Unpin + Send + 'static,
+    broadcast_tx: mpsc::UnboundedSender<Output>,
+    kind: fn(OutputBlob) -> Output,
+) {
+    tokio::spawn(async move {
+        let mut buf = BytesMut::with_capacity(4096);
+        loop {
+            match output.read_buf(&mut buf).await {
+                Ok(n) if n > 0 => {
+                    // move the bytes out of buf and into a message
+                    let _ = broadcast_tx.send(kind(buf.split().freeze()));
+                }
+                _ => {
+                    break;
                 }
             }
         }
+    });
+}
+
+fn job_status(exit_status: ExitStatus, stopped: Option<StopReason>) -> JobStatus {
+    if let Some(code) = exit_status.code() {
+        JobStatus::Exited { code, stopped }
+    } else if let Some(signal) = exit_status.signal() {
+        JobStatus::Killed { signal, stopped }
+    } else {
+        unreachable!()
+    }
+}
+
+/// The status of a job from the status of each stage of its pipeline: the last stage's, or with `pipefail` the
+/// last stage's that failed, if any did.
+fn pipeline_status(stages: &[JobStatus], pipefail: bool) -> JobStatus {
+    let last = *stages.last().expect("a job has at least one stage");
+    if !pipefail {
+        return last;
+    }
+    stages
+        .iter()
+        .rev()
+        .find(|status| {
+            matches!(status, JobStatus::Killed { .. })
+                || matches!(status, JobStatus::Exited { code, .. } if *code != 0)
+        })
+        .copied()
+        .unwrap_or(last)
+}
+
+/// Whether `signal` can be sent to a job with `signal_job`.
+///
+/// Only the standard signals are supported, a supervisor doesn't forward real-time signals. SIGSTOP and SIGCONT
+/// would bypass the job's status, jobs are paused and resumed with `pause_job` and `resume_job` instead.
+fn is_job_signal(signal: i32) -> bool {
+    (1..32).contains(&signal) && signal != libc::SIGSTOP && signal != libc::SIGCONT
+}
+
+/// Send the job the policy's stop signal. Returns when the job should be SIGKILLed, if it hasn't been already.
+fn begin_stop(processes: JobProcesses, policy: StopPolicy) -> Option<Instant> {
+    if policy.signal == libc::SIGKILL || policy.grace.is_zero() {
+        let _ = processes.signal(libc::SIGKILL);
+        None
+    } else {
+        let _ = processes.signal(policy.signal);
+        // a paused job can't act on the signal until it is continued
+        let _ = processes.resume();
+        Some(Instant::now() + policy.grace)
+    }
+}
+
+/// Sleep until `deadline`, or forever if there is none.
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => time::sleep_until(deadline).await,
+        None => future::pending().await,
     }
 }
@@ -1,12 +1,36 @@
+use super::Watcher;
 use crate::error;
-use crate::events::JobStatus;
+use crate::events::{JobStatus, ResourceUsage};
+use std::time::Duration;
 use tokio::sync::oneshot;
 
 pub enum WorkerMessage {
     GetStatus {
         response: oneshot::Sender<error::Result<JobStatus>>,
     },
+    /// Respond with the status of each stage of the job's pipeline.
+    GetStages {
+        response: oneshot::Sender<error::Result<Vec<JobStatus>>>,
+    },
+    /// Respond with what the job's processes used, once they have all exited.
+    GetResourceUsage {
+        response: oneshot::Sender<Option<ResourceUsage>>,
+    },
+    /// Tell `watcher` about the job's pauses, resumes, and exit, as they happen.
+    Watch { watcher: Watcher },
+    Pause {
+        response: oneshot::Sender<error::Result<()>>,
+    },
+    Resume {
+        response: oneshot::Sender<error::Result<()>>,
+    },
+    Signal {
+        signal: i32,
+        response: oneshot::Sender<error::Result<()>>,
+    },
+    /// Stop the job with its stop policy. `grace` overrides the policy's grace period.
     Stop {
+        grace: Option<Duration>,
         response: oneshot::Sender<error::Result<()>>,
     },
 }
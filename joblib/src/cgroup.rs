@@ -0,0 +1,291 @@
+use crate::types::{IoLimit, JobId, ResourceLimits};
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+/// name of the cgroup that all job cgroups are nested under
+const JOBLIB_CGROUP: &str = "joblib";
+
+/// A dedicated cgroup v2 leaf for one job.
+///
+/// The cgroup is created with its limits already applied, so a child only has to move itself in
+/// before exec for the limits to cover everything it ever runs.
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+impl Cgroup {
+    /// Create the cgroup `<cgroup v2 root>/joblib/<job_id>` and apply `limits` to it.
+    pub fn create(job_id: JobId, limits: &ResourceLimits) -> io::Result<Self> {
+        validate(limits)?;
+        let root = unified_root()?;
+        let parent = root.join(JOBLIB_CGROUP);
+        fs::create_dir_all(&parent)?;
+
+        // controllers have to be enabled in every ancestor's subtree_control before a leaf can use them
+        let controllers = controllers_for(limits);
+        if !controllers.is_empty() {
+            fs::write(root.join("cgroup.subtree_control"), &controllers)?;
+            fs::write(parent.join("cgroup.subtree_control"), &controllers)?;
+        }
+
+        let path = parent.join(job_id.to_string());
+        fs::create_dir(&path)?;
+        let cgroup = Self { path };
+        if let Err(err) = cgroup.apply(limits) {
+            // dont leave a half configured cgroup lying around
+            let _ = cgroup.remove();
+            return Err(err);
+        }
+        Ok(cgroup)
+    }
+
+    /// Get a `pre_exec` hook which moves the calling process into this cgroup.
+    ///
+    /// `cgroup.procs` is opened here, in the parent, because the hook runs between fork and exec
+    /// where allocating or otherwise touching the filesystem layer of std is not safe.
+    pub fn joiner(&self) -> io::Result<impl FnMut() -> io::Result<()> + Send + Sync + 'static> {
+        let procs = OpenOptions::new()
+            .write(true)
+            .open(self.path.join("cgroup.procs"))?;
+        Ok(move || join(&procs))
+    }
+
+    /// Remove the cgroup. Only succeeds once every process in it has been reaped.
+    pub fn remove(self) -> io::Result<()> {
+        fs::remove_dir(&self.path)
+    }
+
+    fn apply(&self, limits: &ResourceLimits) -> io::Result<()> {
+        if let Some(cpu) = limits.cpu {
+            fs::write(
+                self.path.join("cpu.max"),
+                format!("{} {}", cpu.quota_us, cpu.period_us),
+            )?;
+        }
+        if let Some(memory_max) = limits.memory_max {
+            fs::write(self.path.join("memory.max"), memory_max.to_string())?;
+        }
+        for io_limit in &limits.io {
+            if let Some(weight) = io_limit.weight {
+                fs::write(
+                    self.path.join("io.weight"),
+                    format!("{}:{} {}", io_limit.major, io_limit.minor, weight),
+                )?;
+            }
+            if let Some(io_max) = io_max_line(io_limit) {
+                fs::write(self.path.join("io.max"), io_max)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check that `limits` are ones the kernel would take, before anything is created for them.
+pub fn validate(limits: &ResourceLimits) -> io::Result<()> {
+    let invalid = |msg: &str| Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+    if let Some(cpu) = limits.cpu {
+        if cpu.quota_us == 0 {
+            return invalid("cpu quota must be non-zero");
+        }
+        if cpu.period_us == 0 {
+            return invalid("cpu period must be non-zero");
+        }
+    }
+    if limits.io.iter().any(|io_limit| {
+        io_limit
+            .weight
+            .is_some_and(|weight| !(1..=10000).contains(&weight))
+    }) {
+        return invalid("io weight must be in 1..=10000");
+    }
+    Ok(())
+}
+
+/// Find the cgroup v2 mount point.
+///
+/// Pure cgroup v2 hosts mount the unified hierarchy at /sys/fs/cgroup, hybrid hosts at /sys/fs/cgroup/unified.
+fn unified_root() -> io::Result<PathBuf> {
+    ["/sys/fs/cgroup", "/sys/fs/cgroup/unified"]
+        .iter()
+        .map(Path::new)
+        .find(|root| root.join("cgroup.controllers").exists())
+        .map(Path::to_path_buf)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "cgroup v2 hierarchy not mounted"))
+}
+
+/// The `cgroup.subtree_control` line enabling the controllers needed by `limits`.
+fn controllers_for(limits: &ResourceLimits) -> String {
+    let mut controllers = vec![];
+    if limits.cpu.is_some() {
+        controllers.push("+cpu");
+    }
+    if limits.memory_max.is_some() {
+        controllers.push("+memory");
+    }
+    if !limits.io.is_empty() {
+        controllers.push("+io");
+    }
+    controllers.join(" ")
+}
+
+/// The `io.max` line for a device, or None if it has no bandwidth/iops limits.
+fn io_max_line(io_limit: &IoLimit) -> Option<String> {
+    let keys = [
+        ("rbps", io_limit.rbps),
+        ("wbps", io_limit.wbps),
+        ("riops", io_limit.riops),
+        ("wiops", io_limit.wiops),
+    ];
+    let mut line = format!("{}:{}", io_limit.major, io_limit.minor);
+    let mut any = false;
+    for (key, value) in keys {
+        if let Some(value) = value {
+            line.push_str(&format!(" {}={}", key, value));
+            any = true;
+        }
+    }
+    any.then_some(line)
+}
+
+/// Writing "0" to `cgroup.procs` moves the writing process into the cgroup.
+fn join(procs: &File) -> io::Result<()> {
+    let zero = b"0";
+    // SAFETY: write(2) is async-signal-safe and the buffer outlives the call
+    let n = unsafe { libc::write(procs.as_raw_fd(), zero.as_ptr().cast(), zero.len()) };
+    if n < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::CpuMax;
+
+    /// The cgroup v2 root, if this process may create cgroups in it.
+    fn writable_root() -> Option<PathBuf> {
+        let root = unified_root().ok()?;
+        // SAFETY: geteuid(2) can't fail
+        (unsafe { libc::geteuid() } == 0).then_some(root)
+    }
+
+    #[test]
+    fn rejects_invalid_limits() {
+        let cpu = |quota_us, period_us| ResourceLimits {
+            cpu: Some(CpuMax {
+                quota_us,
+                period_us,
+            }),
+            ..Default::default()
+        };
+        for limits in [cpu(0, 100_000), cpu(50_000, 0)] {
+            let err = validate(&limits).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            // nothing is created for them
+            let err = Cgroup::create(uuid::Uuid::new_v4(), &limits).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+        let weight = ResourceLimits {
+            io: vec![IoLimit {
+                weight: Some(0),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(validate(&weight).is_err());
+        assert!(validate(&cpu(50_000, 100_000)).is_ok());
+        assert!(validate(&ResourceLimits::default()).is_ok());
+    }
+
+    #[test]
+    fn limit_lines() {
+        assert_eq!(controllers_for(&ResourceLimits::default()), "");
+        let limits = ResourceLimits {
+            cpu: Some(CpuMax {
+                quota_us: 50_000,
+                period_us: 100_000,
+            }),
+            memory_max: Some(1 << 20),
+            io: vec![IoLimit::default()],
+        };
+        assert_eq!(controllers_for(&limits), "+cpu +memory +io");
+
+        // only the limits that are set make it into io.max
+        let io_limit = IoLimit {
+            major: 8,
+            minor: 16,
+            weight: Some(100),
+            rbps: Some(1024),
+            wiops: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(
+            io_max_line(&io_limit).as_deref(),
+            Some("8:16 rbps=1024 wiops=10")
+        );
+        let weight_only = IoLimit {
+            weight: Some(100),
+            ..Default::default()
+        };
+        assert_eq!(io_max_line(&weight_only), None);
+    }
+
+    #[test]
+    fn create_and_remove() {
+        let root = match writable_root() {
+            Some(root) => root,
+            None => return, // needs root and a cgroup v2 hierarchy
+        };
+        let job_id = uuid::Uuid::new_v4();
+        let cgroup = Cgroup::create(job_id, &ResourceLimits::default()).unwrap();
+        let path = root.join(JOBLIB_CGROUP).join(job_id.to_string());
+        assert!(path.join("cgroup.procs").exists());
+        assert!(cgroup.joiner().is_ok());
+
+        // the same job can't have two cgroups
+        let err = Cgroup::create(job_id, &ResourceLimits::default())
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+        cgroup.remove().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn create_applies_limits() {
+        let root = match writable_root() {
+            Some(root) => root,
+            None => return, // needs root and a cgroup v2 hierarchy
+        };
+        let available = fs::read_to_string(root.join("cgroup.controllers")).unwrap_or_default();
+        if !["cpu", "memory"]
+            .iter()
+            .all(|controller| available.split_whitespace().any(|c| c == *controller))
+        {
+            return; // the host doesn't delegate these controllers to the cgroup v2 hierarchy
+        }
+        let limits = ResourceLimits {
+            cpu: Some(CpuMax {
+                quota_us: 50_000,
+                period_us: 100_000,
+            }),
+            memory_max: Some(64 << 20),
+            io: vec![],
+        };
+        let job_id = uuid::Uuid::new_v4();
+        let cgroup = Cgroup::create(job_id, &limits).unwrap();
+        let read = |file: &str| fs::read_to_string(cgroup.path.join(file)).unwrap();
+        assert_eq!(read("cpu.max").trim(), "50000 100000");
+        assert_eq!(read("memory.max").trim(), (64u64 << 20).to_string());
+        let path = cgroup.path.clone();
+        cgroup.remove().unwrap();
+        assert!(!path.exists());
+    }
+}
@@ -5,8 +5,32 @@ use thiserror;
 pub enum Error {
     #[error("No such job exists")]
     DoesNotExist,
+    #[error("Job hasn't started")]
+    NotStarted,
+    #[error("Job hasn't finished")]
+    NotFinished,
     #[error("Job already stopped")]
     AlreadyStopped,
+    #[error("Job already paused")]
+    AlreadyPaused,
+    #[error("Job is not paused")]
+    NotPaused,
+    #[error("Signal {0} can't be sent to a job")]
+    InvalidSignal(i32),
+    #[error("No such workflow exists")]
+    WorkflowDoesNotExist,
+    #[error("Invalid workflow: {0}")]
+    InvalidWorkflow(String),
+    #[error("No such schedule exists")]
+    ScheduleDoesNotExist,
+    #[error("Invalid schedule: {0}")]
+    InvalidSchedule(String),
+    #[error("Job has no terminal")]
+    NoTerminal,
+    #[error("Job stdin is not a stream")]
+    NoStdinStream,
+    #[error("Job stdin is closed")]
+    StdinClosed,
 }
 
 pub type Result<T> = result::Result<T, Error>;
@@ -1,8 +1,309 @@
-#[derive(Clone, Copy, Debug)]
+use crate::types::{Args, Dir, Envs, Isolation, JobId, Labels, OverlapPolicy, Program, ScheduleId};
+use std::time::{Duration, SystemTime};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum JobStatus {
     Running,
-    Exited { code: i32 },
-    Killed { signal: i32 },
+    /// every process of the job is suspended until it is resumed
+    Paused,
+    /// the job's last attempt ended, and it is waiting out its backoff before it is started again
+    Restarting,
+    /// waiting in the queue for a free slot. The job at position 1 is started next, if no other job is queued
+    /// ahead of it meanwhile.
+    Queued {
+        position: u32,
+    },
+    /// the job was stopped while it was queued, so it never ran
+    Cancelled,
+    /// the job's turn in the queue came, but it couldn't be spawned
+    FailedToStart,
+    /// `stopped` is set if the job was told to stop, even if it went on to exit on its own.
+    Exited {
+        code: i32,
+        stopped: Option<StopReason>,
+    },
+    Killed {
+        signal: i32,
+        stopped: Option<StopReason>,
+    },
+}
+
+impl JobStatus {
+    pub fn state(&self) -> JobState {
+        match self {
+            Self::Running => JobState::Running,
+            Self::Paused => JobState::Paused,
+            Self::Restarting => JobState::Restarting,
+            Self::Queued { .. } => JobState::Queued,
+            Self::Cancelled => JobState::Cancelled,
+            Self::FailedToStart => JobState::FailedToStart,
+            Self::Exited { .. } => JobState::Exited,
+            Self::Killed { .. } => JobState::Killed,
+        }
+    }
+}
+
+/// A `JobStatus` without its details, to filter jobs by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum JobState {
+    Running,
+    Paused,
+    Restarting,
+    Queued,
+    Cancelled,
+    FailedToStart,
+    Exited,
+    Killed,
+}
+
+/// A job as `list_jobs` reports it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JobSummary {
+    pub job_id: JobId,
+    pub owner: Option<String>,
+    pub name: Option<String>,
+    pub labels: Labels,
+    pub status: JobStatus,
+    /// when `start_job` was called for it
+    pub submitted: SystemTime,
+    /// when its first attempt was spawned. `None` while it is queued, or if it never ran.
+    pub started: Option<SystemTime>,
+}
+
+/// A page of the jobs `list_jobs` found, oldest first.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct JobListing {
+    pub jobs: Vec<JobSummary>,
+    /// whether more jobs match after the last one on the page
+    pub more: bool,
+}
+
+/// Everything `get_job_info` knows about a job.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JobInfo {
+    pub job_id: JobId,
+    pub owner: Option<String>,
+    pub name: Option<String>,
+    pub labels: Labels,
+    pub status: JobStatus,
+    pub cmd: Program,
+    pub args: Args,
+    pub dir: Dir,
+    /// the environment variables it runs with, after its env policy
+    pub environment: Envs,
+    /// when `start_job` was called for it
+    pub submitted: SystemTime,
+    /// when its first attempt was spawned. `None` while it is queued, or if it never ran.
+    pub started: Option<SystemTime>,
+    /// when it finished for good
+    pub finished: Option<SystemTime>,
+    /// from when it started until it finished, or until now if it hasn't. `None` if it never ran.
+    pub duration: Option<Duration>,
+    /// the pid of the first process of its latest attempt, which is the supervisor for a job in a new pid namespace
+    pub pid: Option<u32>,
+    /// what the processes of its latest attempt used, once they have all exited
+    pub resource_usage: Option<ResourceUsage>,
+}
+
+/// Everything `get_job_details` reports a job's status with, taken at the same moment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JobDetails {
+    pub status: JobStatus,
+    pub attempts: Attempts,
+    /// the status of each stage of its pipeline, starting with its own command
+    pub stages: Vec<JobStatus>,
+    pub isolation: Isolation,
+    /// the environment variables it runs with, after its env policy
+    pub environment: Envs,
+    pub metadata: JobMetadata,
+}
+
+/// What a job's processes used, as waitid(2) reports it. A pipeline's usage adds up its stages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResourceUsage {
+    pub user_time: Duration,
+    pub system_time: Duration,
+    /// the largest resident set size of any one process, in bytes
+    pub max_rss: u64,
+    /// in 512-byte blocks
+    pub block_reads: u64,
+    pub block_writes: u64,
+}
+
+impl ResourceUsage {
+    pub(crate) fn add(&mut self, other: ResourceUsage) {
+        self.user_time += other.user_time;
+        self.system_time += other.system_time;
+        self.max_rss = self.max_rss.max(other.max_rss);
+        self.block_reads += other.block_reads;
+        self.block_writes += other.block_writes;
+    }
+}
+
+/// Something that happened to a job, as `subscribe_events` reports it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LifecycleEvent {
+    pub job_id: JobId,
+    pub owner: Option<String>,
+    pub kind: LifecycleEventKind,
+    pub time: SystemTime,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LifecycleEventKind {
+    /// an attempt was spawned, starting from 1
+    Started {
+        attempt: u32,
+    },
+    Paused,
+    Resumed,
+    /// an attempt exited. A job that is restarted is started again after this.
+    Exited {
+        code: i32,
+    },
+    /// an attempt was killed by a signal
+    Killed {
+        signal: i32,
+    },
+    /// stopped while it was queued, it never ran
+    Cancelled,
+    /// left the queue, but couldn't be spawned
+    FailedToStart,
+    /// forgotten, by `delete_job` or the retention policy
+    Deleted,
+}
+
+/// The name and labels a job was started with.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct JobMetadata {
+    pub name: Option<String>,
+    pub labels: Labels,
+}
+
+/// Why a job was told to stop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// `stop_job` was called
+    User,
+    /// the job ran past its timeout
+    Timeout,
+}
+
+/// The attempts of a job with a restart policy. A job that is never restarted only has its first attempt.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attempts {
+    /// the current attempt, starting from 1
+    pub attempt: u32,
+    /// how each attempt before the current one ended
+    pub previous: Vec<JobStatus>,
+}
+
+/// The state of a workflow and each of its steps.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WorkflowStatus {
+    pub state: WorkflowState,
+    /// in the order the steps were given
+    pub steps: Vec<StepStatus>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WorkflowState {
+    /// some steps haven't finished yet
+    Running,
+    /// every step exited with code 0
+    Succeeded,
+    /// every step is done, and at least one of them failed or was skipped
+    Failed,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StepStatus {
+    pub name: String,
+    /// assigned when the workflow is submitted, the job doesn't exist until the step starts
+    pub job_id: JobId,
+    pub state: StepState,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepState {
+    /// waiting for the steps it depends on
+    Waiting,
+    /// the step's job is running, or waiting to be restarted
+    Running,
+    /// the step's job finished for good
+    Finished(JobStatus),
+    /// a step it depends on with `after_success` didn't succeed
+    Skipped,
+    /// the step's job couldn't be spawned
+    FailedToStart,
+}
+
+impl StepState {
+    pub(crate) fn is_done(&self) -> bool {
+        matches!(
+            self,
+            Self::Finished(_) | Self::Skipped | Self::FailedToStart
+        )
+    }
+
+    pub(crate) fn succeeded(&self) -> bool {
+        matches!(self, Self::Finished(JobStatus::Exited { code: 0, .. }))
+    }
+}
+
+/// A schedule and the jobs it has started.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScheduleInfo {
+    pub schedule_id: ScheduleId,
+    pub cron: String,
+    pub timezone: String,
+    pub overlap: OverlapPolicy,
+    /// when the schedule ticks next. `None` if it never will again.
+    pub next_tick: Option<SystemTime>,
+    /// the jobs the schedule started most recently, oldest first
+    pub history: Vec<JobId>,
+    /// ticks that didn't start a job, because of the overlap policy or because the job failed to start
+    pub skipped: u32,
+    /// ticks waiting for the running job to finish
+    pub queued: u32,
 }
 
 pub type OutputBlob = bytes::Bytes;
+
+/// Where some of a job's output came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputSource {
+    Stdout,
+    Stderr,
+    /// the marker line where a restarted job's next attempt starts
+    Attempt,
+}
+
+/// Some of a job's output, where it came from, and where it is in the stream it was sent on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutputChunk {
+    /// bytes of the stream before this chunk, from the start of the job's first attempt. Stdout and stderr each count
+    /// their own bytes, and the merged stream counts both. The attempt markers count towards every stream.
+    pub offset: u64,
+    /// the order the job's output was captured in, counting from 0. The same output has the same number in every
+    /// stream, and the part of it a resumed stream starts with keeps it.
+    pub seq: u64,
+    pub source: OutputSource,
+    /// when the output was captured
+    pub timestamp: SystemTime,
+    pub data: OutputBlob,
+}
+
+/// What a subscriber to a job's output receives.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OutputEvent {
+    /// the next bytes of output
+    Data(OutputChunk),
+    /// this many bytes of the start of the output were dropped to keep the job within its output budget. It comes
+    /// first, before the output that was kept.
+    Truncated(u64),
+    /// the subscriber fell behind, and missed this many bytes of output here
+    Gap(u64),
+    /// the subscriber fell too far behind and was dropped. Nothing comes after it.
+    Lagged,
+}
@@ -1,17 +1,46 @@
 mod actors;
+mod cgroup;
 pub mod error;
 pub mod events;
+mod namespaces;
+mod privileges;
+mod process_group;
+mod pty;
+mod reaper;
+mod stdin;
+mod sys;
 pub mod types;
+mod workflow;
 
 // re-export the job coord handle as if it is the job coordinator itself.
 pub use actors::coordinator::JobCoordinatorHandle as JobCoordinator;
+pub use actors::scheduler::JobSchedulerHandle as JobScheduler;
+pub use pty::Terminal;
+pub use stdin::StdinWriter;
 
 #[cfg(test)]
 mod joblib_tests {
     use super::*;
     use crate::error::Error as JobError;
-    use crate::events::JobStatus;
+    use crate::events::{
+        Attempts, JobMetadata, JobState, JobStatus, JobSummary, LifecycleEventKind, OutputChunk,
+        OutputEvent, OutputSource, StepState, StopReason, WorkflowState,
+    };
+    use crate::types::{
+        Backoff, CoordinatorConfig, Credentials, EnvInherit, EnvPolicy, EventFilter, Isolation,
+        JobFilter, JobId, JobPage, JobRegistry, JobSpec, Labels, OverlapPolicy, PipelineStage,
+        QueueLimits, RestartPolicy, RetentionPolicy, ScheduleId, ScheduleSpec,
+        SlowSubscriberPolicy, SpoolConfig, StdinSource, StopPolicy, SubscriberQueue, WindowSize,
+        WorkflowId, WorkflowSpec, WorkflowStep,
+    };
     use futures::future::join_all;
+    use std::{
+        collections::{HashMap, HashSet},
+        sync::{Arc, Mutex},
+        time::{Duration, Instant, SystemTime},
+    };
+    use tokio::sync::mpsc;
+    use uuid::Uuid;
 
     #[tokio::test]
     async fn basic() {
@@ -20,20 +49,20 @@ mod joblib_tests {
         let echo_str = "hello world!";
         let no_trailing_newline = "-n";
         let job_id = coordinator
-            .start_job(
+            .start_job(JobSpec::new(
                 "echo".into(),
                 vec![no_trailing_newline.to_string(), echo_str.to_string()],
                 "/tmp".into(),
                 vec![],
-            )
+            ))
             .await
             .expect("job start err");
         let mut output = coordinator
-            .stream_all(job_id)
+            .stream_all(job_id, 0)
             .await
             .expect("failed to grab stdout/stderr for job");
         let mut output_bytes = vec![];
-        while let Some(blob) = output.recv().await {
+        while let Some(OutputEvent::Data(OutputChunk { data: blob, .. })) = output.recv().await {
             output_bytes.extend(blob);
         }
         assert_eq!(String::from_utf8_lossy(&output_bytes), echo_str);
@@ -47,16 +76,21 @@ mod joblib_tests {
 
         // spawn a long sleep and short sleep
         let long_sleep_id = coordinator
-            .start_job(
+            .start_job(JobSpec::new(
                 sleep_cmd.clone(),
                 vec!["1000".into()],
                 "/tmp".into(),
                 vec![],
-            )
+            ))
             .await
             .expect("start job err");
         let short_sleep_id = coordinator
-            .start_job(sleep_cmd.clone(), vec!["2".into()], "/tmp".into(), vec![])
+            .start_job(JobSpec::new(
+                sleep_cmd.clone(),
+                vec!["2".into()],
+                "/tmp".into(),
+                vec![],
+            ))
             .await
             .expect("start job err");
 
@@ -85,12 +119,19 @@ mod joblib_tests {
             .await
             .expect("job id doesnt exist");
         assert!(matches!(long_sleep_status, JobStatus::Running));
-        assert!(matches!(short_sleep_status, JobStatus::Exited { code: 0 }));
+        assert!(matches!(
+            short_sleep_status,
+            JobStatus::Exited {
+                code: 0,
+                stopped: None
+            }
+        ));
 
         // kill the long sleeping job
         match coordinator.stop_job(long_sleep_id).await {
             Err(JobError::DoesNotExist) => panic!("job coordinator dropped the job"),
             Err(JobError::AlreadyStopped) => panic!("long sleep job exited already"),
+            Err(err) => panic!("unexpected stop error: {}", err),
             Ok(()) => {
                 // give the child process some time to be reaped
                 tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
@@ -100,7 +141,14 @@ mod joblib_tests {
                     .get_job_status(long_sleep_id)
                     .await
                     .expect("job id doesnt exist");
-                assert!(matches!(long_sleep_status, JobStatus::Killed { signal: 9 }));
+                // sleep doesn't handle SIGTERM, so it dies without needing a SIGKILL
+                assert!(matches!(
+                    long_sleep_status,
+                    JobStatus::Killed {
+                        signal: libc::SIGTERM,
+                        stopped: Some(StopReason::User)
+                    }
+                ));
             }
         }
         assert!(matches!(
@@ -109,18 +157,1404 @@ mod joblib_tests {
         ));
     }
 
+    #[tokio::test]
+    async fn job_status_waits_for_descendants() {
+        let coordinator = JobCoordinator::spawn(32);
+
+        // the shell exits right away, but the job isn't finished until its background sleep is
+        let job_id = coordinator
+            .start_job(JobSpec::new(
+                "sh".into(),
+                vec!["-c".into(), "sleep 1 &".into()],
+                "/tmp".into(),
+                vec![],
+            ))
+            .await
+            .expect("job start err");
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(matches!(
+            coordinator.get_job_status(job_id).await,
+            Ok(JobStatus::Running)
+        ));
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+        assert!(matches!(
+            coordinator.get_job_status(job_id).await,
+            Ok(JobStatus::Exited {
+                code: 0,
+                stopped: None
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn stop_kills_process_tree() {
+        let coordinator = JobCoordinator::spawn(32);
+
+        // the orphaned sleep holds on to stdout, so the output only ends once it is killed too
+        let job_id = coordinator
+            .start_job(JobSpec::new(
+                "sh".into(),
+                vec!["-c".into(), "sleep 1000 & echo started".into()],
+                "/tmp".into(),
+                vec![],
+            ))
+            .await
+            .expect("job start err");
+        let mut output = coordinator.stream_stdout(job_id, 0).await.unwrap();
+        assert!(matches!(
+            output.recv().await,
+            Some(OutputEvent::Data(chunk)) if chunk.offset == 0 && chunk.data == "started\n"
+        ));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(matches!(
+            coordinator.get_job_status(job_id).await,
+            Ok(JobStatus::Running)
+        ));
+
+        coordinator.stop_job(job_id).await.expect("stop err");
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while output.recv().await.is_some() {}
+        })
+        .await
+        .expect("output never ended, the orphan survived");
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(matches!(
+            coordinator.get_job_status(job_id).await,
+            Ok(JobStatus::Exited {
+                code: 0,
+                stopped: Some(StopReason::User)
+            })
+        ));
+
+        // same for a job in a new pid namespace, where the signal goes through the namespace's supervisors
+        let mut spec = JobSpec::new(
+            "sh".into(),
+            vec!["-c".into(), "sleep 1000 & echo started; wait".into()],
+            "/tmp".into(),
+            vec![],
+        );
+        spec.isolation.pid = true;
+        let job_id = coordinator.start_job(spec).await.expect("job start err");
+        let mut output = coordinator.stream_stdout(job_id, 0).await.unwrap();
+        assert!(matches!(
+            output.recv().await,
+            Some(OutputEvent::Data(chunk)) if chunk.offset == 0 && chunk.data == "started\n"
+        ));
+        coordinator.stop_job(job_id).await.expect("stop err");
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while output.recv().await.is_some() {}
+        })
+        .await
+        .expect("output never ended, the orphan survived");
+    }
+
+    #[tokio::test]
+    async fn pipeline() {
+        let coordinator = JobCoordinator::spawn(32);
+        let stage = |cmd: &str, args: &[&str]| PipelineStage {
+            cmd: cmd.into(),
+            args: args.iter().map(|&arg| arg.into()).collect(),
+        };
+
+        // only the last stage's stdout is the job's stdout
+        let mut spec = JobSpec::new(
+            "printf".into(),
+            vec!["a\nb\na\n".into()],
+            "/tmp".into(),
+            vec![],
+        );
+        spec.pipeline = vec![stage("grep", &["a"]), stage("wc", &["-l"])];
+        let job_id = coordinator.start_job(spec).await.expect("job start err");
+        let mut stdout = coordinator.stream_stdout(job_id, 0).await.unwrap();
+        let mut received = vec![];
+        while let Some(OutputEvent::Data(OutputChunk { data: blob, .. })) = stdout.recv().await {
+            received.extend_from_slice(&blob);
+        }
+        assert_eq!(String::from_utf8_lossy(&received), "2\n");
+
+        // every stage's stderr is the job's stderr, and pipefail reports the stage that failed
+        for (pipefail, code) in [(false, 0), (true, 3)] {
+            let mut spec = JobSpec::new(
+                "sh".into(),
+                vec!["-c".into(), "echo first >&2; exit 3".into()],
+                "/tmp".into(),
+                vec![],
+            );
+            spec.pipeline = vec![stage("sh", &["-c", "cat; echo last >&2"])];
+            spec.pipefail = pipefail;
+            let job_id = coordinator.start_job(spec).await.expect("job start err");
+            let mut stderr = coordinator.stream_stderr(job_id, 0).await.unwrap();
+            let mut received = vec![];
+            while let Some(OutputEvent::Data(OutputChunk { data: blob, .. })) = stderr.recv().await
+            {
+                received.extend_from_slice(&blob);
+            }
+            assert_eq!(String::from_utf8_lossy(&received), "first\nlast\n");
+
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            let exited = |code| JobStatus::Exited {
+                code,
+                stopped: None,
+            };
+            assert_eq!(
+                coordinator.get_job_status(job_id).await.unwrap(),
+                exited(code)
+            );
+            assert_eq!(
+                coordinator.get_job_stages(job_id).await.unwrap(),
+                vec![exited(3), exited(0)]
+            );
+        }
+
+        // stopping a pipeline stops every stage
+        let mut spec = JobSpec::new("sleep".into(), vec!["1000".into()], "/tmp".into(), vec![]);
+        spec.pipeline = vec![stage("sleep", &["1000"])];
+        let job_id = coordinator.start_job(spec).await.expect("job start err");
+        coordinator.stop_job(job_id).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let killed = JobStatus::Killed {
+            signal: libc::SIGTERM,
+            stopped: Some(StopReason::User),
+        };
+        assert_eq!(coordinator.get_job_status(job_id).await.unwrap(), killed);
+        assert_eq!(
+            coordinator.get_job_stages(job_id).await.unwrap(),
+            vec![killed, killed]
+        );
+        // the details of a job agree with each other
+        let details = coordinator.get_job_details(job_id).await.unwrap();
+        assert_eq!(details.status, killed);
+        assert_eq!(details.stages, vec![killed, killed]);
+        assert_eq!(details.attempts.attempt, 1);
+        assert!(matches!(
+            coordinator.get_job_details(Uuid::new_v4()).await,
+            Err(JobError::DoesNotExist)
+        ));
+    }
+
+    #[tokio::test]
+    async fn pause_and_resume() {
+        let coordinator = JobCoordinator::spawn(32);
+        for pid_namespace in [false, true] {
+            let mut spec = JobSpec::new(
+                "sh".into(),
+                vec![
+                    "-c".into(),
+                    "while true; do echo tick; sleep 0.1; done".into(),
+                ],
+                "/tmp".into(),
+                vec![],
+            );
+            spec.isolation.pid = pid_namespace;
+            let job_id = coordinator.start_job(spec).await.expect("job start err");
+            let mut output = coordinator.stream_stdout(job_id, 0).await.unwrap();
+            output.recv().await.expect("job exited early");
+
+            coordinator.pause_job(job_id).await.expect("pause err");
+            assert!(matches!(
+                coordinator.get_job_status(job_id).await,
+                Ok(JobStatus::Paused)
+            ));
+            assert!(matches!(
+                coordinator.pause_job(job_id).await,
+                Err(JobError::AlreadyPaused)
+            ));
+
+            // nothing is printed while paused, once whatever was in flight is drained
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            while output.try_recv().is_ok() {}
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            assert!(output.try_recv().is_err(), "paused job kept running");
+
+            coordinator.resume_job(job_id).await.expect("resume err");
+            assert!(matches!(
+                coordinator.resume_job(job_id).await,
+                Err(JobError::NotPaused)
+            ));
+            output.recv().await.expect("job didn't resume");
+
+            // a paused job can still be stopped
+            coordinator.pause_job(job_id).await.expect("pause err");
+            coordinator.stop_job(job_id).await.expect("stop err");
+            tokio::time::timeout(Duration::from_secs(5), async {
+                while output.recv().await.is_some() {}
+            })
+            .await
+            .expect("paused job didn't stop");
+        }
+    }
+
+    #[tokio::test]
+    async fn signal_job() {
+        let coordinator = JobCoordinator::spawn(32);
+        for pid_namespace in [false, true] {
+            let mut spec = JobSpec::new(
+                "sh".into(),
+                vec![
+                    "-c".into(),
+                    "trap 'echo usr1; exit 3' USR1; echo ready; while true; do sleep 0.1; done"
+                        .into(),
+                ],
+                "/tmp".into(),
+                vec![],
+            );
+            spec.isolation.pid = pid_namespace;
+            let job_id = coordinator.start_job(spec).await.expect("job start err");
+            let mut output = coordinator.stream_stdout(job_id, 0).await.unwrap();
+            assert!(matches!(
+                output.recv().await.expect("job exited early"),
+                OutputEvent::Data(chunk) if chunk.offset == 0 && chunk.data == "ready\n"
+            ));
+
+            // pausing goes through pause_job, so the job's status stays accurate
+            assert!(matches!(
+                coordinator.signal_job(job_id, libc::SIGSTOP).await,
+                Err(JobError::InvalidSignal(libc::SIGSTOP))
+            ));
+
+            coordinator
+                .signal_job(job_id, libc::SIGUSR1)
+                .await
+                .expect("signal err");
+            let mut received = vec![];
+            while let Some(OutputEvent::Data(OutputChunk { data: blob, .. })) = output.recv().await
+            {
+                received.extend_from_slice(&blob);
+            }
+            assert_eq!(received, b"usr1\n");
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert!(matches!(
+                coordinator.get_job_status(job_id).await,
+                Ok(JobStatus::Exited {
+                    code: 3,
+                    stopped: None
+                })
+            ));
+            assert!(matches!(
+                coordinator.signal_job(job_id, libc::SIGUSR1).await,
+                Err(JobError::AlreadyStopped)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn restart_on_failure() {
+        let coordinator = JobCoordinator::spawn(32);
+        let mut spec = JobSpec::new(
+            "sh".into(),
+            vec!["-c".into(), "echo run; exit 1".into()],
+            "/tmp".into(),
+            vec![],
+        );
+        spec.restart_policy = RestartPolicy::OnFailure { max_attempts: 3 };
+        spec.restart_backoff = Backoff {
+            initial: Duration::from_millis(50),
+            max: Duration::from_millis(100),
+        };
+        let job_id = coordinator.start_job(spec).await.expect("job start err");
+
+        // the output of every attempt is kept, with markers where each restart begins
+        let mut output = coordinator.stream_all(job_id, 0).await.unwrap();
+        let mut received = vec![];
+        while let Some(OutputEvent::Data(OutputChunk { data: blob, .. })) = output.recv().await {
+            received.extend_from_slice(&blob);
+        }
+        assert_eq!(
+            String::from_utf8_lossy(&received),
+            "run\n--- attempt 2 ---\nrun\n--- attempt 3 ---\nrun\n"
+        );
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let failed = JobStatus::Exited {
+            code: 1,
+            stopped: None,
+        };
+        assert_eq!(coordinator.get_job_status(job_id).await.unwrap(), failed);
+        assert_eq!(
+            coordinator.get_job_attempts(job_id).await.unwrap(),
+            Attempts {
+                attempt: 3,
+                previous: vec![failed, failed],
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn stop_ends_restarts() {
+        let coordinator = JobCoordinator::spawn(32);
+        let mut spec = JobSpec::new(
+            "sh".into(),
+            vec!["-c".into(), "echo run".into()],
+            "/tmp".into(),
+            vec![],
+        );
+        spec.restart_policy = RestartPolicy::Always;
+        spec.restart_backoff = Backoff {
+            initial: Duration::from_millis(500),
+            max: Duration::from_millis(500),
+        };
+        let job_id = coordinator.start_job(spec).await.expect("job start err");
+        let mut output = coordinator.stream_all(job_id, 0).await.unwrap();
+        output.recv().await.expect("job didn't run");
+
+        // stopping the job while it waits to be restarted calls off the restart
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(
+            coordinator.get_job_status(job_id).await.unwrap(),
+            JobStatus::Restarting
+        );
+        coordinator.stop_job(job_id).await.expect("stop err");
+        assert!(output.recv().await.is_none());
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(
+            coordinator.get_job_status(job_id).await.unwrap(),
+            JobStatus::Exited {
+                code: 0,
+                stopped: None
+            }
+        );
+        assert_eq!(
+            coordinator.get_job_attempts(job_id).await.unwrap().attempt,
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn workflow() {
+        let coordinator = JobCoordinator::spawn(32);
+        let step =
+            |name: &str, script: &str, after: &[&str], after_success: &[&str]| WorkflowStep {
+                name: name.into(),
+                spec: JobSpec::new(
+                    "sh".into(),
+                    vec!["-c".into(), script.into()],
+                    "/tmp".into(),
+                    vec![],
+                ),
+                after: after.iter().map(|name| name.to_string()).collect(),
+                after_success: after_success.iter().map(|name| name.to_string()).collect(),
+            };
+
+        // a failed build skips everything that needs it to succeed, but the cleanup runs anyway
+        let workflow_id = coordinator
+            .submit_workflow(WorkflowSpec {
+                steps: vec![
+                    step("fetch", "echo fetched", &[], &[]),
+                    step("build", "exit 1", &[], &["fetch"]),
+                    step("test", "true", &[], &["build"]),
+                    step("package", "true", &[], &["test"]),
+                    step("cleanup", "true", &["build"], &[]),
+                ],
+            })
+            .await
+            .expect("workflow submit err");
+        let status = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let status = coordinator.get_workflow_status(workflow_id).await.unwrap();
+                if status.state != WorkflowState::Running {
+                    return status;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("workflow didn't finish");
+        assert_eq!(status.state, WorkflowState::Failed);
+        let exited = |code| {
+            StepState::Finished(JobStatus::Exited {
+                code,
+                stopped: None,
+            })
+        };
+        let states: Vec<_> = status
+            .steps
+            .iter()
+            .map(|step| (step.name.as_str(), step.state))
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                ("fetch", exited(0)),
+                ("build", exited(1)),
+                ("test", StepState::Skipped),
+                ("package", StepState::Skipped),
+                ("cleanup", exited(0)),
+            ]
+        );
+
+        // each step that ran is a job of its own
+        let mut output = coordinator
+            .stream_stdout(status.steps[0].job_id, 0)
+            .await
+            .unwrap();
+        assert!(matches!(
+            output.recv().await,
+            Some(OutputEvent::Data(chunk)) if chunk.offset == 0 && chunk.data == "fetched\n"
+        ));
+        assert!(matches!(
+            coordinator.get_job_status(status.steps[2].job_id).await,
+            Err(JobError::DoesNotExist)
+        ));
+
+        let cycle = coordinator
+            .submit_workflow(WorkflowSpec {
+                steps: vec![
+                    step("a", "true", &["b"], &[]),
+                    step("b", "true", &[], &["a"]),
+                ],
+            })
+            .await;
+        assert!(matches!(cycle, Err(JobError::InvalidWorkflow(_))));
+    }
+
+    #[tokio::test]
+    async fn schedules() {
+        let (registry, _) = Registry::new();
+        let coordinator =
+            JobCoordinator::spawn_with_config(32, CoordinatorConfig::default(), registry.clone());
+        // only the last 2 jobs each schedule started are kept
+        let scheduler = JobScheduler::spawn_with_history(coordinator.clone(), 32, 2);
+        let schedule = |cron: &str, timezone: &str, overlap| {
+            let mut job = JobSpec::new("sleep".into(), vec!["1000".into()], "/tmp".into(), vec![]);
+            job.owner = Some("alice".into());
+            ScheduleSpec {
+                cron: cron.into(),
+                timezone: timezone.into(),
+                job,
+                overlap,
+            }
+        };
+
+        for invalid in [
+            schedule("not cron", "UTC", OverlapPolicy::Skip),
+            schedule("* * * * *", "Mars/Olympus_Mons", OverlapPolicy::Skip),
+        ] {
+            assert!(matches!(
+                scheduler.create_schedule(invalid).await,
+                Err(JobError::InvalidSchedule(_))
+            ));
+        }
+
+        // every second, with seconds as the first field
+        let mut ids = vec![];
+        for (timezone, overlap) in [
+            ("UTC", OverlapPolicy::Skip),
+            ("Europe/Berlin", OverlapPolicy::Queue),
+            ("UTC", OverlapPolicy::Allow),
+        ] {
+            let spec = schedule("* * * * * *", timezone, overlap);
+            ids.push(scheduler.create_schedule(spec).await.unwrap());
+        }
+        tokio::time::sleep(Duration::from_millis(3500)).await;
+
+        let schedules = scheduler.list_schedules().await;
+        let info = |id| {
+            schedules
+                .iter()
+                .find(|info| info.schedule_id == id)
+                .unwrap()
+        };
+        let (skip, queue, allow) = (info(ids[0]), info(ids[1]), info(ids[2]));
+        assert_eq!(skip.history.len(), 1);
+        assert!(skip.skipped >= 1);
+        assert_eq!(queue.history.len(), 1);
+        assert!(queue.queued >= 1);
+        assert_eq!(allow.history.len(), 2);
+        assert!(allow.next_tick.is_some());
+        // a scheduled job's owner is registered before the scheduler sees its id
+        for info in &schedules {
+            for &job_id in &info.history {
+                assert_eq!(registry.owner(job_id), Some(Some("alice".into())));
+            }
+        }
+
+        // a queued tick starts once the running job has finished
+        coordinator.stop_job(queue.history[0]).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let schedules = scheduler.list_schedules().await;
+        let queue = schedules.iter().find(|info| info.schedule_id == ids[1]);
+        assert_eq!(queue.unwrap().history.len(), 2);
+
+        for id in ids {
+            scheduler.delete_schedule(id).await.unwrap();
+        }
+        assert!(scheduler.list_schedules().await.is_empty());
+        // stop the jobs that fell out of the history too
+        let all = coordinator
+            .list_jobs(JobFilter::default(), JobPage::default())
+            .await;
+        for job in all.jobs {
+            let _ = coordinator.stop_job(job.job_id).await;
+        }
+        assert!(matches!(
+            scheduler.delete_schedule(Uuid::new_v4()).await,
+            Err(JobError::ScheduleDoesNotExist)
+        ));
+    }
+
+    #[tokio::test]
+    async fn queue() {
+        let coordinator = JobCoordinator::spawn_with_limits(
+            32,
+            QueueLimits {
+                max_running: Some(1),
+                max_running_per_owner: None,
+            },
+        );
+        // each job appends its name to the log, so the log has the order the jobs ran in
+        let log = std::env::temp_dir().join(format!("joblib-queue-{}", Uuid::new_v4()));
+        let job = |name: &str, owner: &str, priority: i32| {
+            let script = format!("echo {} >> {}", name, log.display());
+            let mut spec = JobSpec::new(
+                "sh".into(),
+                vec!["-c".into(), script],
+                "/tmp".into(),
+                vec![],
+            );
+            spec.owner = Some(owner.into());
+            spec.priority = priority;
+            spec
+        };
+
+        let mut first = JobSpec::new("sleep".into(), vec!["1".into()], "/tmp".into(), vec![]);
+        first.owner = Some("alice".into());
+        let first = coordinator.start_job(first).await.unwrap();
+        assert_eq!(
+            coordinator.get_job_status(first).await.unwrap(),
+            JobStatus::Running
+        );
+
+        let alice_low = coordinator
+            .start_job(job("alice-low", "alice", 0))
+            .await
+            .unwrap();
+        let alice_high = coordinator
+            .start_job(job("alice-high", "alice", 5))
+            .await
+            .unwrap();
+        let bob = coordinator.start_job(job("bob", "bob", 0)).await.unwrap();
+        let cancelled = coordinator
+            .start_job(job("cancelled", "bob", 0))
+            .await
+            .unwrap();
+        // a queued job can't be spawned yet, so it can't fail to spawn yet either
+        let mut missing = job("missing", "alice", -1);
+        missing.cmd = "foo_bar_asdf".into();
+        let missing = coordinator.start_job(missing).await.unwrap();
+
+        // priority goes first, then owners take turns
+        for (job_id, position) in [
+            (alice_high, 1),
+            (bob, 2),
+            (alice_low, 3),
+            (cancelled, 4),
+            (missing, 5),
+        ] {
+            assert_eq!(
+                coordinator.get_job_status(job_id).await.unwrap(),
+                JobStatus::Queued { position }
+            );
+        }
+        assert!(matches!(
+            coordinator.pause_job(bob).await,
+            Err(JobError::NotStarted)
+        ));
+
+        coordinator.stop_job(cancelled).await.unwrap();
+        assert_eq!(
+            coordinator.get_job_status(cancelled).await.unwrap(),
+            JobStatus::Cancelled
+        );
+        assert!(matches!(
+            coordinator.stop_job(cancelled).await,
+            Err(JobError::AlreadyStopped)
+        ));
+        // the cancelled job's output just ends
+        let mut output = coordinator.stream_all(cancelled, 0).await.unwrap();
+        assert!(output.recv().await.is_none());
+
+        assert_eq!(
+            coordinator.wait_for_job(missing).await.unwrap(),
+            JobStatus::FailedToStart
+        );
+        let log_contents = std::fs::read_to_string(&log).unwrap();
+        let _ = std::fs::remove_file(&log);
+        assert_eq!(log_contents, "alice-high\nbob\nalice-low\n");
+    }
+
+    #[tokio::test]
+    async fn env_policy() {
+        let coordinator = JobCoordinator::spawn(32);
+        let env_policy = EnvPolicy {
+            inherit: EnvInherit::Only(vec!["HOME".into()]),
+            overrides: vec![("LANG".into(), "C".into())],
+            denied: vec!["LD_PRELOAD".into()],
+        };
+
+        let mut spec = JobSpec::new(
+            "env".into(),
+            vec![],
+            "/tmp".into(),
+            vec![("LD_PRELOAD".into(), "evil.so".into())],
+        );
+        spec.env_policy = env_policy.clone();
+        let err = coordinator.start_job(spec).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+
+        // the override wins over the variable the spec asks for
+        let mut spec = JobSpec::new(
+            "env".into(),
+            vec![],
+            "/tmp".into(),
+            vec![
+                ("FOO".into(), "bar".into()),
+                ("LANG".into(), "fr_FR".into()),
+            ],
+        );
+        spec.env_policy = env_policy;
+        let job_id = coordinator.start_job(spec).await.unwrap();
+        let mut expected: Vec<(String, String)> = std::env::var("HOME")
+            .map(|home| ("HOME".to_string(), home))
+            .into_iter()
+            .collect();
+        expected.push(("FOO".into(), "bar".into()));
+        expected.push(("LANG".into(), "C".into()));
+        expected.sort();
+        assert_eq!(
+            coordinator.get_job_environment(job_id).await.unwrap(),
+            expected
+        );
+
+        let mut output = coordinator.stream_stdout(job_id, 0).await.unwrap();
+        let mut output_bytes = vec![];
+        while let Some(OutputEvent::Data(OutputChunk { data: blob, .. })) = output.recv().await {
+            output_bytes.extend(blob);
+        }
+        let mut printed: Vec<String> = String::from_utf8_lossy(&output_bytes)
+            .lines()
+            .map(str::to_string)
+            .collect();
+        printed.sort();
+        let expected: Vec<String> = expected
+            .into_iter()
+            .map(|(var, val)| format!("{}={}", var, val))
+            .collect();
+        assert_eq!(printed, expected);
+    }
+
+    #[tokio::test]
+    async fn names_and_labels() {
+        let coordinator = JobCoordinator::spawn(32);
+        let spec = |owner: &str, name: &str| {
+            let mut spec = JobSpec::new("true".into(), vec![], "/tmp".into(), vec![]);
+            spec.owner = Some(owner.to_string());
+            spec.name = Some(name.to_string());
+            spec
+        };
+
+        let mut labeled = spec("alice", "build");
+        labeled.labels = Labels::from([("team".to_string(), "infra".to_string())]);
+        let alice_build = coordinator.start_job(labeled).await.unwrap();
+        assert_eq!(
+            coordinator.get_job_metadata(alice_build).await.unwrap(),
+            JobMetadata {
+                name: Some("build".into()),
+                labels: Labels::from([("team".to_string(), "infra".to_string())]),
+            }
+        );
+
+        // names are unique per owner, even once the job has finished
+        coordinator
+            .stream_stdout(alice_build, 0)
+            .await
+            .unwrap()
+            .recv()
+            .await;
+        let err = coordinator
+            .start_job(spec("alice", "build"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+        let bob_build = coordinator.start_job(spec("bob", "build")).await.unwrap();
+
+        let err = coordinator
+            .start_job(spec("alice", "-build"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+        assert_eq!(
+            coordinator
+                .find_job_by_name(Some("alice".into()), "build".into())
+                .await
+                .unwrap(),
+            alice_build
+        );
+        assert_eq!(
+            coordinator
+                .find_job_by_name(Some("bob".into()), "build".into())
+                .await
+                .unwrap(),
+            bob_build
+        );
+        assert!(matches!(
+            coordinator
+                .find_job_by_name(Some("bob".into()), "test".into())
+                .await,
+            Err(JobError::DoesNotExist)
+        ));
+
+        let alice_id = alice_build.to_hyphenated().to_string();
+        assert_eq!(
+            coordinator.find_jobs_by_id_prefix(alice_id.clone()).await,
+            vec![alice_build]
+        );
+        let mut both = vec![alice_build, bob_build];
+        both.sort();
+        assert_eq!(coordinator.find_jobs_by_id_prefix("".into()).await, both);
+        assert!(coordinator
+            .find_jobs_by_id_prefix(format!("{}0", alice_id))
+            .await
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn list_jobs() {
+        let coordinator = JobCoordinator::spawn_with_limits(
+            32,
+            QueueLimits {
+                max_running: Some(2),
+                max_running_per_owner: None,
+            },
+        );
+        let spec = |owner: &str, cmd: &str, env: &str| {
+            let mut spec = JobSpec::new(cmd.into(), vec![], "/tmp".into(), vec![]);
+            spec.owner = Some(owner.to_string());
+            spec.labels = Labels::from([("env".to_string(), env.to_string())]);
+            spec
+        };
+        let before = SystemTime::now();
+        let done = coordinator
+            .start_job(spec("alice", "true", "prod"))
+            .await
+            .unwrap();
+        coordinator
+            .stream_stdout(done, 0)
+            .await
+            .unwrap()
+            .recv()
+            .await;
+        let between = SystemTime::now();
+        let sleep = |owner, env| {
+            let mut spec = spec(owner, "sleep", env);
+            spec.args = vec!["10".into()];
+            spec
+        };
+        let running = [
+            coordinator.start_job(sleep("bob", "prod")).await.unwrap(),
+            coordinator.start_job(sleep("alice", "dev")).await.unwrap(),
+        ];
+        let queued = coordinator
+            .start_job(spec("alice", "true", "dev"))
+            .await
+            .unwrap();
+
+        let all = coordinator
+            .list_jobs(JobFilter::default(), JobPage::default())
+            .await
+            .jobs;
+        let job_ids: Vec<_> = all.iter().map(|job| job.job_id).collect();
+        // oldest first
+        assert_eq!(job_ids[0], done);
+        assert_eq!(job_ids[3], queued);
+        assert_eq!(
+            all[0].status,
+            JobStatus::Exited {
+                code: 0,
+                stopped: None
+            }
+        );
+        assert_eq!(all[3].status, JobStatus::Queued { position: 1 });
+        assert_eq!(all[3].started, None);
+
+        let list = |filter| {
+            let coordinator = coordinator.clone();
+            async move {
+                let mut job_ids: Vec<_> = coordinator
+                    .list_jobs(filter, JobPage::default())
+                    .await
+                    .jobs
+                    .into_iter()
+                    .map(|job| job.job_id)
+                    .collect();
+                job_ids.sort();
+                job_ids
+            }
+        };
+        let sorted = |mut job_ids: Vec<Uuid>| {
+            job_ids.sort();
+            job_ids
+        };
+        assert_eq!(
+            list(JobFilter {
+                owner: Some("alice".into()),
+                states: vec![JobState::Running, JobState::Queued],
+                ..Default::default()
+            })
+            .await,
+            sorted(vec![running[1], queued])
+        );
+        assert_eq!(
+            list(JobFilter {
+                labels: Labels::from([("env".to_string(), "prod".to_string())]),
+                ..Default::default()
+            })
+            .await,
+            sorted(vec![done, running[0]])
+        );
+        // the queued job hasn't started
+        assert_eq!(
+            list(JobFilter {
+                started_after: Some(between),
+                ..Default::default()
+            })
+            .await,
+            sorted(running.to_vec())
+        );
+        assert_eq!(
+            list(JobFilter {
+                started_after: Some(before),
+                started_before: Some(between),
+                ..Default::default()
+            })
+            .await,
+            vec![done]
+        );
+
+        // pages pick up after the last job of the page before
+        let page = |filter, after: Option<&JobSummary>| {
+            let coordinator = coordinator.clone();
+            let page = JobPage {
+                after: after.map(|job| (job.submitted, job.job_id)),
+                limit: Some(2),
+            };
+            async move { coordinator.list_jobs(filter, page).await }
+        };
+        let first = page(JobFilter::default(), None).await;
+        assert_eq!(first.jobs, all[..2]);
+        assert!(first.more);
+        let second = page(JobFilter::default(), first.jobs.last()).await;
+        assert_eq!(second.jobs.len(), 2);
+        assert_eq!(second.jobs[1].job_id, queued);
+        assert!(!second.more);
+        let running_only = JobFilter {
+            states: vec![JobState::Running],
+            ..Default::default()
+        };
+        let first = page(running_only, Some(&all[0])).await;
+        let job_ids: Vec<_> = first.jobs.iter().map(|job| job.job_id).collect();
+        assert_eq!(job_ids, running);
+        assert!(!first.more);
+
+        // a paused job is listed as paused as soon as pausing it succeeds
+        coordinator.pause_job(running[0]).await.unwrap();
+        assert_eq!(
+            list(JobFilter {
+                states: vec![JobState::Paused],
+                ..Default::default()
+            })
+            .await,
+            vec![running[0]]
+        );
+
+        for job_id in running {
+            coordinator.stop_job(job_id).await.unwrap();
+        }
+    }
+
+    /// Keeps track of a coordinator's jobs and workflows the way its caller would, and sends the id of every job
+    /// it forgets.
+    struct Registry {
+        jobs: Mutex<HashMap<JobId, Option<String>>>,
+        workflows: Mutex<HashSet<WorkflowId>>,
+        schedules: Mutex<HashMap<ScheduleId, Option<String>>>,
+        forgotten_tx: mpsc::UnboundedSender<JobId>,
+    }
+
+    impl Registry {
+        fn new() -> (Arc<Self>, mpsc::UnboundedReceiver<JobId>) {
+            let (forgotten_tx, forgotten_rx) = mpsc::unbounded_channel();
+            let registry = Self {
+                jobs: Mutex::new(HashMap::new()),
+                workflows: Mutex::new(HashSet::new()),
+                schedules: Mutex::new(HashMap::new()),
+                forgotten_tx,
+            };
+            (Arc::new(registry), forgotten_rx)
+        }
+
+        fn owner(&self, job_id: JobId) -> Option<Option<String>> {
+            self.jobs.lock().unwrap().get(&job_id).cloned()
+        }
+    }
+
+    impl JobRegistry for Registry {
+        fn job_added(&self, job_id: JobId, owner: Option<&str>) {
+            let owner = owner.map(str::to_string);
+            self.jobs.lock().unwrap().insert(job_id, owner);
+        }
+
+        fn job_forgotten(&self, job_id: JobId) {
+            self.jobs.lock().unwrap().remove(&job_id);
+            let _ = self.forgotten_tx.send(job_id);
+        }
+
+        fn workflow_added(&self, workflow_id: WorkflowId, _owner: Option<&str>) {
+            self.workflows.lock().unwrap().insert(workflow_id);
+        }
+
+        fn workflow_forgotten(&self, workflow_id: WorkflowId) {
+            self.workflows.lock().unwrap().remove(&workflow_id);
+        }
+
+        fn schedule_added(&self, schedule_id: ScheduleId, owner: Option<&str>) {
+            let owner = owner.map(str::to_string);
+            self.schedules.lock().unwrap().insert(schedule_id, owner);
+        }
+
+        fn schedule_deleted(&self, schedule_id: ScheduleId) {
+            self.schedules.lock().unwrap().remove(&schedule_id);
+        }
+    }
+
+    #[tokio::test]
+    async fn schedule_registry() {
+        let coordinator = JobCoordinator::spawn(32);
+        let (registry, _) = Registry::new();
+        let scheduler = JobScheduler::spawn_with_registry(coordinator, 32, registry.clone());
+        let mut job = JobSpec::new("true".into(), vec![], "/tmp".into(), vec![]);
+        job.owner = Some("alice".into());
+        let spec = ScheduleSpec {
+            cron: "0 0 1 1 *".into(),
+            timezone: "UTC".into(),
+            job,
+            overlap: OverlapPolicy::Skip,
+        };
+
+        // the owner is registered by the time the schedule's id is handed out, and forgotten once it is deleted
+        let schedule_id = scheduler.create_schedule(spec).await.unwrap();
+        assert_eq!(
+            registry.schedules.lock().unwrap().get(&schedule_id),
+            Some(&Some("alice".into()))
+        );
+        scheduler.delete_schedule(schedule_id).await.unwrap();
+        assert!(registry.schedules.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn retention() {
+        let (registry, mut forgotten) = Registry::new();
+        let coordinator = JobCoordinator::spawn_with_config(
+            32,
+            CoordinatorConfig {
+                retention: RetentionPolicy {
+                    max_age: Some(Duration::from_millis(500)),
+                    max_finished_per_owner: Some(2),
+                },
+                ..Default::default()
+            },
+            registry.clone(),
+        );
+        let spec = |owner: &str, cmd: &str, args: &[&str]| {
+            let args = args.iter().map(|arg| arg.to_string()).collect();
+            let mut spec = JobSpec::new(cmd.into(), args, "/tmp".into(), vec![]);
+            spec.owner = Some(owner.to_string());
+            spec
+        };
+
+        // alice's oldest finished job is forgotten once she has a third, bob's jobs don't count
+        let mut finished = vec![];
+        for owner in ["alice", "bob", "alice", "alice"] {
+            let job_id = coordinator
+                .start_job(spec(owner, "true", &[]))
+                .await
+                .unwrap();
+            // the registry knows the job before its id is handed out
+            assert_eq!(registry.owner(job_id), Some(Some(owner.to_string())));
+            coordinator.wait_for_job(job_id).await.unwrap();
+            finished.push(job_id);
+        }
+        assert_eq!(forgotten.recv().await, Some(finished[0]));
+        assert!(matches!(
+            coordinator.get_job_status(finished[0]).await,
+            Err(JobError::DoesNotExist)
+        ));
+        assert!(matches!(
+            coordinator.stream_all(finished[0], 0).await,
+            Err(JobError::DoesNotExist)
+        ));
+        assert_eq!(
+            coordinator
+                .list_jobs(JobFilter::default(), JobPage::default())
+                .await
+                .jobs
+                .len(),
+            3
+        );
+
+        let sleeping = coordinator
+            .start_job(spec("alice", "sleep", &["10"]))
+            .await
+            .unwrap();
+        assert!(matches!(
+            coordinator.delete_job(sleeping).await,
+            Err(JobError::NotFinished)
+        ));
+        assert!(matches!(
+            coordinator.delete_job(Uuid::new_v4()).await,
+            Err(JobError::DoesNotExist)
+        ));
+        coordinator.delete_job(finished[1]).await.unwrap();
+        // and forgets it by the time it is deleted
+        assert_eq!(registry.owner(finished[1]), None);
+        assert_eq!(forgotten.recv().await, Some(finished[1]));
+
+        // a deleted job's name is free again
+        let mut named = spec("alice", "true", &[]);
+        named.name = Some("build".into());
+        let build = coordinator.start_job(named.clone()).await.unwrap();
+        coordinator.wait_for_job(build).await.unwrap();
+        assert_eq!(forgotten.recv().await, Some(finished[2]));
+        coordinator.delete_job(build).await.unwrap();
+        assert_eq!(forgotten.recv().await, Some(build));
+        coordinator.start_job(named).await.unwrap();
+
+        // the rest are forgotten once they have finished
+        coordinator.stop_job(sleeping).await.unwrap();
+        let mut expired = vec![];
+        while expired.len() < 3 {
+            expired.push(forgotten.recv().await.unwrap());
+        }
+        assert!(expired.contains(&sleeping));
+        assert!(coordinator
+            .list_jobs(JobFilter::default(), JobPage::default())
+            .await
+            .jobs
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn workflow_retention() {
+        let (registry, mut forgotten) = Registry::new();
+        let coordinator =
+            JobCoordinator::spawn_with_config(32, CoordinatorConfig::default(), registry.clone());
+        let step = |name: &str, cmd: &str, after_success: &[&str]| {
+            let mut spec = JobSpec::new(cmd.into(), vec![], "/tmp".into(), vec![]);
+            spec.owner = Some("alice".into());
+            WorkflowStep {
+                name: name.into(),
+                spec,
+                after: vec![],
+                after_success: after_success.iter().map(|name| name.to_string()).collect(),
+            }
+        };
+        let spec = WorkflowSpec {
+            steps: vec![
+                step("build", "true", &[]),
+                step("test", "false", &["build"]),
+                step("deploy", "true", &["test"]),
+            ],
+        };
+        let workflow_id = coordinator.submit_workflow(spec).await.unwrap();
+        let steps: Vec<_> = coordinator
+            .get_workflow_status(workflow_id)
+            .await
+            .unwrap()
+            .steps
+            .into_iter()
+            .map(|step| step.job_id)
+            .collect();
+        // every step is known before it starts
+        for &job_id in &steps {
+            assert_eq!(registry.owner(job_id), Some(Some("alice".into())));
+        }
+        assert!(registry.workflows.lock().unwrap().contains(&workflow_id));
+        for &job_id in &steps[..2] {
+            coordinator.wait_for_job(job_id).await.unwrap();
+        }
+
+        // the workflow is kept until the last of the jobs that ran is forgotten
+        coordinator.delete_job(steps[0]).await.unwrap();
+        assert_eq!(forgotten.recv().await, Some(steps[0]));
+        assert!(coordinator.get_workflow_status(workflow_id).await.is_ok());
+        coordinator.delete_job(steps[1]).await.unwrap();
+        assert!(matches!(
+            coordinator.get_workflow_status(workflow_id).await,
+            Err(JobError::WorkflowDoesNotExist)
+        ));
+        // along with the step that was skipped
+        assert!(registry.jobs.lock().unwrap().is_empty());
+        assert!(registry.workflows.lock().unwrap().is_empty());
+        assert_eq!(forgotten.recv().await, Some(steps[1]));
+        assert_eq!(forgotten.recv().await, Some(steps[2]));
+
+        // a workflow none of whose steps ran has no jobs to go with, so it is kept for as long as a job would be
+        let (registry, _) = Registry::new();
+        let coordinator = JobCoordinator::spawn_with_config(
+            32,
+            CoordinatorConfig {
+                retention: RetentionPolicy {
+                    max_age: Some(Duration::from_millis(200)),
+                    max_finished_per_owner: None,
+                },
+                ..Default::default()
+            },
+            registry.clone(),
+        );
+        let spec = WorkflowSpec {
+            steps: vec![
+                step("build", "foo_bar_asdf", &[]),
+                step("test", "true", &["build"]),
+            ],
+        };
+        let workflow_id = coordinator.submit_workflow(spec).await.unwrap();
+        let status = coordinator.get_workflow_status(workflow_id).await.unwrap();
+        assert_eq!(status.state, WorkflowState::Failed);
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert!(matches!(
+            coordinator.get_workflow_status(workflow_id).await,
+            Err(JobError::WorkflowDoesNotExist)
+        ));
+        assert!(registry.jobs.lock().unwrap().is_empty());
+        assert!(registry.workflows.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn lifecycle_events() {
+        let coordinator = JobCoordinator::spawn_with_limits(
+            32,
+            QueueLimits {
+                max_running: Some(1),
+                max_running_per_owner: None,
+            },
+        );
+        let spec = |owner: &str, cmd: &str, args: &[&str]| {
+            let args = args.iter().map(|arg| arg.to_string()).collect();
+            let mut spec = JobSpec::new(cmd.into(), args, "/tmp".into(), vec![]);
+            spec.owner = Some(owner.to_string());
+            spec
+        };
+        let mut alices = coordinator
+            .subscribe_events(EventFilter {
+                job_id: None,
+                owner: Some("alice".into()),
+            })
+            .await;
+        let mut everyones = coordinator.subscribe_events(EventFilter::default()).await;
+
+        let sleeping = coordinator
+            .start_job(spec("alice", "sleep", &["10"]))
+            .await
+            .unwrap();
+        let queued = coordinator
+            .start_job(spec("bob", "true", &[]))
+            .await
+            .unwrap();
+        coordinator.pause_job(sleeping).await.unwrap();
+        coordinator.resume_job(sleeping).await.unwrap();
+        // failed requests aren't events
+        assert!(coordinator.resume_job(sleeping).await.is_err());
+        coordinator.stop_job(queued).await.unwrap();
+        coordinator.stop_job(sleeping).await.unwrap();
+        coordinator.wait_for_job(sleeping).await.unwrap();
+        coordinator.delete_job(sleeping).await.unwrap();
+
+        let mut just_sleeping = coordinator
+            .subscribe_events(EventFilter {
+                job_id: Some(sleeping),
+                owner: None,
+            })
+            .await;
+        let failing = coordinator
+            .start_job(spec("alice", "false", &[]))
+            .await
+            .unwrap();
+        coordinator.wait_for_job(failing).await.unwrap();
+
+        let mut events = vec![];
+        while events.len() < 7 {
+            let event = alices.recv().await.unwrap();
+            assert_eq!(event.owner.as_deref(), Some("alice"));
+            events.push((event.job_id, event.kind));
+        }
+        assert_eq!(
+            events,
+            vec![
+                (sleeping, LifecycleEventKind::Started { attempt: 1 }),
+                (sleeping, LifecycleEventKind::Paused),
+                (sleeping, LifecycleEventKind::Resumed),
+                (sleeping, LifecycleEventKind::Killed { signal: 15 }),
+                (sleeping, LifecycleEventKind::Deleted),
+                (failing, LifecycleEventKind::Started { attempt: 1 }),
+                (failing, LifecycleEventKind::Exited { code: 1 }),
+            ]
+        );
+        let mut events = vec![];
+        while events.len() < 8 {
+            events.push(everyones.recv().await.unwrap().kind);
+        }
+        // bob's job was cancelled in the queue
+        assert!(events.contains(&LifecycleEventKind::Cancelled));
+        assert!(just_sleeping.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn job_info() {
+        let coordinator = JobCoordinator::spawn_with_limits(
+            32,
+            QueueLimits {
+                max_running: Some(1),
+                max_running_per_owner: None,
+            },
+        );
+        let mut spec = JobSpec::new(
+            "sh".into(),
+            vec![
+                "-c".into(),
+                "i=0; while [ $i -lt 100000 ]; do i=$((i+1)); done".into(),
+            ],
+            "/tmp".into(),
+            vec![("GREETING".into(), "hello".into())],
+        );
+        spec.owner = Some("alice".into());
+        spec.name = Some("count".into());
+        let busy = coordinator.start_job(spec).await.unwrap();
+        let queued = coordinator
+            .start_job(JobSpec::new("true".into(), vec![], "/".into(), vec![]))
+            .await
+            .unwrap();
+
+        let info = coordinator.get_job_info(queued).await.unwrap();
+        assert_eq!(info.status, JobStatus::Queued { position: 1 });
+        assert_eq!(info.cmd, "true");
+        assert_eq!(info.dir, "/");
+        assert_eq!(info.started, None);
+        assert_eq!(info.duration, None);
+        assert_eq!(info.pid, None);
+        assert_eq!(info.resource_usage, None);
+
+        let info = coordinator.get_job_info(busy).await.unwrap();
+        assert_eq!(info.status, JobStatus::Running);
+        assert!(info.pid.is_some());
+        assert_eq!(info.resource_usage, None);
+
+        coordinator.wait_for_job(busy).await.unwrap();
+        let info = coordinator.get_job_info(busy).await.unwrap();
+        assert_eq!(info.owner.as_deref(), Some("alice"));
+        assert_eq!(info.name.as_deref(), Some("count"));
+        assert_eq!(info.args[0], "-c");
+        assert!(info
+            .environment
+            .contains(&("GREETING".to_string(), "hello".to_string())));
+        let (started, finished) = (info.started.unwrap(), info.finished.unwrap());
+        assert!(info.submitted <= started && started <= finished);
+        assert_eq!(info.duration, finished.duration_since(started).ok());
+        let usage = info.resource_usage.unwrap();
+        assert!(usage.user_time + usage.system_time > Duration::ZERO);
+        assert!(usage.max_rss > 0);
+
+        assert!(matches!(
+            coordinator.get_job_info(Uuid::new_v4()).await,
+            Err(JobError::DoesNotExist)
+        ));
+    }
+
+    #[tokio::test]
+    async fn timeout_escalates_to_sigkill() {
+        let coordinator = JobCoordinator::spawn(32);
+
+        // the job ignores the stop signal, so it has to be SIGKILLed after the grace period.
+        // sleep is exec'd so that nothing else holds on to the job's output.
+        let mut spec = JobSpec::new(
+            "sh".into(),
+            vec!["-c".into(), "trap '' TERM; exec sleep 10".into()],
+            "/tmp".into(),
+            vec![],
+        );
+        spec.timeout = Some(Duration::from_millis(200));
+        spec.stop_policy = StopPolicy {
+            signal: libc::SIGTERM,
+            grace: Duration::from_millis(300),
+        };
+        let start = Instant::now();
+        let job_id = coordinator.start_job(spec).await.expect("job start err");
+        let mut output = coordinator.stream_all(job_id, 0).await.unwrap();
+
+        // once the timeout has begun stopping the job, it can't be stopped again or paused
+        tokio::time::sleep(Duration::from_millis(350)).await;
+        assert!(matches!(
+            coordinator.stop_job(job_id).await,
+            Err(JobError::AlreadyStopped)
+        ));
+        assert!(matches!(
+            coordinator.pause_job(job_id).await,
+            Err(JobError::AlreadyStopped)
+        ));
+        assert!(matches!(
+            coordinator.resume_job(job_id).await,
+            Err(JobError::AlreadyStopped)
+        ));
+
+        while output.recv().await.is_some() {}
+        assert!(start.elapsed() >= Duration::from_millis(500));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(matches!(
+            coordinator.get_job_status(job_id).await,
+            Ok(JobStatus::Killed {
+                signal: libc::SIGKILL,
+                stopped: Some(StopReason::Timeout)
+            })
+        ));
+
+        // a job that handles the stop signal can exit cleanly within the grace period
+        let spec = JobSpec::new(
+            "sh".into(),
+            vec![
+                "-c".into(),
+                "trap 'exit 3' TERM; sleep 10 >/dev/null & wait".into(),
+            ],
+            "/tmp".into(),
+            vec![],
+        );
+        let job_id = coordinator.start_job(spec).await.expect("job start err");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        coordinator
+            .stop_job_with_grace(job_id, Some(Duration::from_secs(5)))
+            .await
+            .expect("stop err");
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(matches!(
+            coordinator.get_job_status(job_id).await,
+            Ok(JobStatus::Exited {
+                code: 3,
+                stopped: Some(StopReason::User)
+            })
+        ));
+    }
+
     #[tokio::test]
     async fn concurrent_output() {
         let coordinator = JobCoordinator::spawn(32);
         let echo_str = "hello world!";
         let no_trailing_newline = "-n";
         let job_id = coordinator
-            .start_job(
+            .start_job(JobSpec::new(
                 "echo".into(),
                 vec![no_trailing_newline.to_string(), echo_str.to_string()],
                 "/tmp".into(),
                 vec![],
-            )
+            ))
             .await
             .expect("job start err");
         // get output for 3600 clients.
@@ -128,12 +1562,14 @@ mod joblib_tests {
         let mut subscribers = Vec::with_capacity(num_subs);
         for _ in 0..num_subs {
             let mut output = coordinator
-                .stream_all(job_id)
+                .stream_all(job_id, 0)
                 .await
                 .expect("failed to grab stdout/stderr for job");
             subscribers.push(tokio::spawn(async move {
                 let mut output_bytes = vec![];
-                while let Some(blob) = output.recv().await {
+                while let Some(OutputEvent::Data(OutputChunk { data: blob, .. })) =
+                    output.recv().await
+                {
                     output_bytes.extend(blob);
                 }
                 // Each client will wait 1 second.
@@ -159,4 +1595,624 @@ mod joblib_tests {
             }
         }
     }
+
+    #[tokio::test]
+    async fn spooled_output() {
+        let dir = std::env::temp_dir().join(format!("joblib-spool-{}", Uuid::new_v4()));
+        let spool = SpoolConfig {
+            segment_size: 16 << 10,
+            max_job_bytes: None,
+            hot_tail: 1 << 10,
+            ..SpoolConfig::new(&dir)
+        };
+        let (registry, mut forgotten) = Registry::new();
+        let coordinator = JobCoordinator::spawn_with_config(
+            32,
+            CoordinatorConfig {
+                spool: Some(spool.clone()),
+                ..Default::default()
+            },
+            registry.clone(),
+        );
+        let seq =
+            |count: u32| JobSpec::new("seq".into(), vec![count.to_string()], "/tmp".into(), vec![]);
+        let expected: String = (1..=20000).map(|n| format!("{}\n", n)).collect();
+        let read_all = |mut output: tokio::sync::mpsc::Receiver<OutputEvent>| async move {
+            let mut bytes = vec![];
+            while let Some(OutputEvent::Data(OutputChunk { data: blob, .. })) = output.recv().await
+            {
+                bytes.extend(blob);
+            }
+            String::from_utf8(bytes).unwrap()
+        };
+
+        // a subscriber that comes in after the job is done still gets its output from the start, off the disk
+        let job_id = coordinator.start_job(seq(20000)).await.unwrap();
+        let live = coordinator.stream_stdout(job_id, 0).await.unwrap();
+        coordinator.wait_for_job(job_id).await.unwrap();
+        assert_eq!(read_all(live).await, expected);
+        let late = coordinator.stream_all(job_id, 0).await.unwrap();
+        assert_eq!(read_all(late).await, expected);
+        let job_dir = dir.join(job_id.to_hyphenated().to_string());
+        assert!(std::fs::read_dir(&job_dir).unwrap().count() > 1);
+
+        // the spool is removed along with the job
+        coordinator.delete_job(job_id).await.unwrap();
+        assert_eq!(forgotten.recv().await, Some(job_id));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!job_dir.exists());
+
+        // output past the cap isn't kept
+        let coordinator = JobCoordinator::spawn_with_config(
+            32,
+            CoordinatorConfig {
+                spool: Some(SpoolConfig {
+                    max_job_bytes: Some(4 << 10),
+                    ..spool
+                }),
+                ..Default::default()
+            },
+            Arc::new(()),
+        );
+        let job_id = coordinator.start_job(seq(20000)).await.unwrap();
+        coordinator.wait_for_job(job_id).await.unwrap();
+        let late = read_all(coordinator.stream_stdout(job_id, 0).await.unwrap()).await;
+        assert!(!late.is_empty() && late.len() <= 4 << 10);
+        assert!(expected.starts_with(&late));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn output_budget() {
+        let coordinator = JobCoordinator::spawn_with_config(
+            32,
+            CoordinatorConfig {
+                output_budget: Some(1 << 10),
+                ..Default::default()
+            },
+            Arc::new(()),
+        );
+        let job_id = coordinator
+            .start_job(JobSpec::new(
+                "seq".into(),
+                vec!["20000".into()],
+                "/tmp".into(),
+                vec![],
+            ))
+            .await
+            .unwrap();
+        coordinator.wait_for_job(job_id).await.unwrap();
+        let expected: String = (1..=20000).map(|n| format!("{}\n", n)).collect();
+
+        // a late subscriber is told how much was dropped, then gets the rest
+        let mut output = coordinator.stream_stdout(job_id, 0).await.unwrap();
+        let dropped = match output.recv().await {
+            Some(OutputEvent::Truncated(dropped)) => dropped as usize,
+            event => panic!("expected a truncation marker, got {:?}", event),
+        };
+        let mut kept = vec![];
+        while let Some(event) = output.recv().await {
+            match event {
+                OutputEvent::Data(OutputChunk { data: blob, .. }) => kept.extend(blob),
+                event => panic!("expected output, got {:?}", event),
+            }
+        }
+        assert_eq!(kept.len(), 1 << 10);
+        assert_eq!(dropped + kept.len(), expected.len());
+        assert_eq!(&expected.as_bytes()[dropped..], &kept[..]);
+
+        // nothing was dropped from stderr
+        let mut stderr = coordinator.stream_stderr(job_id, 0).await.unwrap();
+        assert_eq!(stderr.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn slow_subscribers() {
+        let dir = std::env::temp_dir().join(format!("joblib-slow-{}", Uuid::new_v4()));
+        let spawn = |slow, spool| {
+            JobCoordinator::spawn_with_config(
+                32,
+                CoordinatorConfig {
+                    spool,
+                    subscriber_queue: SubscriberQueue { capacity: 2, slow },
+                    ..Default::default()
+                },
+                Arc::new(()),
+            )
+        };
+        // the output starts once the subscribers are there
+        let spec = JobSpec::new(
+            "sh".into(),
+            vec!["-c".into(), "sleep 0.3; seq 20000".into()],
+            "/tmp".into(),
+            vec![],
+        );
+        let expected: String = (1..=20000).map(|n| format!("{}\n", n)).collect();
+        let read_all = |mut output: tokio::sync::mpsc::Receiver<OutputEvent>| async move {
+            let mut events = vec![];
+            while let Some(event) = output.recv().await {
+                events.push(event);
+            }
+            events
+        };
+        let data = |events: &[OutputEvent]| {
+            let mut bytes = vec![];
+            for event in events {
+                if let OutputEvent::Data(OutputChunk { data: blob, .. }) = event {
+                    bytes.extend_from_slice(blob);
+                }
+            }
+            bytes
+        };
+
+        // a stalled subscriber catches up from what was kept, in memory or on disk, and the others don't wait for it
+        let spool = SpoolConfig {
+            hot_tail: 1 << 10,
+            ..SpoolConfig::new(&dir)
+        };
+        for spool in [None, Some(spool)] {
+            let coordinator = spawn(SlowSubscriberPolicy::CatchUp, spool);
+            let job_id = coordinator.start_job(spec.clone()).await.unwrap();
+            let stalled = coordinator.stream_all(job_id, 0).await.unwrap();
+            let reading = tokio::spawn(read_all(coordinator.stream_all(job_id, 0).await.unwrap()));
+            coordinator.wait_for_job(job_id).await.unwrap();
+            assert_eq!(data(&reading.await.unwrap()), expected.as_bytes());
+            let events = read_all(stalled).await;
+            assert!(events
+                .iter()
+                .all(|event| matches!(event, OutputEvent::Data(_))));
+            assert_eq!(data(&events), expected.as_bytes());
+        }
+
+        // a stalled subscriber that skips ahead is told how much it missed
+        let coordinator = spawn(SlowSubscriberPolicy::Skip, None);
+        let job_id = coordinator.start_job(spec.clone()).await.unwrap();
+        let stalled = coordinator.stream_all(job_id, 0).await.unwrap();
+        coordinator.wait_for_job(job_id).await.unwrap();
+        let events = read_all(stalled).await;
+        let skipped: u64 = events
+            .iter()
+            .map(|event| match event {
+                OutputEvent::Gap(skipped) => *skipped,
+                _ => 0,
+            })
+            .sum();
+        assert!(skipped > 0);
+        assert_eq!(data(&events).len() as u64 + skipped, expected.len() as u64);
+        assert!(expected.as_bytes().starts_with(&data(&events[..2])));
+
+        // a stalled subscriber that's disconnected gets what it had room for, then an error
+        let coordinator = spawn(SlowSubscriberPolicy::Disconnect, None);
+        let job_id = coordinator.start_job(spec).await.unwrap();
+        let stalled = coordinator.stream_all(job_id, 0).await.unwrap();
+        coordinator.wait_for_job(job_id).await.unwrap();
+        let events = read_all(stalled).await;
+        assert_eq!(events.len(), 3);
+        assert_eq!(events.last(), Some(&OutputEvent::Lagged));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn resume_output() {
+        let dir = std::env::temp_dir().join(format!("joblib-spool-{}", Uuid::new_v4()));
+        let read_chunks = |mut output: tokio::sync::mpsc::Receiver<OutputEvent>| async move {
+            let mut chunks = vec![];
+            while let Some(event) = output.recv().await {
+                match event {
+                    OutputEvent::Data(chunk) => chunks.push(chunk),
+                    event => panic!("unexpected output event: {:?}", event),
+                }
+            }
+            chunks
+        };
+        // each chunk starts where the one before it ended
+        let join = |offset: u64, chunks: Vec<OutputChunk>| {
+            let mut bytes = vec![];
+            for chunk in chunks {
+                assert_eq!(chunk.offset, offset + bytes.len() as u64);
+                bytes.extend(chunk.data);
+            }
+            String::from_utf8(bytes).unwrap()
+        };
+        let lines = |count: u32| (1..=count).map(|n| format!("{}\n", n)).collect::<String>();
+        let stdout = format!("{}--- attempt 2 ---\n{}", lines(2000), lines(2000));
+        let stderr = format!("{}--- attempt 2 ---\n{}", lines(1000), lines(1000));
+
+        let spool = SpoolConfig {
+            segment_size: 4 << 10,
+            max_job_bytes: None,
+            hot_tail: 1 << 10,
+            ..SpoolConfig::new(&dir)
+        };
+        for spool in [None, Some(spool)] {
+            let coordinator = JobCoordinator::spawn_with_config(
+                32,
+                CoordinatorConfig {
+                    spool,
+                    ..Default::default()
+                },
+                Arc::new(()),
+            );
+            let mut spec = JobSpec::new(
+                "sh".into(),
+                vec!["-c".into(), "seq 2000; seq 1000 >&2; exit 1".into()],
+                "/tmp".into(),
+                vec![],
+            );
+            spec.restart_policy = RestartPolicy::OnFailure { max_attempts: 2 };
+            spec.restart_backoff = Backoff {
+                initial: Duration::from_millis(50),
+                max: Duration::from_millis(50),
+            };
+            let job_id = coordinator.start_job(spec).await.unwrap();
+            coordinator.wait_for_job(job_id).await.unwrap();
+
+            // each stream picks up from any byte of it, the attempt markers included
+            for offset in [0, 1, 4000, 8893, 8900, stdout.len() - 1, stdout.len() + 10] {
+                let chunks = read_chunks(
+                    coordinator
+                        .stream_stdout(job_id, offset as u64)
+                        .await
+                        .unwrap(),
+                )
+                .await;
+                assert_eq!(
+                    join(offset as u64, chunks),
+                    stdout.get(offset..).unwrap_or("")
+                );
+            }
+            for offset in [0, 3000, stderr.len() - 1] {
+                let chunks = read_chunks(
+                    coordinator
+                        .stream_stderr(job_id, offset as u64)
+                        .await
+                        .unwrap(),
+                )
+                .await;
+                assert_eq!(join(offset as u64, chunks), stderr[offset..]);
+            }
+
+            // the merged stream counts both, with each marker once
+            let all = join(
+                0,
+                read_chunks(coordinator.stream_all(job_id, 0).await.unwrap()).await,
+            );
+            assert_eq!(
+                all.len(),
+                stdout.len() + stderr.len() - "--- attempt 2 ---\n".len()
+            );
+            for offset in [1, 5000, 10000, all.len() - 1] {
+                let chunks =
+                    read_chunks(coordinator.stream_all(job_id, offset as u64).await.unwrap()).await;
+                assert_eq!(join(offset as u64, chunks), all[offset..]);
+            }
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+
+        // output that was dropped from memory is reported as a gap
+        let coordinator = JobCoordinator::spawn_with_config(
+            32,
+            CoordinatorConfig {
+                output_budget: Some(1 << 10),
+                ..Default::default()
+            },
+            Arc::new(()),
+        );
+        let job_id = coordinator
+            .start_job(JobSpec::new(
+                "seq".into(),
+                vec!["2000".into()],
+                "/tmp".into(),
+                vec![],
+            ))
+            .await
+            .unwrap();
+        coordinator.wait_for_job(job_id).await.unwrap();
+        let mut output = coordinator.stream_stdout(job_id, 100).await.unwrap();
+        let kept = lines(2000).len() - (1 << 10);
+        assert_eq!(
+            output.recv().await,
+            Some(OutputEvent::Gap(kept as u64 - 100))
+        );
+        match output.recv().await {
+            Some(OutputEvent::Data(chunk)) => assert_eq!(chunk.offset, kept as u64),
+            event => panic!("unexpected output event: {:?}", event),
+        }
+    }
+
+    #[tokio::test]
+    async fn tagged_output() {
+        let dir = std::env::temp_dir().join(format!("joblib-spool-{}", Uuid::new_v4()));
+        let read_chunks = |mut output: tokio::sync::mpsc::Receiver<OutputEvent>| async move {
+            let mut chunks = vec![];
+            while let Some(event) = output.recv().await {
+                match event {
+                    OutputEvent::Data(chunk) => chunks.push(chunk),
+                    event => panic!("unexpected output event: {:?}", event),
+                }
+            }
+            chunks
+        };
+        // the spool keeps timestamps to the microsecond
+        let micros = |chunk: &OutputChunk| {
+            chunk
+                .timestamp
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_micros()
+        };
+
+        let spool = SpoolConfig {
+            segment_size: 64,
+            max_job_bytes: None,
+            hot_tail: 8,
+            ..SpoolConfig::new(&dir)
+        };
+        for spool in [None, Some(spool)] {
+            let coordinator = JobCoordinator::spawn_with_config(
+                32,
+                CoordinatorConfig {
+                    spool,
+                    ..Default::default()
+                },
+                Arc::new(()),
+            );
+            let mut spec = JobSpec::new(
+                "sh".into(),
+                vec![
+                    "-c".into(),
+                    "echo out; sleep 0.1; echo err >&2; sleep 0.1; echo out; exit 1".into(),
+                ],
+                "/tmp".into(),
+                vec![],
+            );
+            spec.restart_policy = RestartPolicy::OnFailure { max_attempts: 2 };
+            spec.restart_backoff = Backoff {
+                initial: Duration::from_millis(50),
+                max: Duration::from_millis(50),
+            };
+            let started = SystemTime::now();
+            let job_id = coordinator.start_job(spec).await.unwrap();
+            let live = coordinator.stream_all(job_id, 0).await.unwrap();
+            coordinator.wait_for_job(job_id).await.unwrap();
+            let live = read_chunks(live).await;
+
+            // the merged stream says where each chunk came from, in the order it was captured
+            use OutputSource::*;
+            assert_eq!(
+                live.iter().map(|chunk| chunk.source).collect::<Vec<_>>(),
+                [Stdout, Stderr, Stdout, Attempt, Stdout, Stderr, Stdout]
+            );
+            assert!(live.iter().map(|chunk| chunk.seq).eq(0..7));
+            assert!(live[0].timestamp >= started);
+            assert!(live
+                .windows(2)
+                .all(|pair| pair[0].timestamp <= pair[1].timestamp));
+            assert!(live[1].timestamp >= live[0].timestamp + Duration::from_millis(50));
+
+            // a later subscriber gets the same tags, and so does a single stream
+            let late = read_chunks(coordinator.stream_all(job_id, 0).await.unwrap()).await;
+            assert_eq!(late.len(), live.len());
+            for (late, live) in late.iter().zip(&live) {
+                assert_eq!((late.seq, late.source), (live.seq, live.source));
+                assert_eq!(micros(late), micros(live));
+            }
+            let stderr = read_chunks(coordinator.stream_stderr(job_id, 0).await.unwrap()).await;
+            assert_eq!(
+                stderr
+                    .iter()
+                    .map(|chunk| (chunk.seq, chunk.source))
+                    .collect::<Vec<_>>(),
+                [(1, Stderr), (3, Attempt), (5, Stderr)]
+            );
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn isolated_job() {
+        let coordinator = JobCoordinator::spawn(32);
+        let mut spec = JobSpec::new(
+            "sh".into(),
+            // the job is pid 2 (under a minimal init), and only sees the loopback interface
+            vec![
+                "-c".into(),
+                "echo $$; tail -n +3 /proc/net/dev | cut -d: -f1".into(),
+            ],
+            "/tmp".into(),
+            vec![],
+        );
+        let isolation = Isolation {
+            pid: true,
+            mount: true,
+            network: true,
+            uts: true,
+            ipc: true,
+        };
+        spec.isolation = isolation;
+        let job_id = coordinator.start_job(spec).await.expect("job start err");
+        let mut output = coordinator
+            .stream_all(job_id, 0)
+            .await
+            .expect("failed to grab stdout/stderr for job");
+        let mut output_bytes = vec![];
+        while let Some(OutputEvent::Data(OutputChunk { data: blob, .. })) = output.recv().await {
+            output_bytes.extend(blob);
+        }
+        let output = String::from_utf8_lossy(&output_bytes);
+        let lines: Vec<_> = output.lines().map(str::trim).collect();
+        assert_eq!(lines, vec!["2", "lo"]);
+        assert_eq!(
+            coordinator.get_job_isolation(job_id).await.unwrap(),
+            isolation
+        );
+    }
+
+    #[tokio::test]
+    async fn refuses_root() {
+        let coordinator = JobCoordinator::spawn(32);
+        let mut spec = JobSpec::new("true".into(), vec![], "/tmp".into(), vec![]);
+        spec.credentials = Some(Credentials {
+            uid: 0,
+            gid: 0,
+            groups: vec![],
+            allow_root: false,
+        });
+        let err = coordinator
+            .start_job(spec.clone())
+            .await
+            .expect_err("job started as root");
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+
+        // unless root is explicitly allowed
+        if let Some(credentials) = spec.credentials.as_mut() {
+            credentials.allow_root = true;
+        }
+        coordinator.start_job(spec).await.expect("job start err");
+    }
+
+    #[tokio::test]
+    async fn drops_privileges() {
+        if !Credentials::can_switch().unwrap() {
+            return; // only a privileged process can run jobs as someone else
+        }
+        let coordinator = JobCoordinator::spawn(32);
+        let mut spec = JobSpec::new(
+            "sh".into(),
+            vec!["-c".into(), "id -u; id -g; id -G".into()],
+            "/tmp".into(),
+            vec![],
+        );
+        spec.credentials = Some(Credentials {
+            uid: 54321,
+            gid: 54322,
+            groups: vec![54322, 54323],
+            allow_root: false,
+        });
+        let job_id = coordinator.start_job(spec).await.expect("job start err");
+        let mut output = coordinator
+            .stream_stdout(job_id, 0)
+            .await
+            .expect("failed to grab output for job");
+        let mut output_bytes = vec![];
+        while let Some(OutputEvent::Data(OutputChunk { data: blob, .. })) = output.recv().await {
+            output_bytes.extend(blob);
+        }
+        let output = String::from_utf8_lossy(&output_bytes);
+        let lines: Vec<_> = output.lines().map(str::trim).collect();
+        assert_eq!(lines, vec!["54321", "54322", "54322 54323"]);
+    }
+
+    #[tokio::test]
+    async fn tty_job() {
+        let coordinator = JobCoordinator::spawn(32);
+        let mut spec = JobSpec::new(
+            "sh".into(),
+            vec![
+                "-c".into(),
+                "stty size; read line; stty size; echo \"got $line\"".into(),
+            ],
+            "/tmp".into(),
+            vec![],
+        );
+        spec.tty = Some(WindowSize { rows: 24, cols: 80 });
+        let job_id = coordinator.start_job(spec).await.expect("job start err");
+        let terminal = coordinator
+            .get_job_terminal(job_id)
+            .await
+            .expect("job has no terminal");
+        let mut output = coordinator
+            .stream_stdout(job_id, 0)
+            .await
+            .expect("failed to grab terminal output for job");
+
+        // wait for the first `stty size` before resizing
+        let mut output_bytes = vec![];
+        while !String::from_utf8_lossy(&output_bytes).contains("24 80") {
+            match output.recv().await {
+                Some(OutputEvent::Data(OutputChunk { data: blob, .. })) => {
+                    output_bytes.extend(blob)
+                }
+                _ => panic!("job exited early"),
+            }
+        }
+        terminal
+            .resize(WindowSize {
+                rows: 50,
+                cols: 132,
+            })
+            .unwrap();
+        terminal.write("hello\n".into()).unwrap();
+        while let Some(OutputEvent::Data(OutputChunk { data: blob, .. })) = output.recv().await {
+            output_bytes.extend(blob);
+        }
+        let output = String::from_utf8_lossy(&output_bytes);
+        assert!(output.contains("50 132"), "{:?}", output);
+        assert!(output.contains("got hello"), "{:?}", output);
+
+        // jobs without a tty have no terminal
+        let job_id = coordinator
+            .start_job(JobSpec::new("true".into(), vec![], "/tmp".into(), vec![]))
+            .await
+            .expect("job start err");
+        assert!(matches!(
+            coordinator.get_job_terminal(job_id).await,
+            Err(JobError::NoTerminal)
+        ));
+    }
+
+    #[tokio::test]
+    async fn stdin_job() {
+        let coordinator = JobCoordinator::spawn(32);
+
+        // a fixed payload is followed by EOF
+        let mut spec = JobSpec::new("cat".into(), vec![], "/tmp".into(), vec![]);
+        spec.stdin = StdinSource::Bytes("fixed payload".into());
+        let job_id = coordinator.start_job(spec).await.expect("job start err");
+        let mut output = coordinator
+            .stream_stdout(job_id, 0)
+            .await
+            .expect("failed to grab stdout for job");
+        let mut output_bytes = vec![];
+        while let Some(OutputEvent::Data(OutputChunk { data: blob, .. })) = output.recv().await {
+            output_bytes.extend(blob);
+        }
+        assert_eq!(output_bytes, b"fixed payload");
+
+        // a stream stays open until it is explicitly closed
+        let mut spec = JobSpec::new("cat".into(), vec![], "/tmp".into(), vec![]);
+        spec.stdin = StdinSource::Stream;
+        let job_id = coordinator.start_job(spec).await.expect("job start err");
+        let stdin = coordinator
+            .get_job_stdin(job_id)
+            .await
+            .expect("job has no stdin stream");
+        let mut output = coordinator
+            .stream_stdout(job_id, 0)
+            .await
+            .expect("failed to grab stdout for job");
+        stdin.write("hello ".into()).unwrap();
+        stdin.write("world".into()).unwrap();
+        stdin.close().unwrap();
+        let mut output_bytes = vec![];
+        while let Some(OutputEvent::Data(OutputChunk { data: blob, .. })) = output.recv().await {
+            output_bytes.extend(blob);
+        }
+        assert_eq!(output_bytes, b"hello world");
+
+        // stdin defaults to /dev/null, and only stream stdin gets a writer
+        let job_id = coordinator
+            .start_job(JobSpec::new("cat".into(), vec![], "/tmp".into(), vec![]))
+            .await
+            .expect("job start err");
+        assert!(matches!(
+            coordinator.get_job_stdin(job_id).await,
+            Err(JobError::NoStdinStream)
+        ));
+        let mut output = coordinator
+            .stream_stdout(job_id, 0)
+            .await
+            .expect("failed to grab stdout for job");
+        assert!(output.recv().await.is_none());
+    }
 }
@@ -0,0 +1,213 @@
+use crate::sys::check;
+use crate::types::Isolation;
+use std::{
+    io, mem, ptr,
+    sync::atomic::{AtomicI32, Ordering},
+};
+
+/// pid of the process, or process group, that a supervising process forwards signals to.
+static FORWARD_PID: AtomicI32 = AtomicI32::new(0);
+
+/// Signal a supervisor translates into pausing every process in its pid namespace.
+///
+/// SIGSTOP can't be forwarded like other signals, it would stop the supervisor instead of being handled.
+pub fn pause_signal() -> libc::c_int {
+    libc::SIGRTMIN()
+}
+
+/// Signal a supervisor translates into resuming every process in its pid namespace.
+pub fn resume_signal() -> libc::c_int {
+    libc::SIGRTMIN() + 1
+}
+
+/// Get a `pre_exec` hook which moves the calling process into the namespaces requested by `isolation`.
+///
+/// Everything in the hook runs between fork and exec, so it sticks to raw syscalls and never allocates.
+pub fn isolator(isolation: Isolation) -> impl FnMut() -> io::Result<()> + Send + Sync + 'static {
+    move || isolate(isolation)
+}
+
+fn isolate(isolation: Isolation) -> io::Result<()> {
+    let mount = isolation.mount || isolation.pid; // a private /proc needs a private mount table
+    let mut flags = 0;
+    if isolation.pid {
+        flags |= libc::CLONE_NEWPID;
+    }
+    if mount {
+        flags |= libc::CLONE_NEWNS;
+    }
+    if isolation.network {
+        flags |= libc::CLONE_NEWNET;
+    }
+    if isolation.uts {
+        flags |= libc::CLONE_NEWUTS;
+    }
+    if isolation.ipc {
+        flags |= libc::CLONE_NEWIPC;
+    }
+    check(unsafe { libc::unshare(flags) })?;
+
+    if mount {
+        // stop mounts made by the job from propagating back to the host
+        check(unsafe {
+            libc::mount(
+                ptr::null(),
+                c"/".as_ptr(),
+                ptr::null(),
+                libc::MS_REC | libc::MS_PRIVATE,
+                ptr::null(),
+            )
+        })?;
+    }
+    if isolation.network {
+        loopback_up()?;
+    }
+    if isolation.pid {
+        // unshare(CLONE_NEWPID) only applies to children of the caller. This process stays behind in the host
+        // namespace as a supervisor, and its child becomes pid 1 of the new namespace.
+        fork_supervised()?;
+
+        // pid 1 ignores signals it has no handler for, so it can't be the job itself - otherwise a SIGTERM
+        // would do nothing. It acts as a minimal init instead, and the job runs as pid 2.
+        mount_proc()?;
+        fork_supervised()?;
+    }
+    Ok(())
+}
+
+/// Fork. The parent supervises the child until it exits, and never returns. The child returns `Ok`.
+///
+/// The child is SIGKILLed if the parent dies, so killing the outermost process takes the whole chain with it.
+fn fork_supervised() -> io::Result<()> {
+    let parent = unsafe { libc::getpid() };
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => {
+            check(unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) })?;
+            // a parent outside of our pid namespace shows up as pid 0
+            let ppid = unsafe { libc::getppid() };
+            if ppid != parent && ppid != 0 {
+                // parent died before the death signal was armed
+                unsafe { libc::_exit(1) };
+            }
+            Ok(())
+        }
+        child => supervise(child),
+    }
+}
+
+/// Forward signals to `child`, reap every process that exits, and exit the same way `child` does.
+fn supervise(child: libc::pid_t) -> ! {
+    FORWARD_PID.store(child, Ordering::SeqCst);
+    // handlers go in before the fds are closed. `Command::spawn` returns once every copy of its exec pipe is
+    // closed, and from then on the job can be paused.
+    for signal in (1..32).chain([pause_signal(), resume_signal()]) {
+        if matches!(signal, libc::SIGKILL | libc::SIGSTOP | libc::SIGCHLD) {
+            continue;
+        }
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction =
+                forward_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigaction(signal, &action, ptr::null_mut());
+        }
+    }
+    close_inherited_fds();
+
+    let mut status = 0;
+    loop {
+        // as pid 1 of a namespace, orphans get re-parented to us, so reap any child, not just ours
+        let pid = unsafe { libc::waitpid(-1, &mut status, 0) };
+        if pid == child {
+            break;
+        }
+        if pid == -1 && io::Error::last_os_error().raw_os_error() != Some(libc::EINTR) {
+            unsafe { libc::_exit(1) };
+        }
+    }
+
+    unsafe {
+        if libc::WIFSIGNALED(status) {
+            // die by the same signal. pid 1 of a namespace can't be killed like this, so fall through to the
+            // shell convention of 128 + signal.
+            let signal = libc::WTERMSIG(status);
+            libc::signal(signal, libc::SIG_DFL);
+            libc::kill(libc::getpid(), signal);
+            libc::_exit(128 + signal);
+        }
+        libc::_exit(libc::WEXITSTATUS(status));
+    }
+}
+
+extern "C" fn forward_signal(signal: libc::c_int) {
+    // pid 1 pauses and resumes its whole namespace. kill(-1) reaches every process in it but the caller.
+    if unsafe { libc::getpid() } == 1 {
+        if signal == pause_signal() {
+            unsafe { libc::kill(-1, libc::SIGSTOP) };
+            return;
+        }
+        if signal == resume_signal() {
+            unsafe { libc::kill(-1, libc::SIGCONT) };
+            return;
+        }
+    }
+    let pid = FORWARD_PID.load(Ordering::SeqCst);
+    // the job leads its own process group, so signal everything it forked too. Supervisors don't lead a group,
+    // and neither does the job until it has called setsid, so fall back to the process itself.
+    if unsafe { libc::kill(-pid, signal) } == -1 {
+        unsafe { libc::kill(pid, signal) };
+    }
+}
+
+/// Close everything but stdin/stdout/stderr.
+///
+/// A supervisor never execs, so it would otherwise hold on to the server's fds - including the pipe that
+/// `Command::spawn` waits on to learn whether exec succeeded.
+fn close_inherited_fds() {
+    let closed = unsafe { libc::syscall(libc::SYS_close_range, 3u32, u32::MAX, 0u32) };
+    if closed != 0 {
+        // close_range is only available since linux 5.9
+        let max_fd = unsafe { libc::sysconf(libc::_SC_OPEN_MAX) };
+        for fd in 3..max_fd as libc::c_int {
+            unsafe { libc::close(fd) };
+        }
+    }
+}
+
+fn mount_proc() -> io::Result<()> {
+    check(unsafe {
+        libc::mount(
+            c"proc".as_ptr(),
+            c"/proc".as_ptr(),
+            c"proc".as_ptr(),
+            libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+            ptr::null(),
+        )
+    })
+}
+
+/// The part of `struct ifreq` used by SIOCGIFFLAGS/SIOCSIFFLAGS. libc doesn't define it.
+#[repr(C)]
+struct IfreqFlags {
+    name: [libc::c_char; libc::IFNAMSIZ],
+    flags: libc::c_short,
+    _pad: [u8; 22], // the rest of the ifreq union
+}
+
+/// A new network namespace only has a loopback interface, and it starts out down.
+fn loopback_up() -> io::Result<()> {
+    let sock = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    check(sock)?;
+    let mut ifreq: IfreqFlags = unsafe { mem::zeroed() };
+    for (dst, src) in ifreq.name.iter_mut().zip(b"lo") {
+        *dst = *src as libc::c_char;
+    }
+    let result =
+        check(unsafe { libc::ioctl(sock, libc::SIOCGIFFLAGS, &mut ifreq) }).and_then(|_| {
+            ifreq.flags |= (libc::IFF_UP | libc::IFF_RUNNING) as libc::c_short;
+            check(unsafe { libc::ioctl(sock, libc::SIOCSIFFLAGS, &ifreq) })
+        });
+    unsafe { libc::close(sock) };
+    result
+}
@@ -0,0 +1,104 @@
+use crate::sys::check;
+use crate::types::Credentials;
+use std::{ffi::CString, fs, io, mem, ptr};
+
+const CAP_SETGID: u32 = 6;
+const CAP_SETUID: u32 = 7;
+
+impl Credentials {
+    /// Whether this process may drop privileges to other credentials, which takes CAP_SETUID and CAP_SETGID.
+    pub fn can_switch() -> io::Result<bool> {
+        let status = fs::read_to_string("/proc/self/status")?;
+        let effective = status
+            .lines()
+            .find_map(|line| line.strip_prefix("CapEff:"))
+            .and_then(|caps| u64::from_str_radix(caps.trim(), 16).ok())
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "no CapEff in /proc/self/status")
+            })?;
+        let has = |cap: u32| effective & (1 << cap) != 0;
+        Ok(has(CAP_SETUID) && has(CAP_SETGID))
+    }
+
+    /// Look up the uid, primary gid and supplementary groups of a unix account.
+    pub fn for_account(name: &str) -> io::Result<Self> {
+        let c_name = CString::new(name).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, "account name contains nul")
+        })?;
+
+        let mut passwd: libc::passwd = unsafe { mem::zeroed() };
+        let mut buf = vec![0 as libc::c_char; 4096];
+        let mut result = ptr::null_mut();
+        loop {
+            let err = unsafe {
+                libc::getpwnam_r(
+                    c_name.as_ptr(),
+                    &mut passwd,
+                    buf.as_mut_ptr(),
+                    buf.len(),
+                    &mut result,
+                )
+            };
+            match err {
+                0 => break,
+                libc::ERANGE => buf.resize(buf.len() * 2, 0),
+                err => return Err(io::Error::from_raw_os_error(err)),
+            }
+        }
+        if result.is_null() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no such unix account: {}", name),
+            ));
+        }
+
+        let mut groups: Vec<libc::gid_t> = vec![0; 32];
+        loop {
+            let mut ngroups = groups.len() as libc::c_int;
+            let found = unsafe {
+                libc::getgrouplist(
+                    c_name.as_ptr(),
+                    passwd.pw_gid,
+                    groups.as_mut_ptr(),
+                    &mut ngroups,
+                )
+            };
+            // getgrouplist reports how many groups there are when the buffer is too small
+            if found == -1 {
+                groups.resize(ngroups.max(groups.len() as libc::c_int * 2) as usize, 0);
+            } else {
+                groups.truncate(ngroups as usize);
+                break;
+            }
+        }
+
+        Ok(Self {
+            uid: passwd.pw_uid,
+            gid: passwd.pw_gid,
+            groups,
+            allow_root: false,
+        })
+    }
+}
+
+/// Get a `pre_exec` hook which permanently drops privileges to `credentials`.
+///
+/// It has to be the last hook to run, since everything before it may need the privileges it drops.
+pub fn dropper(
+    credentials: Credentials,
+) -> io::Result<impl FnMut() -> io::Result<()> + Send + Sync + 'static> {
+    if credentials.uid == 0 && !credentials.allow_root {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "refusing to run job as root",
+        ));
+    }
+    Ok(move || drop_privileges(&credentials))
+}
+
+fn drop_privileges(credentials: &Credentials) -> io::Result<()> {
+    // groups first - once the uid changes we are no longer allowed to change them
+    check(unsafe { libc::setgroups(credentials.groups.len(), credentials.groups.as_ptr()) })?;
+    check(unsafe { libc::setgid(credentials.gid) })?;
+    check(unsafe { libc::setuid(credentials.uid) })
+}
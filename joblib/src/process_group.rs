@@ -0,0 +1,111 @@
+use crate::namespaces;
+use std::{fs, io};
+
+/// Get a `pre_exec` hook which makes the child the leader of a new session and process group, so that everything
+/// the job forks can be signalled together.
+pub fn session_leader() -> impl FnMut() -> io::Result<()> + Send + Sync + 'static {
+    || {
+        if unsafe { libc::setsid() } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// Get a `pre_exec` hook which moves the child into the process group `pgid`, or a new group led by the child if
+/// `pgid` is 0.
+///
+/// Used for the stages of a pipeline. A process can only join a group in its own session, so the stages share the
+/// library's session instead of each leading a new one.
+pub fn group_member(pgid: libc::pid_t) -> impl FnMut() -> io::Result<()> + Send + Sync + 'static {
+    move || {
+        if unsafe { libc::setpgid(0, pgid) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// The processes that make up a running job.
+#[derive(Clone, Copy, Debug)]
+pub enum JobProcesses {
+    /// the process group led by the job
+    Group(libc::pid_t),
+    /// the supervisor of a job in a new pid namespace. It forwards signals to the job's process group, and
+    /// the kernel kills whatever is left in the namespace once the job exits.
+    Supervisor(libc::pid_t),
+}
+
+impl JobProcesses {
+    /// Send a signal to every process of the job.
+    pub fn signal(&self, signal: libc::c_int) -> io::Result<()> {
+        match *self {
+            Self::Group(pgid) => kill(-pgid, signal),
+            Self::Supervisor(pid) => kill(pid, signal),
+        }
+    }
+
+    /// Suspend every process of the job.
+    pub fn pause(&self) -> io::Result<()> {
+        match *self {
+            Self::Group(_) => self.signal(libc::SIGSTOP),
+            Self::Supervisor(pid) => kill(pid, namespaces::pause_signal()),
+        }
+    }
+
+    /// Continue every process of the job.
+    pub fn resume(&self) -> io::Result<()> {
+        match *self {
+            Self::Group(_) => self.signal(libc::SIGCONT),
+            Self::Supervisor(pid) => kill(pid, namespaces::resume_signal()),
+        }
+    }
+
+    /// Whether every process of the job has exited. Only meaningful once the job's own process has exited.
+    pub fn all_exited(&self) -> bool {
+        match *self {
+            Self::Group(pgid) => {
+                // fast path: nothing at all is left in the group
+                if unsafe { libc::kill(-pgid, 0) } == -1
+                    && io::Error::last_os_error().raw_os_error() == Some(libc::ESRCH)
+                {
+                    return true;
+                }
+                // orphaned zombies are up to whoever they were re-parented to, they don't keep the job alive
+                !has_live_member(pgid)
+            }
+            Self::Supervisor(_) => true,
+        }
+    }
+}
+
+fn kill(pid: libc::pid_t, signal: libc::c_int) -> io::Result<()> {
+    if unsafe { libc::kill(pid, signal) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Scan /proc for a process in the process group that hasn't exited.
+fn has_live_member(pgid: libc::pid_t) -> bool {
+    let entries = match fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(_) => return false,
+    };
+    entries
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+        .any(|pid| {
+            let stat = match fs::read_to_string(format!("/proc/{}/stat", pid)) {
+                Ok(stat) => stat,
+                Err(_) => return false, // already gone
+            };
+            // the format is "pid (comm) state ppid pgrp ...", and comm can contain spaces and parens
+            let mut fields = match stat.rsplit_once(')') {
+                Some((_, fields)) => fields.split_whitespace(),
+                None => return false,
+            };
+            let state = fields.next();
+            let pgrp = fields.nth(1).and_then(|pgrp| pgrp.parse().ok());
+            state != Some("Z") && pgrp == Some(pgid)
+        })
+}
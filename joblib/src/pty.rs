@@ -0,0 +1,167 @@
+use crate::error::{self, Error as JobError};
+use crate::events::OutputBlob;
+use crate::sys::check;
+use crate::types::{Output, WindowSize};
+
+use futures::future::FutureExt;
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    os::unix::io::{AsRawFd, FromRawFd},
+    ptr,
+    sync::Arc,
+};
+use tokio::{
+    io::unix::AsyncFd,
+    select,
+    sync::{mpsc, oneshot},
+};
+
+/// A handle for typing into a job's terminal and resizing it.
+#[derive(Clone, Debug)]
+pub struct Terminal {
+    sender: mpsc::UnboundedSender<TerminalInput>,
+}
+
+#[derive(Debug)]
+pub enum TerminalInput {
+    Data(OutputBlob),
+    Resize(WindowSize),
+}
+
+impl Terminal {
+    /// Write input to the terminal, as if it were typed.
+    pub fn write(&self, data: OutputBlob) -> error::Result<()> {
+        self.sender
+            .send(TerminalInput::Data(data))
+            .map_err(|_| JobError::AlreadyStopped)
+    }
+
+    /// Change the terminal window size. The job gets a SIGWINCH.
+    pub fn resize(&self, size: WindowSize) -> error::Result<()> {
+        self.sender
+            .send(TerminalInput::Resize(size))
+            .map_err(|_| JobError::AlreadyStopped)
+    }
+}
+
+/// Both ends of a pseudo-terminal. The master is non-blocking and registered with the tokio reactor.
+pub struct Pty {
+    pub master: AsyncFd<File>,
+    pub slave: File,
+}
+
+impl Pty {
+    pub fn open(size: WindowSize) -> io::Result<Self> {
+        let (mut master, mut slave) = (0, 0);
+        let winsize = winsize(size);
+        check(unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                ptr::null_mut(),
+                ptr::null(),
+                &winsize,
+            )
+        })?;
+        // SAFETY: openpty just gave us these fds, nothing else owns them
+        let (master, slave) = unsafe { (File::from_raw_fd(master), File::from_raw_fd(slave)) };
+        // openpty doesn't set close-on-exec. The child gets the slave as its stdio regardless.
+        for fd in [master.as_raw_fd(), slave.as_raw_fd()] {
+            check(unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) })?;
+        }
+        check(unsafe { libc::fcntl(master.as_raw_fd(), libc::F_SETFL, libc::O_NONBLOCK) })?;
+        Ok(Self {
+            master: AsyncFd::new(master)?,
+            slave,
+        })
+    }
+}
+
+/// Get a `pre_exec` hook which makes the child's stdin (the pty slave) its controlling terminal.
+///
+/// A terminal can only be the controlling terminal of a session leader, so the child must already be one.
+pub fn controlling_terminal() -> impl FnMut() -> io::Result<()> + Send + Sync + 'static {
+    || check(unsafe { libc::ioctl(libc::STDIN_FILENO, libc::TIOCSCTTY, 0) })
+}
+
+/// Pipe terminal output to the broadcaster, and terminal input from `Terminal` handles into the terminal.
+///
+/// Returns the `Terminal` handle for the job.
+pub fn spawn_io(master: AsyncFd<File>, output_tx: mpsc::UnboundedSender<Output>) -> Terminal {
+    let master = Arc::new(master);
+    let (sender, mut input_rx) = mpsc::unbounded_channel();
+
+    // the writer stops once the terminal is closed, so the master fd isn't held open forever
+    let (closed_tx, closed_rx) = oneshot::channel::<()>();
+
+    let reader = master.clone();
+    tokio::spawn(async move {
+        let mut buf = [0u8; 4096];
+        loop {
+            let mut guard = match reader.readable().await {
+                Ok(guard) => guard,
+                Err(_) => break,
+            };
+            match guard.try_io(|master| master.get_ref().read(&mut buf)) {
+                // reads fail with EIO once every copy of the slave is closed, that's the terminal's EOF
+                Ok(Ok(0)) | Ok(Err(_)) => break,
+                Ok(Ok(n)) => {
+                    let blob = OutputBlob::copy_from_slice(&buf[..n]);
+                    let _ = output_tx.send(Output::Stdout(blob));
+                }
+                Err(_would_block) => continue,
+            }
+        }
+        drop(closed_tx);
+    });
+
+    let writer = master;
+    tokio::spawn(async move {
+        let mut closed_rx = closed_rx.fuse();
+        loop {
+            let input = select! {
+                maybe_input = input_rx.recv() => match maybe_input {
+                    Some(input) => input,
+                    None => break,
+                },
+                _ = &mut closed_rx => break,
+            };
+            match input {
+                TerminalInput::Data(data) => {
+                    if write_all(&writer, &data).await.is_err() {
+                        break;
+                    }
+                }
+                TerminalInput::Resize(size) => {
+                    let winsize = winsize(size);
+                    let fd = writer.get_ref().as_raw_fd();
+                    let _ = unsafe { libc::ioctl(fd, libc::TIOCSWINSZ, &winsize) };
+                }
+            }
+        }
+    });
+
+    Terminal { sender }
+}
+
+async fn write_all(master: &AsyncFd<File>, mut data: &[u8]) -> io::Result<()> {
+    while !data.is_empty() {
+        let mut guard = master.writable().await?;
+        match guard.try_io(|master| master.get_ref().write(data)) {
+            Ok(Ok(n)) => data = &data[n..],
+            Ok(Err(err)) => return Err(err),
+            Err(_would_block) => continue,
+        }
+    }
+    Ok(())
+}
+
+fn winsize(size: WindowSize) -> libc::winsize {
+    libc::winsize {
+        ws_row: size.rows,
+        ws_col: size.cols,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    }
+}
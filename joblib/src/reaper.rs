@@ -0,0 +1,64 @@
+use crate::events::ResourceUsage;
+use std::{io, mem, process::ExitStatus, time::Duration};
+use tokio::{
+    process::Child,
+    signal::unix::{signal, SignalKind},
+};
+
+/// Wait for a child to exit, and get what it used along with its exit status. `Child::wait` only reports the exit
+/// status.
+///
+/// The usage covers the child and every descendant it waited for, but not descendants left to be reaped by init.
+pub async fn wait(mut child: Child) -> io::Result<(ExitStatus, ResourceUsage)> {
+    let pid = child.id().expect("child has not been reaped yet") as libc::id_t;
+    // listen before checking, so an exit between the check and the wait isn't missed
+    let mut sigchld = signal(SignalKind::child())?;
+    let rusage = loop {
+        // SAFETY: rusage is plain old data
+        let mut rusage: libc::rusage = unsafe { mem::zeroed() };
+        if exited(pid, &mut rusage)? {
+            break rusage;
+        }
+        sigchld.recv().await;
+    };
+    let status = child.wait().await?;
+    Ok((status, resource_usage(&rusage)))
+}
+
+/// Whether the child has exited, filling in what it used if it has. The raw waitid(2) syscall reports the usage like
+/// wait4(2) does, but WNOWAIT leaves the child for `Child::wait` to reap, so tokio never kills or reaps a pid that may
+/// belong to another process by then.
+fn exited(pid: libc::id_t, rusage: &mut libc::rusage) -> io::Result<bool> {
+    // SAFETY: siginfo is plain old data
+    let mut info: libc::siginfo_t = unsafe { mem::zeroed() };
+    let options = libc::WEXITED | libc::WNOHANG | libc::WNOWAIT;
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_waitid,
+            libc::P_PID,
+            pid,
+            &mut info as *mut libc::siginfo_t,
+            options,
+            rusage as *mut libc::rusage,
+        )
+    };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // a child that hasn't exited yet leaves the siginfo zeroed
+    Ok(unsafe { info.si_pid() } != 0)
+}
+
+fn resource_usage(rusage: &libc::rusage) -> ResourceUsage {
+    let time = |time: libc::timeval| {
+        Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+    };
+    ResourceUsage {
+        user_time: time(rusage.ru_utime),
+        system_time: time(rusage.ru_stime),
+        // in kilobytes
+        max_rss: rusage.ru_maxrss as u64 * 1024,
+        block_reads: rusage.ru_inblock as u64,
+        block_writes: rusage.ru_oublock as u64,
+    }
+}
@@ -0,0 +1,63 @@
+use crate::error::{self, Error as JobError};
+use crate::events::OutputBlob;
+
+use tokio::{io::AsyncWriteExt, process::ChildStdin, sync::mpsc};
+
+/// A handle for writing to the stdin of a job started with `StdinSource::Stream`.
+///
+/// The job's stdin stays open until `close` is called on any of the handles.
+#[derive(Clone, Debug)]
+pub struct StdinWriter {
+    sender: mpsc::UnboundedSender<StdinInput>,
+}
+
+#[derive(Debug)]
+enum StdinInput {
+    Data(OutputBlob),
+    Eof,
+}
+
+impl StdinWriter {
+    /// Queue data to be written to the job's stdin.
+    pub fn write(&self, data: OutputBlob) -> error::Result<()> {
+        self.sender
+            .send(StdinInput::Data(data))
+            .map_err(|_| JobError::StdinClosed)
+    }
+
+    /// Close the job's stdin once all queued data is written, so the job reads EOF.
+    pub fn close(&self) -> error::Result<()> {
+        self.sender
+            .send(StdinInput::Eof)
+            .map_err(|_| JobError::StdinClosed)
+    }
+}
+
+/// Write `data` to the job's stdin in the background, then close it.
+pub fn write_bytes(mut stdin: ChildStdin, data: OutputBlob) {
+    tokio::spawn(async move {
+        // the job may exit without reading everything, that's not our problem
+        let _ = stdin.write_all(&data).await;
+    });
+}
+
+/// Pipe data from `StdinWriter` handles into the job's stdin.
+///
+/// Returns the `StdinWriter` handle for the job.
+pub fn spawn_writer(mut stdin: ChildStdin) -> StdinWriter {
+    let (sender, mut input_rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(input) = input_rx.recv().await {
+            match input {
+                StdinInput::Data(data) => {
+                    if stdin.write_all(&data).await.is_err() {
+                        break; // the job closed its end of the pipe
+                    }
+                }
+                StdinInput::Eof => break,
+            }
+        }
+        // dropping stdin closes the pipe, and dropping input_rx makes any later writes fail
+    });
+    StdinWriter { sender }
+}
@@ -0,0 +1,11 @@
+use std::io;
+
+/// Turn the return value of a libc call that returns -1 on failure into the error it set errno to. Safe to call
+/// between fork and exec, it doesn't allocate.
+pub(crate) fn check(ret: libc::c_int) -> io::Result<()> {
+    if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
@@ -1,4 +1,10 @@
-use crate::events::OutputBlob;
+use crate::events::{JobState, JobStatus, OutputBlob, StopReason};
+use std::{
+    collections::BTreeMap,
+    env, io,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
 use uuid::Uuid;
 
 // TODO: make these more generic. requiring exact types is too strict.
@@ -12,10 +18,504 @@ pub type Dir = String;
 pub type Envs = Vec<(String, String)>;
 /// job id used to track and manage jobs
 pub type JobId = Uuid;
+/// key/value labels describing a job
+pub type Labels = BTreeMap<String, String>;
+/// workflow id used to track a workflow's steps
+pub type WorkflowId = Uuid;
+/// schedule id used to track the jobs a schedule starts
+pub type ScheduleId = Uuid;
+
+/// Everything needed to start a job.
+#[derive(Clone, Debug)]
+pub struct JobSpec {
+    pub cmd: Program,
+    pub args: Args,
+    pub dir: Dir,
+    pub envs: Envs,
+    /// what the job inherits from the library's own environment, and which variables it may set.
+    pub env_policy: EnvPolicy,
+    /// more commands to run after `cmd`, each reading the stdout of the one before it, like a shell pipeline.
+    /// The job's stdout is the last command's stdout, and its stderr is every command's stderr.
+    pub pipeline: Vec<PipelineStage>,
+    /// report the last stage of the pipeline that failed as the job's status, instead of the last stage's status.
+    pub pipefail: bool,
+    /// optional cgroup v2 resource limits. No cgroup is created for the job if there are no limits.
+    pub limits: ResourceLimits,
+    /// linux namespaces the job is cloned into
+    pub isolation: Isolation,
+    /// unix user to run the job as. `None` runs the job as the same user as the library.
+    pub credentials: Option<Credentials>,
+    /// run the job in a pseudo-terminal of this size, instead of piping stdout/stderr.
+    /// All terminal output is reported as stdout.
+    pub tty: Option<WindowSize>,
+    /// where the job's stdin comes from. Ignored for jobs with a tty, their stdin is the terminal.
+    pub stdin: StdinSource,
+    /// wall-clock time the job may run for, before it is stopped with its stop policy.
+    pub timeout: Option<Duration>,
+    /// how the job is stopped, by `stop_job` or a timeout.
+    pub stop_policy: StopPolicy,
+    /// whether the job is started again, under the same job id, once it exits.
+    pub restart_policy: RestartPolicy,
+    /// how long to wait before each restart.
+    pub restart_backoff: Backoff,
+    /// who the job belongs to. Owners share the coordinator's queue fairly, and jobs without an owner share it as
+    /// one owner.
+    pub owner: Option<String>,
+    /// queued jobs with a higher priority are started first.
+    pub priority: i32,
+    /// a name the job can be found by, unique among the jobs of its owner. Names are made of ascii letters, digits,
+    /// `_`, `.` and `-`, and start with a letter or digit.
+    pub name: Option<String>,
+    pub labels: Labels,
+}
+
+impl JobSpec {
+    /// Create a job spec with no extra options set.
+    pub fn new(cmd: Program, args: Args, dir: Dir, envs: Envs) -> Self {
+        Self {
+            cmd,
+            args,
+            dir,
+            envs,
+            env_policy: EnvPolicy::default(),
+            pipeline: Vec::new(),
+            pipefail: false,
+            limits: ResourceLimits::default(),
+            isolation: Isolation::default(),
+            credentials: None,
+            tty: None,
+            stdin: StdinSource::default(),
+            timeout: None,
+            stop_policy: StopPolicy::default(),
+            restart_policy: RestartPolicy::default(),
+            restart_backoff: Backoff::default(),
+            owner: None,
+            priority: 0,
+            name: None,
+            labels: Labels::new(),
+        }
+    }
+}
+
+/// How many jobs the coordinator runs at once. Jobs started past a limit wait in a queue until a slot frees up.
+///
+/// A job holds its slot from when it is spawned until it finishes for good, including while it is paused or waiting
+/// to be restarted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QueueLimits {
+    /// jobs running at once. `None` for no limit.
+    pub max_running: Option<usize>,
+    /// jobs with the same owner running at once. `None` for no limit.
+    pub max_running_per_owner: Option<usize>,
+}
+
+/// How long a coordinator remembers jobs that have finished for good. By default they are kept forever.
+///
+/// A forgotten job is gone along with its actors and output, as if it never existed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// forget a job this long after it finished. `None` to keep it.
+    pub max_age: Option<Duration>,
+    /// forget an owner's jobs that finished first once they have more finished jobs than this. `None` for no limit.
+    pub max_finished_per_owner: Option<usize>,
+}
+
+/// Where a coordinator keeps job output on disk. Without one, a job's output is kept in memory for as long as the job
+/// is, up to the coordinator's output budget.
+///
+/// Each job's output is appended to segment files in its own directory under `dir`, which is removed when the job is
+/// forgotten. Only the last `hot_tail` bytes are kept in memory as well, and subscribers that need more than that
+/// read it back from disk.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpoolConfig {
+    pub dir: PathBuf,
+    /// start a new segment once the last one is this many bytes
+    pub segment_size: u64,
+    /// stop keeping a job's output once it has this many bytes. Output past the cap still goes to subscribers that
+    /// are streaming, but later subscribers don't see it. `None` for no cap.
+    pub max_job_bytes: Option<u64>,
+    /// bytes of each job's latest output to keep in memory
+    pub hot_tail: usize,
+}
+
+impl SpoolConfig {
+    /// Spool into `dir` with 8 MiB segments, a 64 KiB hot tail, and no cap.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            segment_size: 8 << 20,
+            max_job_bytes: None,
+            hot_tail: 64 << 10,
+        }
+    }
+}
+
+/// What a job's broadcaster does with a subscriber whose queue is full, because it reads slower than the job writes.
+/// The job and the other subscribers never wait for it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SlowSubscriberPolicy {
+    /// stop sending it live output, and let it catch up at its own pace from the output that was kept. Whatever was
+    /// dropped from memory before it got there is reported with `OutputEvent::Gap`.
+    #[default]
+    CatchUp,
+    /// drop it, and end its stream with `OutputEvent::Lagged`
+    Disconnect,
+    /// drop the output it has no room for, and report how much with `OutputEvent::Gap` once it has room again
+    Skip,
+}
+
+/// How much output is queued for each subscriber to a job's output, and what happens when it's full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SubscriberQueue {
+    /// events queued for a subscriber
+    pub capacity: usize,
+    pub slow: SlowSubscriberPolicy,
+}
+
+impl Default for SubscriberQueue {
+    fn default() -> Self {
+        Self {
+            capacity: 256,
+            slow: SlowSubscriberPolicy::default(),
+        }
+    }
+}
+
+/// How a coordinator runs jobs, how long it keeps them around, and where it keeps their output.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CoordinatorConfig {
+    pub queue_limits: QueueLimits,
+    pub retention: RetentionPolicy,
+    pub spool: Option<SpoolConfig>,
+    /// bytes of each job's latest output to keep in memory when there is no spool. Older output is dropped, and later
+    /// subscribers are told how much of it they missed. `None` to keep it all.
+    pub output_budget: Option<usize>,
+    pub subscriber_queue: SubscriberQueue,
+}
+
+/// Keeps track of the jobs and workflows a coordinator knows about, and the schedules a scheduler knows about, e.g.
+/// who owns them.
+///
+/// The coordinator calls it from its own task, as it takes on and forgets jobs, so the registry is never behind it: a
+/// job is added before its id is handed to anyone, and removed before `delete_job` returns. The scheduler does the
+/// same with schedules. The calls must not block.
+pub trait JobRegistry: Send + Sync {
+    /// A job was taken on, including the steps of a workflow that haven't started yet.
+    fn job_added(&self, _job_id: JobId, _owner: Option<&str>) {}
+    /// A job was forgotten, by `delete_job` or the retention policy.
+    fn job_forgotten(&self, _job_id: JobId) {}
+    fn workflow_added(&self, _workflow_id: WorkflowId, _owner: Option<&str>) {}
+    /// A finished workflow was forgotten, along with the last of its steps' jobs.
+    fn workflow_forgotten(&self, _workflow_id: WorkflowId) {}
+    /// A schedule was created. Its owner is the owner of the jobs it starts.
+    fn schedule_added(&self, _schedule_id: ScheduleId, _owner: Option<&str>) {}
+    fn schedule_deleted(&self, _schedule_id: ScheduleId) {}
+}
+
+/// Keeps track of nothing.
+impl JobRegistry for () {}
+
+/// Which jobs `list_jobs` returns. Every field that is set has to match.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct JobFilter {
+    pub owner: Option<String>,
+    /// jobs in any of these states. Empty for any state.
+    pub states: Vec<JobState>,
+    /// jobs with every one of these labels
+    pub labels: Labels,
+    /// jobs whose first attempt was spawned after this. Jobs that haven't been spawned never match.
+    pub started_after: Option<SystemTime>,
+    /// jobs whose first attempt was spawned before this. Jobs that haven't been spawned never match.
+    pub started_before: Option<SystemTime>,
+}
+
+/// Which page of the jobs that match a `JobFilter` `list_jobs` returns. The default is every job.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct JobPage {
+    /// only jobs after this one, by when they were submitted and then by id, like the page before ended with
+    pub after: Option<(SystemTime, JobId)>,
+    /// at most this many jobs
+    pub limit: Option<usize>,
+}
+
+/// Which jobs `subscribe_events` reports events for. Every field that is set has to match.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EventFilter {
+    pub job_id: Option<JobId>,
+    pub owner: Option<String>,
+}
+
+/// A command in a job's pipeline. It runs with the same directory, environment, and limits as the rest of the job.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PipelineStage {
+    pub cmd: Program,
+    pub args: Args,
+}
+
+/// A graph of jobs, where each step starts once the steps it depends on have finished.
+#[derive(Clone, Debug)]
+pub struct WorkflowSpec {
+    pub steps: Vec<WorkflowStep>,
+}
+
+/// A step of a workflow. Step names must be unique within the workflow, and dependencies can't form a cycle.
+#[derive(Clone, Debug)]
+pub struct WorkflowStep {
+    pub name: String,
+    pub spec: JobSpec,
+    /// steps that must finish, however they end, before this one starts
+    pub after: Vec<String>,
+    /// steps that must exit with code 0 before this one starts. If any of them doesn't, this step is skipped.
+    pub after_success: Vec<String>,
+}
+
+/// A job that is started again on every tick of a cron schedule.
+#[derive(Clone, Debug)]
+pub struct ScheduleSpec {
+    /// a standard cron expression with 5 fields: minute, hour, day of month, month and day of week. 6 or 7 fields
+    /// add seconds first and a year last.
+    pub cron: String,
+    /// IANA time zone the expression is evaluated in, e.g. "Europe/Berlin"
+    pub timezone: String,
+    pub job: JobSpec,
+    pub overlap: OverlapPolicy,
+}
+
+/// What to do when a schedule ticks while a job it started is still running.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverlapPolicy {
+    /// don't start a job for the tick
+    #[default]
+    Skip,
+    /// start a job for the tick once the running job has finished
+    Queue,
+    /// start a job for the tick anyway
+    Allow,
+}
+
+/// cgroup v2 resource limits for a job.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// `cpu.max`
+    pub cpu: Option<CpuMax>,
+    /// `memory.max` in bytes
+    pub memory_max: Option<u64>,
+    /// `io.max` and `io.weight` per device
+    pub io: Vec<IoLimit>,
+}
+
+impl ResourceLimits {
+    pub fn is_empty(&self) -> bool {
+        self.cpu.is_none() && self.memory_max.is_none() && self.io.is_empty()
+    }
+}
+
+/// CPU bandwidth limit: the job may use up to `quota_us` of cpu time every `period_us`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CpuMax {
+    pub quota_us: u64,
+    pub period_us: u64,
+}
+
+/// IO limits for a single block device, identified by its major:minor numbers.
+///
+/// Any limit left as `None` is unrestricted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IoLimit {
+    pub major: u32,
+    pub minor: u32,
+    /// proportional weight in the range 1..=10000
+    pub weight: Option<u16>,
+    /// read bytes per second
+    pub rbps: Option<u64>,
+    /// write bytes per second
+    pub wbps: Option<u64>,
+    /// read IO operations per second
+    pub riops: Option<u64>,
+    /// write IO operations per second
+    pub wiops: Option<u64>,
+}
+
+/// Which environment variables a job runs with. The variables the job spec asks for are set over the inherited
+/// ones, and the overrides are set over both.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EnvPolicy {
+    pub inherit: EnvInherit,
+    /// set for every job whatever its spec asks for, e.g. a fixed PATH
+    pub overrides: Envs,
+    /// variables a job spec may not ask for. A job that asks for one of them isn't started.
+    pub denied: Vec<String>,
+}
+
+/// What a job inherits from the library's own environment.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum EnvInherit {
+    /// nothing, the job starts from an empty environment
+    Nothing,
+    /// only these variables, if they are set
+    Only(Vec<String>),
+    /// every variable
+    #[default]
+    Everything,
+}
+
+impl EnvPolicy {
+    /// The environment a job runs with, given the variables its spec asks for, sorted by name.
+    ///
+    /// Fails with `PermissionDenied` if the spec asks for a denied variable.
+    pub(crate) fn environment(&self, envs: &Envs) -> io::Result<Envs> {
+        if let Some((name, _)) = envs.iter().find(|(name, _)| self.denied.contains(name)) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("environment variable {} can't be set", name),
+            ));
+        }
+        // variables that aren't valid unicode can't be inherited
+        let inherited = env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .filter(|(name, _)| match &self.inherit {
+                EnvInherit::Nothing => false,
+                EnvInherit::Only(names) => names.contains(name),
+                EnvInherit::Everything => true,
+            });
+        let mut environment: BTreeMap<_, _> = inherited.collect();
+        environment.extend(envs.iter().cloned());
+        environment.extend(self.overrides.iter().cloned());
+        Ok(environment.into_iter().collect())
+    }
+}
+
+/// Which new linux namespaces a job runs in. The default is to share all of the server's namespaces.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Isolation {
+    /// new pid namespace with a private /proc. Implies a new mount namespace.
+    ///
+    /// NOTE: the job runs under a minimal init, so if it dies by any signal other than SIGKILL its status is
+    /// reported with the shell convention: exited with code 128 + signal.
+    pub pid: bool,
+    /// new mount namespace, with all mounts made private
+    pub mount: bool,
+    /// new network namespace with only a loopback interface
+    pub network: bool,
+    /// new hostname/domain name namespace
+    pub uts: bool,
+    /// new System V IPC/posix message queue namespace
+    pub ipc: bool,
+}
+
+impl Isolation {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// A unix identity for a job. Privileges are dropped to it in the child, just before exec.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+    /// supplementary groups
+    pub groups: Vec<u32>,
+    /// jobs with uid 0 are refused unless this is set
+    pub allow_root: bool,
+}
+
+/// The source of a job's stdin.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum StdinSource {
+    /// stdin is `/dev/null`
+    #[default]
+    Null,
+    /// stdin is a fixed payload, followed by EOF
+    Bytes(OutputBlob),
+    /// stdin is written while the job runs, with a `StdinWriter` from the `JobCoordinator`
+    Stream,
+}
+
+/// How to stop a job: send it `signal`, and SIGKILL it if it is still running after `grace`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StopPolicy {
+    pub signal: i32,
+    pub grace: Duration,
+}
+
+impl Default for StopPolicy {
+    fn default() -> Self {
+        Self {
+            signal: libc::SIGTERM,
+            grace: Duration::from_secs(10),
+        }
+    }
+}
+
+/// When to start a job again after it exits. A job stopped with `stop_job` is never restarted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RestartPolicy {
+    #[default]
+    Never,
+    /// restart the job if it exits with a non-zero code or is killed, for at most `max_attempts` attempts
+    /// including the first
+    OnFailure { max_attempts: u32 },
+    /// restart the job whenever it exits
+    Always,
+}
+
+impl RestartPolicy {
+    /// Whether a job should be started again after its `attempt` ended with `status`.
+    pub(crate) fn should_restart(&self, attempt: u32, status: JobStatus) -> bool {
+        let (failed, stopped) = match status {
+            JobStatus::Exited { code, stopped } => (code != 0, stopped),
+            JobStatus::Killed { stopped, .. } => (true, stopped),
+            _ => return false,
+        };
+        if stopped == Some(StopReason::User) {
+            return false;
+        }
+        match *self {
+            Self::Never => false,
+            Self::OnFailure { max_attempts } => failed && attempt < max_attempts,
+            Self::Always => true,
+        }
+    }
+}
+
+/// Exponential backoff between restarts: the first restart waits `initial`, and each one after that waits
+/// twice as long as the last, up to `max`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Backoff {
+    /// How long to wait before starting the attempt after `attempt`.
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Size of a terminal window, in characters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WindowSize {
+    pub rows: u16,
+    pub cols: u16,
+}
 
 /// Output blobs distinguished by source of the output.
 #[derive(Clone)]
 pub enum Output {
     Stdout(OutputBlob),
     Stderr(OutputBlob),
+    /// a restarted job's next attempt starts here
+    Attempt(u32),
 }
@@ -0,0 +1,180 @@
+use crate::error::{self, Error as JobError};
+use crate::events::{JobStatus, StepState, StepStatus, WorkflowState, WorkflowStatus};
+use crate::types::{JobId, JobSpec, WorkflowSpec};
+use std::collections::HashMap;
+
+/// The steps of a workflow, and what state each of them is in.
+///
+/// The coordinator starts whatever `ready_steps` returns, and reports back as the steps' jobs finish.
+pub struct Workflow {
+    steps: Vec<Step>,
+}
+
+struct Step {
+    name: String,
+    job_id: JobId,
+    /// taken when the step starts
+    spec: Option<JobSpec>,
+    /// indexes of the steps this one depends on
+    after: Vec<usize>,
+    after_success: Vec<usize>,
+    state: StepState,
+}
+
+impl Workflow {
+    /// Check the workflow's dependencies, and assign each step a job id.
+    pub fn new(spec: WorkflowSpec) -> error::Result<Self> {
+        let invalid = |reason: String| Err(JobError::InvalidWorkflow(reason));
+        if spec.steps.is_empty() {
+            return invalid("no steps".into());
+        }
+        let mut indexes = HashMap::new();
+        for (index, step) in spec.steps.iter().enumerate() {
+            if step.name.is_empty() {
+                return invalid("step without a name".into());
+            }
+            if indexes.insert(step.name.as_str(), index).is_some() {
+                return invalid(format!("duplicate step {:?}", step.name));
+            }
+        }
+        let resolve = |step: &str, names: &[String]| -> error::Result<Vec<usize>> {
+            names
+                .iter()
+                .map(|name| match indexes.get(name.as_str()) {
+                    Some(index) => Ok(*index),
+                    None => Err(JobError::InvalidWorkflow(format!(
+                        "step {:?} depends on unknown step {:?}",
+                        step, name
+                    ))),
+                })
+                .collect()
+        };
+        let mut steps = Vec::with_capacity(spec.steps.len());
+        for step in &spec.steps {
+            steps.push(Step {
+                name: step.name.clone(),
+                job_id: uuid::Uuid::new_v4(),
+                spec: Some(step.spec.clone()),
+                after: resolve(&step.name, &step.after)?,
+                after_success: resolve(&step.name, &step.after_success)?,
+                state: StepState::Waiting,
+            });
+        }
+        let workflow = Self { steps };
+        if let Some(step) = workflow.find_cycle() {
+            return invalid(format!("step {:?} is part of a dependency cycle", step));
+        }
+        Ok(workflow)
+    }
+
+    /// Find a step that can never start because of a dependency cycle, by repeatedly removing steps with no
+    /// dependencies left.
+    fn find_cycle(&self) -> Option<&str> {
+        let mut remaining: Vec<usize> = self
+            .steps
+            .iter()
+            .map(|step| step.after.len() + step.after_success.len())
+            .collect();
+        let mut removed = vec![false; self.steps.len()];
+        while let Some(index) = (0..self.steps.len()).find(|&i| !removed[i] && remaining[i] == 0) {
+            removed[index] = true;
+            for (dependent, step) in self.steps.iter().enumerate() {
+                remaining[dependent] -= step
+                    .after
+                    .iter()
+                    .chain(&step.after_success)
+                    .filter(|&&dependency| dependency == index)
+                    .count();
+            }
+        }
+        removed
+            .iter()
+            .position(|removed| !removed)
+            .map(|index| self.steps[index].name.as_str())
+    }
+
+    /// The job ids of every step.
+    pub fn job_ids(&self) -> impl Iterator<Item = JobId> + '_ {
+        self.steps.iter().map(|step| step.job_id)
+    }
+
+    /// Skip steps whose `after_success` dependencies didn't succeed, and mark the steps whose dependencies are
+    /// all done as running.
+    ///
+    /// Returns the job id and spec of every step that should be started now.
+    pub fn ready_steps(&mut self) -> Vec<(JobId, JobSpec)> {
+        let mut ready = Vec::new();
+        // skipping a step can make its own dependents skippable, so go until nothing changes
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for index in 0..self.steps.len() {
+                if self.steps[index].state != StepState::Waiting {
+                    continue;
+                }
+                let step = &self.steps[index];
+                let state = |dependency: &usize| self.steps[*dependency].state;
+                if step
+                    .after_success
+                    .iter()
+                    .map(state)
+                    .any(|state| state.is_done() && !state.succeeded())
+                {
+                    self.steps[index].state = StepState::Skipped;
+                    changed = true;
+                } else if step
+                    .after
+                    .iter()
+                    .chain(&step.after_success)
+                    .map(state)
+                    .all(|state| state.is_done())
+                {
+                    let step = &mut self.steps[index];
+                    step.state = StepState::Running;
+                    let spec = step.spec.take().expect("step started twice");
+                    ready.push((step.job_id, spec));
+                    changed = true;
+                }
+            }
+        }
+        ready
+    }
+
+    /// Record that a step's job finished for good.
+    pub fn step_finished(&mut self, job_id: JobId, status: JobStatus) {
+        self.set_state(job_id, StepState::Finished(status));
+    }
+
+    /// Record that a step's job couldn't be spawned.
+    pub fn step_failed_to_start(&mut self, job_id: JobId) {
+        self.set_state(job_id, StepState::FailedToStart);
+    }
+
+    fn set_state(&mut self, job_id: JobId, state: StepState) {
+        if let Some(step) = self.steps.iter_mut().find(|step| step.job_id == job_id) {
+            step.state = state;
+        }
+    }
+
+    pub fn status(&self) -> WorkflowStatus {
+        let state = if !self.steps.iter().all(|step| step.state.is_done()) {
+            WorkflowState::Running
+        } else if self.steps.iter().all(|step| step.state.succeeded()) {
+            WorkflowState::Succeeded
+        } else {
+            WorkflowState::Failed
+        };
+        WorkflowStatus {
+            state,
+            steps: self
+                .steps
+                .iter()
+                .map(|step| StepStatus {
+                    name: step.name.clone(),
+                    job_id: step.job_id,
+                    state: step.state,
+                })
+                .collect(),
+        }
+    }
+}
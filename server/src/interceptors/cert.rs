@@ -10,7 +10,7 @@ pub fn extract_subj_uid(mut req: Request<()>) -> Result<Request<()>, Status> {
     let client_certs = req
         .peer_certs()
         .ok_or_else(|| Status::unauthenticated("Request missing client cert"))?;
-    if client_certs.len() == 0 {
+    if client_certs.is_empty() {
         return Err(Status::unauthenticated("Request missing client cert"));
     }
 
@@ -1,10 +1,18 @@
+// tonic dictates `Result<_, Status>` in interceptors and streams, so there is no avoiding a large Err variant.
+#![allow(clippy::result_large_err)]
+
 mod interceptors;
 mod services;
 
 pub use cert::UserExtension;
 use interceptors::cert;
+use joblib::types::{
+    CoordinatorConfig, EnvInherit, EnvPolicy, QueueLimits, RetentionPolicy, SlowSubscriberPolicy,
+    SpoolConfig, SubscriberQueue,
+};
 use protobuf::remote_jobs_server::RemoteJobsServer;
-pub use services::jobservice::RemoteJobsService;
+pub use services::jobservice::{RemoteJobsService, UserMap};
+use std::time::Duration;
 use tokio_rustls::rustls::{
     self, ciphersuite::TLS13_AES_256_GCM_SHA384, AllowAnyAuthenticatedClient, RootCertStore,
     ServerConfig,
@@ -14,10 +22,136 @@ use tonic::transport::{Server, ServerTlsConfig};
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let addr = "[::1]:50051";
-    serve(addr).await
+    // optionally map users to unix accounts from a file, see `UserMap::from_file` for the format
+    let user_map = match std::env::var_os("REMOTEJOBS_USER_MAP") {
+        Some(path) => {
+            let allow_root = std::env::var_os("REMOTEJOBS_ALLOW_ROOT").is_some();
+            UserMap::from_file(path, allow_root)?
+        }
+        None => UserMap::default(),
+    };
+    // optionally limit how many jobs run at once, the rest are queued
+    let queue_limits = QueueLimits {
+        max_running: env_limit("REMOTEJOBS_MAX_RUNNING_JOBS")?,
+        max_running_per_owner: env_limit("REMOTEJOBS_MAX_RUNNING_JOBS_PER_USER")?,
+    };
+    // optionally forget finished jobs after a while, or past a number of them per user
+    let retention = RetentionPolicy {
+        max_age: env_limit("REMOTEJOBS_KEEP_FINISHED_JOBS_MINUTES")?
+            .map(|minutes| Duration::from_secs(minutes as u64 * 60)),
+        max_finished_per_owner: env_limit("REMOTEJOBS_MAX_FINISHED_JOBS_PER_USER")?,
+    };
+    let config = CoordinatorConfig {
+        queue_limits,
+        retention,
+        spool: spool_config()?,
+        // optionally keep only each job's latest output in memory, when it isn't spooled to disk
+        output_budget: env_limit("REMOTEJOBS_OUTPUT_MEMORY_MB")?.map(|budget| budget << 20),
+        subscriber_queue: subscriber_queue()?,
+    };
+    let job_service = RemoteJobsService::new(1024, user_map, env_policy()?, config);
+    serve(addr, job_service).await
 }
 
-async fn serve(addr: &str) -> Result<(), Box<dyn std::error::Error>> {
+/// Read where job output is spooled from environment variables. Without `REMOTEJOBS_DATA_DIR`, output is kept in
+/// memory.
+///
+/// - `REMOTEJOBS_DATA_DIR`: directory to spool job output under, one directory per job
+/// - `REMOTEJOBS_OUTPUT_SEGMENT_MB`: size of each spool file before the next one is started, 8 by default
+/// - `REMOTEJOBS_MAX_OUTPUT_MB_PER_JOB`: output past this much is streamed but not kept. No limit by default.
+fn spool_config() -> Result<Option<SpoolConfig>, Box<dyn std::error::Error>> {
+    const MB: u64 = 1 << 20;
+    let dir = match std::env::var_os("REMOTEJOBS_DATA_DIR") {
+        Some(dir) => dir,
+        None => return Ok(None),
+    };
+    let mut config = SpoolConfig::new(dir);
+    if let Some(size) = env_limit("REMOTEJOBS_OUTPUT_SEGMENT_MB")? {
+        config.segment_size = size as u64 * MB;
+    }
+    config.max_job_bytes =
+        env_limit("REMOTEJOBS_MAX_OUTPUT_MB_PER_JOB")?.map(|max| max as u64 * MB);
+    Ok(Some(config))
+}
+
+/// Read how output is queued for each client streaming it from environment variables.
+///
+/// - `REMOTEJOBS_OUTPUT_QUEUE_LEN`: output messages queued for each client, 256 by default
+/// - `REMOTEJOBS_SLOW_OUTPUT_CLIENTS`: what happens to a client that lets its queue fill up. `catch-up` (the default)
+///   sends it the output that was kept at its own pace, `disconnect` ends its stream with an error, and `skip` drops
+///   what it has no room for and tells it how much it missed.
+fn subscriber_queue() -> Result<SubscriberQueue, Box<dyn std::error::Error>> {
+    let mut queue = SubscriberQueue::default();
+    if let Some(capacity) = env_limit("REMOTEJOBS_OUTPUT_QUEUE_LEN")? {
+        queue.capacity = capacity;
+    }
+    queue.slow = match std::env::var("REMOTEJOBS_SLOW_OUTPUT_CLIENTS").as_deref() {
+        Err(_) | Ok("catch-up") => SlowSubscriberPolicy::CatchUp,
+        Ok("disconnect") => SlowSubscriberPolicy::Disconnect,
+        Ok("skip") => SlowSubscriberPolicy::Skip,
+        Ok(policy) => {
+            return Err(format!(
+                "REMOTEJOBS_SLOW_OUTPUT_CLIENTS: {} is not catch-up, disconnect or skip",
+                policy
+            )
+            .into())
+        }
+    };
+    Ok(queue)
+}
+
+/// Read the environment policy for jobs from environment variables. By default jobs inherit the server's whole
+/// environment.
+///
+/// - `REMOTEJOBS_JOB_ENV_INHERIT`: `none`, `all`, or a comma separated list of the variables jobs inherit
+/// - `REMOTEJOBS_JOB_ENV_OVERRIDES`: comma separated `VAR=VAL` pairs set for every job, e.g. `PATH=/usr/bin:/bin`
+/// - `REMOTEJOBS_JOB_ENV_DENY`: comma separated list of variables clients can't set, e.g. `LD_PRELOAD`
+fn env_policy() -> Result<EnvPolicy, Box<dyn std::error::Error>> {
+    let list = |var| match std::env::var(var) {
+        Ok(list) => list
+            .split(',')
+            .filter(|item| !item.is_empty())
+            .map(str::to_string)
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    let inherit = match std::env::var("REMOTEJOBS_JOB_ENV_INHERIT").as_deref() {
+        Err(_) | Ok("all") => EnvInherit::Everything,
+        Ok("none") => EnvInherit::Nothing,
+        Ok(_) => EnvInherit::Only(list("REMOTEJOBS_JOB_ENV_INHERIT")),
+    };
+    let overrides = list("REMOTEJOBS_JOB_ENV_OVERRIDES")
+        .into_iter()
+        .map(|pair| match pair.split_once('=') {
+            Some((var, val)) => Ok((var.to_string(), val.to_string())),
+            None => Err(format!(
+                "REMOTEJOBS_JOB_ENV_OVERRIDES: {} is not VAR=VAL",
+                pair
+            )),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(EnvPolicy {
+        inherit,
+        overrides,
+        denied: list("REMOTEJOBS_JOB_ENV_DENY"),
+    })
+}
+
+/// Read a positive number from an environment variable, if it is set.
+fn env_limit(var: &str) -> Result<Option<usize>, Box<dyn std::error::Error>> {
+    match std::env::var(var) {
+        Ok(limit) => match limit.parse() {
+            Ok(0) | Err(_) => Err(format!("{} must be a positive number", var).into()),
+            Ok(limit) => Ok(Some(limit)),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+async fn serve(
+    addr: &str,
+    job_service: RemoteJobsService,
+) -> Result<(), Box<dyn std::error::Error>> {
     let addr = addr.parse().unwrap();
 
     // load client CA cert
@@ -57,7 +191,6 @@ async fn serve(addr: &str) -> Result<(), Box<dyn std::error::Error>> {
     let tls_config = ServerTlsConfig::new()
         .rustls_server_config(rustls_config)
         .to_owned();
-    let job_service = RemoteJobsService::default();
     let remote_jobs_server =
         RemoteJobsServer::with_interceptor(job_service, cert::extract_subj_uid);
     println!("Listening on {}", addr);
@@ -74,19 +207,39 @@ async fn serve(addr: &str) -> Result<(), Box<dyn std::error::Error>> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use protobuf::output_chunk;
     use protobuf::output_request::OutputType;
+    use protobuf::output_response::Event;
     use protobuf::status_response::JobStatus;
+    use protobuf::{attach_request, AttachRequest, WindowSize};
+    use protobuf::{attempt_outcome, restart_policy, AttemptOutcome, RestartPolicy};
     use protobuf::{remote_jobs_client::RemoteJobsClient, StartRequest};
-    use protobuf::{OutputRequest, OutputResponse, StatusRequest};
+    use protobuf::{stage_outcome, PipelineStage, StageOutcome};
+    use protobuf::{stdin_request, stdin_source, StdinRequest, StdinSource};
+    use protobuf::{step_status, workflow_status_response, WorkflowStep};
+    use protobuf::{
+        CreateScheduleRequest, DeleteScheduleRequest, ListSchedulesRequest, OverlapPolicy,
+    };
+    use protobuf::{
+        DeleteJobRequest, GetJobRequest, JobState, ListJobsRequest, OutputRequest, OutputResponse,
+        StatusRequest, WatchJobsRequest,
+    };
+    use protobuf::{PauseRequest, ResolveJobRequest, ResumeRequest, SignalRequest, StopRequest};
+    use protobuf::{WorkflowRequest, WorkflowStatusRequest};
     use std::collections::HashMap;
     use std::path::PathBuf;
-    use tonic::transport::{Certificate, Channel, ClientTlsConfig, Identity};
-    use tonic::Code;
+    use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity};
+    use tonic::{Code, Status, Streaming};
+    use uuid::Uuid;
 
     // start the server
     async fn start_server(addr: &'static str) {
+        start_server_with(addr, RemoteJobsService::default()).await;
+    }
+
+    async fn start_server_with(addr: &'static str, job_service: RemoteJobsService) {
         tokio::spawn(async move {
-            let _ = serve(addr).await;
+            let _ = serve(addr, job_service).await;
         });
         // wait a short duration so server can start before clients connect
         // TODO: do something more robust to wait for server start
@@ -106,24 +259,24 @@ mod tests {
         pathbuf.push(format!("{}.pem", user));
         let client_cert_path = pathbuf
             .canonicalize()
-            .expect(&format!("missing client cert: {:?}", pathbuf));
+            .unwrap_or_else(|_| panic!("missing client cert: {:?}", pathbuf));
         pathbuf.pop();
 
         // get user key path
         pathbuf.push(format!("{}.key", user));
         let client_key_path = pathbuf
             .canonicalize()
-            .expect(&format!("missing client key: {:?}", pathbuf));
+            .unwrap_or_else(|_| panic!("missing client key: {:?}", pathbuf));
 
         // read client cert
         let client_cert = tokio::fs::read(client_cert_path.clone())
             .await
-            .expect(&format!("failed to read {:?}", client_cert_path));
+            .unwrap_or_else(|_| panic!("failed to read {:?}", client_cert_path));
 
         // read client key
         let client_key = tokio::fs::read(client_key_path.clone())
             .await
-            .expect(&format!("failed to read {:?}", client_key_path));
+            .unwrap_or_else(|_| panic!("failed to read {:?}", client_key_path));
         let client_identity = Identity::from_pem(client_cert, client_key);
 
         ClientTlsConfig::new()
@@ -135,13 +288,23 @@ mod tests {
     async fn build_client(
         user: &'static str,
         server_addr: &'static str,
+    ) -> RemoteJobsClient<Channel> {
+        build_client_with(user, server_addr, |endpoint| endpoint).await
+    }
+
+    /// Build a client whose channel is set up further by `configure`, e.g. with smaller flow control windows.
+    async fn build_client_with(
+        user: &'static str,
+        server_addr: &'static str,
+        configure: impl FnOnce(Endpoint) -> Endpoint,
     ) -> RemoteJobsClient<Channel> {
         let tls = build_tls_config(user).await;
 
-        let channel = Channel::from_shared(format!("https://{}", server_addr))
+        let endpoint = Channel::from_shared(format!("https://{}", server_addr))
             .expect("channel parse error")
             .tls_config(tls)
-            .expect("tls config")
+            .expect("tls config");
+        let channel = configure(endpoint)
             .connect()
             .await
             .expect("channel connect");
@@ -166,6 +329,18 @@ mod tests {
             args: vec!["hello eve".into()],
             dir: "/tmp".into(),
             envs: HashMap::new(),
+            limits: None,
+            isolation: None,
+            tty: None,
+            stdin: None,
+            timeout_ms: 0,
+            stop_policy: None,
+            restart_policy: None,
+            pipeline: vec![],
+            pipefail: false,
+            priority: 0,
+            name: String::new(),
+            labels: HashMap::new(),
         });
         let response = client.start_job(request).await;
         assert!(response.is_err());
@@ -183,6 +358,18 @@ mod tests {
             args: vec!["-n".into(), "hello alice".into()],
             dir: "/tmp".into(),
             envs: HashMap::new(),
+            limits: None,
+            isolation: None,
+            tty: None,
+            stdin: None,
+            timeout_ms: 0,
+            stop_policy: None,
+            restart_policy: None,
+            pipeline: vec![],
+            pipefail: false,
+            priority: 0,
+            name: String::new(),
+            labels: HashMap::new(),
         });
         let response = client
             .start_job(request)
@@ -194,6 +381,7 @@ mod tests {
         let stream_request = tonic::Request::new(OutputRequest {
             job_id: job_id.clone(),
             output: OutputType::All.into(),
+            offset: 0,
         });
         let mut stream = client
             .stream_output(stream_request)
@@ -201,8 +389,11 @@ mod tests {
             .expect("no stream response")
             .into_inner();
         let mut received = vec![];
-        while let Some(OutputResponse { data }) = stream.message().await.unwrap() {
-            received.extend_from_slice(&data);
+        while let Some(OutputResponse {
+            event: Some(Event::Chunk(chunk)),
+        }) = stream.message().await.unwrap()
+        {
+            received.extend_from_slice(&chunk.data);
         }
         assert_eq!("hello alice", String::from_utf8_lossy(&received));
 
@@ -233,6 +424,18 @@ mod tests {
             args: vec!["hello bob".into()],
             dir: "/tmp".into(),
             envs: HashMap::new(),
+            limits: None,
+            isolation: None,
+            tty: None,
+            stdin: None,
+            timeout_ms: 0,
+            stop_policy: None,
+            restart_policy: None,
+            pipeline: vec![],
+            pipefail: false,
+            priority: 0,
+            name: String::new(),
+            labels: HashMap::new(),
         });
         let response = client.start_job(request).await;
         match response {
@@ -262,7 +465,21 @@ mod tests {
             cmd: "foo_bar_asfd".into(),
             args: vec!["-n".into(), "hello charlie".into()],
             dir: "/tmp".into(),
-            envs: HashMap::new(),
+            // jobs run unprivileged, so keep directories they can't search out of the PATH. Otherwise the
+            // lookup fails with permission denied instead of not found.
+            envs: HashMap::from([("PATH".into(), "/usr/local/bin:/usr/bin:/bin".into())]),
+            limits: None,
+            isolation: None,
+            tty: None,
+            stdin: None,
+            timeout_ms: 0,
+            stop_policy: None,
+            restart_policy: None,
+            pipeline: vec![],
+            pipefail: false,
+            priority: 0,
+            name: String::new(),
+            labels: HashMap::new(),
         });
         let response = client.start_job(request).await;
         match response {
@@ -277,6 +494,18 @@ mod tests {
             args: vec![],
             dir: "/tmp".into(),
             envs: HashMap::new(),
+            limits: None,
+            isolation: None,
+            tty: None,
+            stdin: None,
+            timeout_ms: 0,
+            stop_policy: None,
+            restart_policy: None,
+            pipeline: vec![],
+            pipefail: false,
+            priority: 0,
+            name: String::new(),
+            labels: HashMap::new(),
         });
         let response = client.start_job(request).await;
         match response {
@@ -285,4 +514,1767 @@ mod tests {
             Ok(_) => panic!("Job succeeded even with empty PATH"),
         }
     }
+
+    #[tokio::test]
+    async fn job_runs_as_mapped_user() {
+        let addr = "[::1]:50056";
+        start_server(addr).await;
+        let mut client = build_client("charlie", addr).await;
+
+        // charlie has no account on the test host, so the mock user map falls back to nobody
+        let request = tonic::Request::new(StartRequest {
+            cmd: "id".into(),
+            args: vec!["-u".into()],
+            dir: "/tmp".into(),
+            envs: HashMap::new(),
+            limits: None,
+            isolation: None,
+            tty: None,
+            stdin: None,
+            timeout_ms: 0,
+            stop_policy: None,
+            restart_policy: None,
+            pipeline: vec![],
+            pipefail: false,
+            priority: 0,
+            name: String::new(),
+            labels: HashMap::new(),
+        });
+        let job_id = client
+            .start_job(request)
+            .await
+            .expect("Bad start job response")
+            .into_inner()
+            .job_id;
+
+        let stream_request = tonic::Request::new(OutputRequest {
+            job_id,
+            output: OutputType::Stdout.into(),
+            offset: 0,
+        });
+        let mut stream = client
+            .stream_output(stream_request)
+            .await
+            .expect("no stream response")
+            .into_inner();
+        let mut received = vec![];
+        while let Some(OutputResponse {
+            event: Some(Event::Chunk(chunk)),
+        }) = stream.message().await.unwrap()
+        {
+            received.extend_from_slice(&chunk.data);
+        }
+        let nobody = joblib::types::Credentials::for_account("nobody").expect("no nobody account");
+        assert_eq!(
+            String::from_utf8_lossy(&received).trim(),
+            nobody.uid.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn attach_to_tty_job() {
+        let addr = "[::1]:50057";
+        start_server(addr).await;
+        let mut client = build_client("alice", addr).await;
+
+        let request = tonic::Request::new(StartRequest {
+            cmd: "sh".into(),
+            args: vec!["-c".into(), "read line; echo \"got $line\"".into()],
+            dir: "/tmp".into(),
+            envs: HashMap::new(),
+            limits: None,
+            isolation: None,
+            tty: Some(WindowSize { rows: 24, cols: 80 }),
+            stdin: None,
+            timeout_ms: 0,
+            stop_policy: None,
+            restart_policy: None,
+            pipeline: vec![],
+            pipefail: false,
+            priority: 0,
+            name: String::new(),
+            labels: HashMap::new(),
+        });
+        let job_id = client
+            .start_job(request)
+            .await
+            .expect("Bad start job response")
+            .into_inner()
+            .job_id;
+
+        // the first message names the job, the rest are typed into its terminal
+        let requests = vec![
+            AttachRequest {
+                request: Some(attach_request::Request::JobId(job_id)),
+            },
+            AttachRequest {
+                request: Some(attach_request::Request::Input(b"hello\n".to_vec())),
+            },
+        ];
+        let mut stream = client
+            .attach(tonic::Request::new(tokio_stream::iter(requests)))
+            .await
+            .expect("no attach response")
+            .into_inner();
+        let mut received = vec![];
+        while let Some(OutputResponse {
+            event: Some(Event::Chunk(chunk)),
+        }) = stream.message().await.unwrap()
+        {
+            received.extend_from_slice(&chunk.data);
+        }
+        // the terminal echoes the input back, and translates newlines
+        assert_eq!(String::from_utf8_lossy(&received), "hello\r\ngot hello\r\n");
+    }
+
+    #[tokio::test]
+    async fn attach_across_restarts() {
+        let addr = "[::1]:50076";
+        start_server(addr).await;
+        let mut client = build_client("alice", addr).await;
+
+        let request = tonic::Request::new(StartRequest {
+            cmd: "sh".into(),
+            args: vec!["-c".into(), "read line; echo \"got $line\"; exit 1".into()],
+            dir: "/tmp".into(),
+            envs: HashMap::new(),
+            limits: None,
+            isolation: None,
+            tty: Some(WindowSize { rows: 24, cols: 80 }),
+            stdin: None,
+            timeout_ms: 0,
+            stop_policy: None,
+            restart_policy: Some(RestartPolicy {
+                mode: restart_policy::Mode::OnFailure.into(),
+                max_attempts: 2,
+                backoff_ms: 50,
+                max_backoff_ms: 0,
+            }),
+            pipeline: vec![],
+            pipefail: false,
+            priority: 0,
+            name: String::new(),
+            labels: HashMap::new(),
+        });
+        let job_id = client
+            .start_job(request)
+            .await
+            .expect("Bad start job response")
+            .into_inner()
+            .job_id;
+
+        let (requests_tx, requests_rx) = tokio::sync::mpsc::unbounded_channel();
+        let input = |data: &[u8]| AttachRequest {
+            request: Some(attach_request::Request::Input(data.to_vec())),
+        };
+        requests_tx
+            .send(AttachRequest {
+                request: Some(attach_request::Request::JobId(job_id)),
+            })
+            .unwrap();
+        requests_tx.send(input(b"first\n")).unwrap();
+        let mut stream = client
+            .attach(tonic::Request::new(
+                tokio_stream::wrappers::UnboundedReceiverStream::new(requests_rx),
+            ))
+            .await
+            .expect("no attach response")
+            .into_inner();
+        let mut received = String::new();
+        while let Some(OutputResponse {
+            event: Some(Event::Chunk(chunk)),
+        }) = stream.message().await.unwrap()
+        {
+            received.push_str(&String::from_utf8_lossy(&chunk.data));
+            // the second attempt reads from a terminal of its own, once it has started
+            if received.ends_with("--- attempt 2 ---\n") {
+                tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+                requests_tx.send(input(b"second\n")).unwrap();
+            }
+        }
+        assert_eq!(
+            received,
+            "first\r\ngot first\r\n--- attempt 2 ---\nsecond\r\ngot second\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn write_stdin_stream() {
+        let addr = "[::1]:50058";
+        start_server(addr).await;
+        let mut client = build_client("alice", addr).await;
+
+        let request = tonic::Request::new(StartRequest {
+            cmd: "cat".into(),
+            args: vec![],
+            dir: "/tmp".into(),
+            envs: HashMap::new(),
+            limits: None,
+            isolation: None,
+            tty: None,
+            stdin: Some(StdinSource {
+                mode: stdin_source::Mode::Stream.into(),
+                data: vec![],
+            }),
+            timeout_ms: 0,
+            stop_policy: None,
+            restart_policy: None,
+            pipeline: vec![],
+            pipefail: false,
+            priority: 0,
+            name: String::new(),
+            labels: HashMap::new(),
+        });
+        let job_id = client
+            .start_job(request)
+            .await
+            .expect("Bad start job response")
+            .into_inner()
+            .job_id;
+
+        // stdin stays open between streams until one of them sends eof
+        let stdin_request = |request| StdinRequest {
+            request: Some(request),
+        };
+        for requests in [
+            vec![
+                stdin_request(stdin_request::Request::JobId(job_id.clone())),
+                stdin_request(stdin_request::Request::Data(b"hello ".to_vec())),
+            ],
+            vec![
+                stdin_request(stdin_request::Request::JobId(job_id.clone())),
+                stdin_request(stdin_request::Request::Data(b"world".to_vec())),
+                stdin_request(stdin_request::Request::Eof(true)),
+            ],
+        ] {
+            client
+                .write_stdin(tonic::Request::new(tokio_stream::iter(requests)))
+                .await
+                .expect("write stdin failed");
+        }
+
+        let stream_request = tonic::Request::new(OutputRequest {
+            job_id,
+            output: OutputType::Stdout.into(),
+            offset: 0,
+        });
+        let mut stream = client
+            .stream_output(stream_request)
+            .await
+            .expect("no stream response")
+            .into_inner();
+        let mut received = vec![];
+        while let Some(OutputResponse {
+            event: Some(Event::Chunk(chunk)),
+        }) = stream.message().await.unwrap()
+        {
+            received.extend_from_slice(&chunk.data);
+        }
+        assert_eq!(received, b"hello world");
+    }
+
+    #[tokio::test]
+    async fn pause_and_resume_job() {
+        let addr = "[::1]:50059";
+        start_server(addr).await;
+        let mut client = build_client("alice", addr).await;
+
+        let request = tonic::Request::new(StartRequest {
+            cmd: "sleep".into(),
+            args: vec!["30".into()],
+            dir: "/tmp".into(),
+            envs: HashMap::new(),
+            limits: None,
+            isolation: None,
+            tty: None,
+            stdin: None,
+            timeout_ms: 0,
+            stop_policy: None,
+            restart_policy: None,
+            pipeline: vec![],
+            pipefail: false,
+            priority: 0,
+            name: String::new(),
+            labels: HashMap::new(),
+        });
+        let job_id = client
+            .start_job(request)
+            .await
+            .expect("Bad start job response")
+            .into_inner()
+            .job_id;
+
+        client
+            .pause_job(tonic::Request::new(PauseRequest {
+                job_id: job_id.clone(),
+            }))
+            .await
+            .expect("pause failed");
+        let status = client
+            .query_status(tonic::Request::new(StatusRequest {
+                job_id: job_id.clone(),
+            }))
+            .await
+            .expect("no status response")
+            .into_inner()
+            .job_status;
+        assert_eq!(status, Some(JobStatus::Paused(true)));
+
+        // a paused job can't be paused again
+        let status = client
+            .pause_job(tonic::Request::new(PauseRequest {
+                job_id: job_id.clone(),
+            }))
+            .await
+            .expect_err("paused twice");
+        assert_eq!(status.code(), Code::FailedPrecondition);
+
+        client
+            .resume_job(tonic::Request::new(ResumeRequest {
+                job_id: job_id.clone(),
+            }))
+            .await
+            .expect("resume failed");
+        let status = client
+            .query_status(tonic::Request::new(StatusRequest {
+                job_id: job_id.clone(),
+            }))
+            .await
+            .expect("no status response")
+            .into_inner()
+            .job_status;
+        assert_eq!(status, Some(JobStatus::Running(true)));
+
+        client
+            .stop_job(tonic::Request::new(StopRequest {
+                job_id: job_id.clone(),
+                grace_period: None,
+            }))
+            .await
+            .expect("stop failed");
+
+        // nor can a stopped job be stopped again
+        let status = client
+            .stop_job(tonic::Request::new(StopRequest {
+                job_id,
+                grace_period: None,
+            }))
+            .await
+            .expect_err("stopped twice");
+        assert_eq!(status.code(), Code::FailedPrecondition);
+    }
+
+    #[tokio::test]
+    async fn signal_allowlist() {
+        let addr = "[::1]:50060";
+        start_server(addr).await;
+        let mut alice = build_client("alice", addr).await;
+        let mut bob = build_client("bob", addr).await;
+
+        let request = tonic::Request::new(StartRequest {
+            cmd: "sleep".into(),
+            args: vec!["30".into()],
+            dir: "/tmp".into(),
+            envs: HashMap::new(),
+            limits: None,
+            isolation: None,
+            tty: None,
+            stdin: None,
+            timeout_ms: 0,
+            stop_policy: None,
+            restart_policy: None,
+            pipeline: vec![],
+            pipefail: false,
+            priority: 0,
+            name: String::new(),
+            labels: HashMap::new(),
+        });
+        let job_id = alice
+            .start_job(request)
+            .await
+            .expect("Bad start job response")
+            .into_inner()
+            .job_id;
+        let signal_request = |signal| {
+            tonic::Request::new(SignalRequest {
+                job_id: job_id.clone(),
+                signal,
+            })
+        };
+
+        // SIGSEGV isn't on the task manager allowlist, and analysts can't send any signal
+        let status = alice
+            .signal_job(signal_request(libc::SIGSEGV))
+            .await
+            .expect_err("sent a signal that isn't allowed");
+        assert_eq!(status.code(), Code::PermissionDenied);
+        let status = bob
+            .signal_job(signal_request(libc::SIGUSR1))
+            .await
+            .expect_err("analyst sent a signal");
+        assert_eq!(status.code(), Code::PermissionDenied);
+
+        alice
+            .signal_job(signal_request(libc::SIGUSR1))
+            .await
+            .expect("signal failed");
+        let stream_request = tonic::Request::new(OutputRequest {
+            job_id: job_id.clone(),
+            output: OutputType::All.into(),
+            offset: 0,
+        });
+        let mut stream = alice
+            .stream_output(stream_request)
+            .await
+            .expect("no stream response")
+            .into_inner();
+        while stream.message().await.unwrap().is_some() {}
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let status = alice
+            .query_status(tonic::Request::new(StatusRequest { job_id }))
+            .await
+            .expect("no status response")
+            .into_inner()
+            .job_status;
+        assert_eq!(status, Some(JobStatus::KillSignal(libc::SIGUSR1)));
+    }
+
+    #[tokio::test]
+    async fn restart_on_failure() {
+        let addr = "[::1]:50061";
+        start_server(addr).await;
+        let mut client = build_client("alice", addr).await;
+
+        let request = tonic::Request::new(StartRequest {
+            cmd: "sh".into(),
+            args: vec!["-c".into(), "echo run; exit 2".into()],
+            dir: "/tmp".into(),
+            envs: HashMap::new(),
+            limits: None,
+            isolation: None,
+            tty: None,
+            stdin: None,
+            timeout_ms: 0,
+            stop_policy: None,
+            restart_policy: Some(RestartPolicy {
+                mode: restart_policy::Mode::OnFailure.into(),
+                max_attempts: 2,
+                backoff_ms: 50,
+                max_backoff_ms: 0,
+            }),
+            pipeline: vec![],
+            pipefail: false,
+            priority: 0,
+            name: String::new(),
+            labels: HashMap::new(),
+        });
+        let job_id = client
+            .start_job(request)
+            .await
+            .expect("Bad start job response")
+            .into_inner()
+            .job_id;
+
+        let stream_request = tonic::Request::new(OutputRequest {
+            job_id: job_id.clone(),
+            output: OutputType::All.into(),
+            offset: 0,
+        });
+        let mut stream = client
+            .stream_output(stream_request)
+            .await
+            .expect("no stream response")
+            .into_inner();
+        let mut received = vec![];
+        while let Some(OutputResponse {
+            event: Some(Event::Chunk(chunk)),
+        }) = stream.message().await.unwrap()
+        {
+            received.extend_from_slice(&chunk.data);
+        }
+        assert_eq!(
+            String::from_utf8_lossy(&received),
+            "run\n--- attempt 2 ---\nrun\n"
+        );
+
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let status = client
+            .query_status(tonic::Request::new(StatusRequest { job_id }))
+            .await
+            .expect("no status response")
+            .into_inner();
+        assert_eq!(status.job_status, Some(JobStatus::ExitCode(2)));
+        assert_eq!(status.attempt, 2);
+        assert_eq!(
+            status.previous_attempts,
+            vec![AttemptOutcome {
+                outcome: Some(attempt_outcome::Outcome::ExitCode(2)),
+                stop_reason: protobuf::StopReason::NotStopped.into(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn workflow() {
+        let addr = "[::1]:50062";
+        start_server(addr).await;
+        let mut client = build_client("alice", addr).await;
+
+        let step =
+            |name: &str, script: &str, after: &[&str], after_success: &[&str]| WorkflowStep {
+                name: name.into(),
+                job: Some(StartRequest {
+                    cmd: "sh".into(),
+                    args: vec!["-c".into(), script.into()],
+                    dir: "/tmp".into(),
+                    envs: HashMap::new(),
+                    limits: None,
+                    isolation: None,
+                    tty: None,
+                    stdin: None,
+                    timeout_ms: 0,
+                    stop_policy: None,
+                    restart_policy: None,
+                    pipeline: vec![],
+                    pipefail: false,
+                    priority: 0,
+                    name: String::new(),
+                    labels: HashMap::new(),
+                }),
+                after: after.iter().map(|&name| name.into()).collect(),
+                after_success: after_success.iter().map(|&name| name.into()).collect(),
+            };
+
+        // a cycle is rejected up front
+        let request = tonic::Request::new(WorkflowRequest {
+            steps: vec![
+                step("a", "true", &["b"], &[]),
+                step("b", "true", &["a"], &[]),
+            ],
+        });
+        let err = client.submit_workflow(request).await.unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+
+        let request = tonic::Request::new(WorkflowRequest {
+            steps: vec![
+                step("build", "exit 1", &[], &[]),
+                step("test", "true", &[], &["build"]),
+                step("cleanup", "true", &["build"], &[]),
+            ],
+        });
+        let workflow_id = client
+            .submit_workflow(request)
+            .await
+            .expect("Bad submit workflow response")
+            .into_inner()
+            .workflow_id;
+
+        let status = loop {
+            let status = client
+                .query_workflow(tonic::Request::new(WorkflowStatusRequest {
+                    workflow_id: workflow_id.clone(),
+                }))
+                .await
+                .expect("no workflow status response")
+                .into_inner();
+            if status.state() != workflow_status_response::State::Running {
+                break status;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        };
+        assert_eq!(status.state(), workflow_status_response::State::Failed);
+        let steps: Vec<_> = status
+            .steps
+            .iter()
+            .map(|step| (step.name.as_str(), step.state(), step.outcome.clone()))
+            .collect();
+        assert_eq!(
+            steps,
+            vec![
+                (
+                    "build",
+                    step_status::State::Finished,
+                    Some(step_status::Outcome::ExitCode(1))
+                ),
+                ("test", step_status::State::Skipped, None),
+                (
+                    "cleanup",
+                    step_status::State::Finished,
+                    Some(step_status::Outcome::ExitCode(0))
+                ),
+            ]
+        );
+
+        // step jobs belong to the submitter like any other job
+        let job_id = status.steps[0].job_id.clone();
+        client
+            .query_status(tonic::Request::new(StatusRequest { job_id }))
+            .await
+            .expect("no status response");
+    }
+
+    #[tokio::test]
+    async fn pipeline() {
+        let addr = "[::1]:50063";
+        start_server(addr).await;
+        let mut client = build_client("alice", addr).await;
+
+        let start_request = |tty| StartRequest {
+            cmd: "printf".into(),
+            args: vec!["a\nb\na\n".into()],
+            dir: "/tmp".into(),
+            envs: HashMap::new(),
+            limits: None,
+            isolation: None,
+            tty,
+            stdin: None,
+            timeout_ms: 0,
+            stop_policy: None,
+            restart_policy: None,
+            pipeline: vec![
+                PipelineStage {
+                    cmd: "grep".into(),
+                    args: vec!["a".into()],
+                },
+                PipelineStage {
+                    cmd: "wc".into(),
+                    args: vec!["-l".into()],
+                },
+            ],
+            pipefail: true,
+            priority: 0,
+            name: String::new(),
+            labels: HashMap::new(),
+        };
+
+        // the stages of a pipeline can't share a terminal
+        let err = client
+            .start_job(tonic::Request::new(start_request(Some(WindowSize {
+                rows: 24,
+                cols: 80,
+            }))))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+
+        let job_id = client
+            .start_job(tonic::Request::new(start_request(None)))
+            .await
+            .expect("Bad start job response")
+            .into_inner()
+            .job_id;
+
+        let stream_request = tonic::Request::new(OutputRequest {
+            job_id: job_id.clone(),
+            output: OutputType::Stdout.into(),
+            offset: 0,
+        });
+        let mut stream = client
+            .stream_output(stream_request)
+            .await
+            .expect("no stream response")
+            .into_inner();
+        let mut received = vec![];
+        while let Some(OutputResponse {
+            event: Some(Event::Chunk(chunk)),
+        }) = stream.message().await.unwrap()
+        {
+            received.extend_from_slice(&chunk.data);
+        }
+        assert_eq!(String::from_utf8_lossy(&received), "2\n");
+
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        let status = client
+            .query_status(tonic::Request::new(StatusRequest { job_id }))
+            .await
+            .expect("no status response")
+            .into_inner();
+        assert_eq!(status.job_status, Some(JobStatus::ExitCode(0)));
+        let exited = StageOutcome {
+            outcome: Some(stage_outcome::Outcome::ExitCode(0)),
+        };
+        assert_eq!(status.stages, vec![exited.clone(), exited.clone(), exited]);
+    }
+
+    #[tokio::test]
+    async fn schedules() {
+        let addr = "[::1]:50064";
+        start_server(addr).await;
+        let mut charlie = build_client("charlie", addr).await;
+        let mut alice = build_client("alice", addr).await;
+
+        let schedule_request = |cron: &str| CreateScheduleRequest {
+            cron: cron.into(),
+            timezone: "".into(),
+            job: Some(StartRequest {
+                cmd: "true".into(),
+                args: vec![],
+                dir: "/tmp".into(),
+                envs: HashMap::new(),
+                limits: None,
+                isolation: None,
+                tty: None,
+                stdin: None,
+                timeout_ms: 0,
+                stop_policy: None,
+                restart_policy: None,
+                pipeline: vec![],
+                pipefail: false,
+                priority: 0,
+                name: String::new(),
+                labels: HashMap::new(),
+            }),
+            overlap: OverlapPolicy::Skip.into(),
+        };
+
+        let err = charlie
+            .create_schedule(tonic::Request::new(schedule_request("not a cron")))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+
+        // every second
+        let schedule_id = charlie
+            .create_schedule(tonic::Request::new(schedule_request("* * * * * *")))
+            .await
+            .expect("Bad create schedule response")
+            .into_inner()
+            .schedule_id;
+
+        tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+        let schedules = charlie
+            .list_schedules(tonic::Request::new(ListSchedulesRequest {}))
+            .await
+            .expect("no list schedules response")
+            .into_inner()
+            .schedules;
+        assert_eq!(schedules.len(), 1);
+        assert_eq!(schedules[0].schedule_id, schedule_id);
+        assert_eq!(schedules[0].timezone, "UTC");
+        assert_ne!(schedules[0].next_tick_unix_ms, 0);
+        let job_id = schedules[0]
+            .history
+            .first()
+            .expect("schedule didn't start a job")
+            .clone();
+
+        // scheduled jobs belong to the schedule's owner
+        charlie
+            .query_status(tonic::Request::new(StatusRequest {
+                job_id: job_id.clone(),
+            }))
+            .await
+            .expect("no status response");
+        let err = alice
+            .query_status(tonic::Request::new(StatusRequest { job_id }))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+
+        // alice can only see and delete her own schedules
+        let schedules = alice
+            .list_schedules(tonic::Request::new(ListSchedulesRequest {}))
+            .await
+            .expect("no list schedules response")
+            .into_inner()
+            .schedules;
+        assert!(schedules.is_empty());
+        let delete_request = || {
+            tonic::Request::new(DeleteScheduleRequest {
+                schedule_id: schedule_id.clone(),
+            })
+        };
+        let err = alice.delete_schedule(delete_request()).await.unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+
+        charlie
+            .delete_schedule(delete_request())
+            .await
+            .expect("Bad delete schedule response");
+        // the schedule's owner is forgotten along with it
+        let err = charlie.delete_schedule(delete_request()).await.unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn queue() {
+        let addr = "[::1]:50065";
+        let queue_limits = QueueLimits {
+            max_running: Some(1),
+            max_running_per_owner: None,
+        };
+        start_server_with(
+            addr,
+            RemoteJobsService::new(
+                1024,
+                UserMap::default(),
+                EnvPolicy::default(),
+                CoordinatorConfig {
+                    queue_limits,
+                    ..Default::default()
+                },
+            ),
+        )
+        .await;
+        let mut alice = build_client("alice", addr).await;
+        let mut charlie = build_client("charlie", addr).await;
+
+        let start_request = |cmd: &str, args: &[&str], priority| StartRequest {
+            cmd: cmd.into(),
+            args: args.iter().map(|&arg| arg.into()).collect(),
+            dir: "/tmp".into(),
+            envs: HashMap::new(),
+            limits: None,
+            isolation: None,
+            tty: None,
+            stdin: None,
+            timeout_ms: 0,
+            stop_policy: None,
+            restart_policy: None,
+            pipeline: vec![],
+            pipefail: false,
+            priority,
+            name: String::new(),
+            labels: HashMap::new(),
+        };
+        async fn start(client: &mut RemoteJobsClient<Channel>, request: StartRequest) -> Vec<u8> {
+            client
+                .start_job(tonic::Request::new(request))
+                .await
+                .expect("Bad start job response")
+                .into_inner()
+                .job_id
+        }
+        async fn status(
+            client: &mut RemoteJobsClient<Channel>,
+            job_id: &[u8],
+        ) -> Option<JobStatus> {
+            let request = tonic::Request::new(StatusRequest {
+                job_id: job_id.to_vec(),
+            });
+            client
+                .query_status(request)
+                .await
+                .expect("no status response")
+                .into_inner()
+                .job_status
+        }
+
+        let running = start(&mut alice, start_request("sleep", &["1"], 0)).await;
+        let queued = start(&mut alice, start_request("true", &[], 0)).await;
+        let urgent = start(&mut charlie, start_request("true", &[], 5)).await;
+        assert_eq!(
+            status(&mut alice, &running).await,
+            Some(JobStatus::Running(true))
+        );
+        assert_eq!(
+            status(&mut charlie, &urgent).await,
+            Some(JobStatus::QueuePosition(1))
+        );
+        assert_eq!(
+            status(&mut alice, &queued).await,
+            Some(JobStatus::QueuePosition(2))
+        );
+
+        alice
+            .stop_job(tonic::Request::new(StopRequest {
+                job_id: queued.clone(),
+                grace_period: None,
+            }))
+            .await
+            .expect("Bad stop job response");
+        assert_eq!(
+            status(&mut alice, &queued).await,
+            Some(JobStatus::Cancelled(true))
+        );
+
+        tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+        assert_eq!(
+            status(&mut charlie, &urgent).await,
+            Some(JobStatus::ExitCode(0))
+        );
+    }
+
+    #[tokio::test]
+    async fn job_environment() {
+        let addr = "[::1]:50066";
+        let env_policy = EnvPolicy {
+            inherit: EnvInherit::Nothing,
+            overrides: vec![("PATH".into(), "/usr/bin:/bin".into())],
+            denied: vec!["LD_PRELOAD".into()],
+        };
+        start_server_with(
+            addr,
+            RemoteJobsService::new(
+                1024,
+                UserMap::default(),
+                env_policy,
+                CoordinatorConfig::default(),
+            ),
+        )
+        .await;
+        let mut client = build_client("alice", addr).await;
+
+        let start_request = |var: &str| StartRequest {
+            cmd: "true".into(),
+            args: vec![],
+            dir: "/tmp".into(),
+            envs: HashMap::from([(var.into(), "value".into())]),
+            limits: None,
+            isolation: None,
+            tty: None,
+            stdin: None,
+            timeout_ms: 0,
+            stop_policy: None,
+            restart_policy: None,
+            pipeline: vec![],
+            pipefail: false,
+            priority: 0,
+            name: String::new(),
+            labels: HashMap::new(),
+        };
+        let err = client
+            .start_job(tonic::Request::new(start_request("LD_PRELOAD")))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+
+        let job_id = client
+            .start_job(tonic::Request::new(start_request("FOO")))
+            .await
+            .expect("Bad start job response")
+            .into_inner()
+            .job_id;
+        let status = client
+            .query_status(tonic::Request::new(StatusRequest { job_id }))
+            .await
+            .expect("no status response")
+            .into_inner();
+        assert_eq!(
+            status.environment,
+            HashMap::from([
+                ("FOO".into(), "value".into()),
+                ("PATH".into(), "/usr/bin:/bin".into())
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn job_names() {
+        let addr = "[::1]:50067";
+        start_server(addr).await;
+        let mut alice = build_client("alice", addr).await;
+        let mut charlie = build_client("charlie", addr).await;
+
+        let start_request = |name: &str| StartRequest {
+            cmd: "true".into(),
+            args: vec![],
+            dir: "/tmp".into(),
+            envs: HashMap::new(),
+            limits: None,
+            isolation: None,
+            tty: None,
+            stdin: None,
+            timeout_ms: 0,
+            stop_policy: None,
+            restart_policy: None,
+            pipeline: vec![],
+            pipefail: false,
+            priority: 0,
+            name: name.into(),
+            labels: HashMap::from([("team".into(), "infra".into())]),
+        };
+        let job_id = alice
+            .start_job(tonic::Request::new(start_request("build")))
+            .await
+            .expect("Bad start job response")
+            .into_inner()
+            .job_id;
+        let err = alice
+            .start_job(tonic::Request::new(start_request("build")))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::AlreadyExists);
+        let charlies_job_id = charlie
+            .start_job(tonic::Request::new(start_request("build")))
+            .await
+            .expect("Bad start job response")
+            .into_inner()
+            .job_id;
+
+        let status = alice
+            .query_status(tonic::Request::new(StatusRequest {
+                job_id: job_id.clone(),
+            }))
+            .await
+            .expect("no status response")
+            .into_inner();
+        assert_eq!(status.name, "build");
+        assert_eq!(
+            status.labels,
+            HashMap::from([("team".into(), "infra".into())])
+        );
+
+        let uuid = Uuid::from_slice(&job_id)
+            .unwrap()
+            .to_hyphenated()
+            .to_string();
+        let charlies_uuid = Uuid::from_slice(&charlies_job_id)
+            .unwrap()
+            .to_hyphenated()
+            .to_string();
+        async fn resolve(
+            client: &mut RemoteJobsClient<Channel>,
+            job: &str,
+        ) -> Result<Vec<u8>, Status> {
+            client
+                .resolve_job(tonic::Request::new(ResolveJobRequest { job: job.into() }))
+                .await
+                .map(|response| response.into_inner().job_id)
+        }
+        assert_eq!(resolve(&mut alice, "build").await.unwrap(), job_id);
+        assert_eq!(
+            resolve(&mut charlie, "build").await.unwrap(),
+            charlies_job_id
+        );
+        assert_eq!(resolve(&mut alice, &uuid).await.unwrap(), job_id);
+        assert_eq!(resolve(&mut alice, &uuid[..8]).await.unwrap(), job_id);
+        assert_eq!(
+            resolve(&mut alice, &uuid[..8].to_uppercase())
+                .await
+                .unwrap(),
+            job_id
+        );
+        // charlie may see every job, alice only her own
+        assert_eq!(resolve(&mut charlie, &uuid[..8]).await.unwrap(), job_id);
+        let err = resolve(&mut alice, &charlies_uuid).await.unwrap_err();
+        assert_eq!(err.code(), Code::NotFound);
+        let err = resolve(&mut alice, "test").await.unwrap_err();
+        assert_eq!(err.code(), Code::NotFound);
+        let err = resolve(&mut alice, "").await.unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn list_jobs() {
+        let addr = "[::1]:50068";
+        start_server(addr).await;
+        let mut alice = build_client("alice", addr).await;
+        let mut charlie = build_client("charlie", addr).await;
+
+        let start_request = |cmd: &str, args: &[&str], env: &str| StartRequest {
+            cmd: cmd.into(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            dir: "/tmp".into(),
+            envs: HashMap::new(),
+            limits: None,
+            isolation: None,
+            tty: None,
+            stdin: None,
+            timeout_ms: 0,
+            stop_policy: None,
+            restart_policy: None,
+            pipeline: vec![],
+            pipefail: false,
+            priority: 0,
+            name: String::new(),
+            labels: HashMap::from([("env".into(), env.into())]),
+        };
+        async fn start(client: &mut RemoteJobsClient<Channel>, request: StartRequest) -> Vec<u8> {
+            client
+                .start_job(tonic::Request::new(request))
+                .await
+                .expect("Bad start job response")
+                .into_inner()
+                .job_id
+        }
+        let sleeping = start(&mut alice, start_request("sleep", &["10"], "dev")).await;
+        let mut alices_jobs = vec![sleeping.clone()];
+        for _ in 0..2 {
+            alices_jobs.push(start(&mut alice, start_request("true", &[], "prod")).await);
+        }
+        let charlies_job = start(&mut charlie, start_request("true", &[], "prod")).await;
+
+        async fn list(
+            client: &mut RemoteJobsClient<Channel>,
+            request: ListJobsRequest,
+        ) -> Result<(Vec<Vec<u8>>, String), Status> {
+            let response = client
+                .list_jobs(tonic::Request::new(request))
+                .await?
+                .into_inner();
+            let job_ids = response.jobs.into_iter().map(|job| job.job_id).collect();
+            Ok((job_ids, response.next_page_token))
+        }
+        // alice only sees her own jobs, oldest first
+        let (job_ids, next_page_token) =
+            list(&mut alice, ListJobsRequest::default()).await.unwrap();
+        assert_eq!(job_ids, alices_jobs);
+        assert!(next_page_token.is_empty());
+
+        let mut all_jobs = alices_jobs.clone();
+        all_jobs.push(charlies_job);
+        let (job_ids, _) = list(&mut charlie, ListJobsRequest::default())
+            .await
+            .unwrap();
+        assert_eq!(job_ids, all_jobs);
+
+        let (job_ids, next_page_token) = list(
+            &mut charlie,
+            ListJobsRequest {
+                page_size: 3,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(job_ids, all_jobs[..3]);
+        let (job_ids, next_page_token) = list(
+            &mut charlie,
+            ListJobsRequest {
+                page_size: 3,
+                page_token: next_page_token,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(job_ids, all_jobs[3..]);
+        assert!(next_page_token.is_empty());
+
+        let (job_ids, _) = list(
+            &mut charlie,
+            ListJobsRequest {
+                owner: "alice".into(),
+                labels: HashMap::from([("env".into(), "prod".into())]),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(job_ids, alices_jobs[1..]);
+        let (job_ids, _) = list(
+            &mut charlie,
+            ListJobsRequest {
+                states: vec![protobuf::JobState::Running.into()],
+                labels: HashMap::from([("env".into(), "dev".into())]),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(job_ids, vec![sleeping.clone()]);
+        let (job_ids, _) = list(
+            &mut charlie,
+            ListJobsRequest {
+                states: vec![protobuf::JobState::Queued.into()],
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert!(job_ids.is_empty());
+
+        let err = list(
+            &mut alice,
+            ListJobsRequest {
+                page_token: "garbage".into(),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+
+        alice
+            .stop_job(tonic::Request::new(StopRequest {
+                job_id: sleeping,
+                grace_period: None,
+            }))
+            .await
+            .expect("Bad stop job response");
+    }
+
+    #[tokio::test]
+    async fn job_retention() {
+        let addr = "[::1]:50069";
+        let retention = RetentionPolicy {
+            max_age: None,
+            max_finished_per_owner: Some(1),
+        };
+        start_server_with(
+            addr,
+            RemoteJobsService::new(
+                1024,
+                UserMap::default(),
+                EnvPolicy::default(),
+                CoordinatorConfig {
+                    retention,
+                    ..Default::default()
+                },
+            ),
+        )
+        .await;
+        let mut alice = build_client("alice", addr).await;
+        let mut charlie = build_client("charlie", addr).await;
+
+        let start_request = |cmd: &str, args: &[&str]| StartRequest {
+            cmd: cmd.into(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            dir: "/tmp".into(),
+            envs: HashMap::new(),
+            limits: None,
+            isolation: None,
+            tty: None,
+            stdin: None,
+            timeout_ms: 0,
+            stop_policy: None,
+            restart_policy: None,
+            pipeline: vec![],
+            pipefail: false,
+            priority: 0,
+            name: String::new(),
+            labels: HashMap::new(),
+        };
+        async fn start(client: &mut RemoteJobsClient<Channel>, request: StartRequest) -> Vec<u8> {
+            client
+                .start_job(tonic::Request::new(request))
+                .await
+                .expect("Bad start job response")
+                .into_inner()
+                .job_id
+        }
+        async fn wait(client: &mut RemoteJobsClient<Channel>, job_id: &[u8]) {
+            loop {
+                let status = client
+                    .query_status(tonic::Request::new(StatusRequest {
+                        job_id: job_id.to_vec(),
+                    }))
+                    .await
+                    .expect("no status response")
+                    .into_inner();
+                if matches!(
+                    status.job_status,
+                    Some(JobStatus::ExitCode(_) | JobStatus::KillSignal(_))
+                ) {
+                    return;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            }
+        }
+        async fn delete(
+            client: &mut RemoteJobsClient<Channel>,
+            job_id: &[u8],
+        ) -> Result<(), Status> {
+            client
+                .delete_job(tonic::Request::new(DeleteJobRequest {
+                    job_id: job_id.to_vec(),
+                }))
+                .await
+                .map(|_| ())
+        }
+        async fn list(client: &mut RemoteJobsClient<Channel>) -> Vec<Vec<u8>> {
+            client
+                .list_jobs(tonic::Request::new(ListJobsRequest::default()))
+                .await
+                .expect("no list jobs response")
+                .into_inner()
+                .jobs
+                .into_iter()
+                .map(|job| job.job_id)
+                .collect()
+        }
+
+        let first = start(&mut alice, start_request("true", &[])).await;
+        wait(&mut alice, &first).await;
+        // only owners may delete their jobs, whatever the scope of anyone else
+        let err = delete(&mut charlie, &first).await.unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+
+        let sleeping = start(&mut alice, start_request("sleep", &["10"])).await;
+        let err = delete(&mut alice, &sleeping).await.unwrap_err();
+        assert_eq!(err.code(), Code::FailedPrecondition);
+
+        // alice keeps one finished job, so the first one is forgotten along with who owned it
+        let second = start(&mut alice, start_request("true", &[])).await;
+        wait(&mut alice, &second).await;
+        assert_eq!(
+            list(&mut alice).await,
+            vec![sleeping.clone(), second.clone()]
+        );
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let err = alice
+            .query_status(tonic::Request::new(StatusRequest {
+                job_id: first.clone(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+
+        alice
+            .stop_job(tonic::Request::new(StopRequest {
+                job_id: sleeping.clone(),
+                grace_period: None,
+            }))
+            .await
+            .expect("Bad stop job response");
+        wait(&mut alice, &sleeping).await;
+        assert_eq!(list(&mut alice).await, vec![sleeping.clone()]);
+        delete(&mut alice, &sleeping).await.unwrap();
+        assert!(list(&mut alice).await.is_empty());
+
+        // a deleted job's owner is gone by the time the delete returns
+        let err = alice
+            .stop_job(tonic::Request::new(StopRequest {
+                job_id: sleeping.clone(),
+                grace_period: None,
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+    }
+    #[tokio::test]
+    async fn get_job() {
+        let addr = "[::1]:50070";
+        start_server(addr).await;
+        let mut alice = build_client("alice", addr).await;
+        let mut charlie = build_client("charlie", addr).await;
+
+        let job_id = charlie
+            .start_job(tonic::Request::new(StartRequest {
+                cmd: "sh".into(),
+                args: vec!["-c".into(), "echo hi; sleep 0.2".into()],
+                dir: "/tmp".into(),
+                envs: HashMap::from([("GREETING".into(), "hello".into())]),
+                limits: None,
+                isolation: None,
+                tty: None,
+                stdin: None,
+                timeout_ms: 0,
+                stop_policy: None,
+                restart_policy: None,
+                pipeline: vec![],
+                pipefail: false,
+                priority: 0,
+                name: "greet".into(),
+                labels: HashMap::new(),
+            }))
+            .await
+            .expect("Bad start job response")
+            .into_inner()
+            .job_id;
+        let get_job = |client: &mut RemoteJobsClient<Channel>| {
+            let request = tonic::Request::new(GetJobRequest {
+                job_id: job_id.clone(),
+            });
+            let mut client = client.clone();
+            async move {
+                client
+                    .get_job(request)
+                    .await
+                    .map(|response| response.into_inner().job.expect("no job info"))
+            }
+        };
+
+        let job = get_job(&mut charlie).await.unwrap();
+        assert_eq!(job.state(), JobState::Running);
+        assert_eq!(job.owner, "charlie");
+        assert_eq!(job.name, "greet");
+        assert_eq!(job.cmd, "sh");
+        assert_eq!(job.dir, "/tmp");
+        assert_eq!(job.environment.get("GREETING").unwrap(), "hello");
+        assert_ne!(job.pid, 0);
+        assert_eq!(job.finished_unix_ms, 0);
+        assert_eq!(job.resource_usage, None);
+
+        // alice may only query her own jobs
+        let err = get_job(&mut alice).await.unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+
+        let job = loop {
+            let job = get_job(&mut charlie).await.unwrap();
+            if job.state() == JobState::Exited {
+                break job;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        };
+        assert_eq!(job.outcome, Some(protobuf::job_info::Outcome::ExitCode(0)));
+        assert!(job.started_unix_ms <= job.finished_unix_ms);
+        assert!(job.duration_ms >= 200);
+        assert!(job.resource_usage.unwrap().max_rss_bytes > 0);
+    }
+    #[tokio::test]
+    async fn watch_jobs() {
+        use protobuf::job_event::Event;
+
+        let addr = "[::1]:50071";
+        start_server(addr).await;
+        let mut alice = build_client("alice", addr).await;
+        let mut charlie = build_client("charlie", addr).await;
+
+        async fn watch(
+            client: &mut RemoteJobsClient<Channel>,
+            job_id: Vec<u8>,
+            owner: &str,
+        ) -> Result<Streaming<protobuf::JobEvent>, Status> {
+            client
+                .watch_jobs(tonic::Request::new(WatchJobsRequest {
+                    job_id,
+                    owner: owner.into(),
+                }))
+                .await
+                .map(|response| response.into_inner())
+        }
+        async fn start(client: &mut RemoteJobsClient<Channel>) -> Vec<u8> {
+            client
+                .start_job(tonic::Request::new(StartRequest {
+                    cmd: "true".into(),
+                    args: vec![],
+                    dir: "/tmp".into(),
+                    envs: HashMap::new(),
+                    limits: None,
+                    isolation: None,
+                    tty: None,
+                    stdin: None,
+                    timeout_ms: 0,
+                    stop_policy: None,
+                    restart_policy: None,
+                    pipeline: vec![],
+                    pipefail: false,
+                    priority: 0,
+                    name: String::new(),
+                    labels: HashMap::new(),
+                }))
+                .await
+                .expect("Bad start job response")
+                .into_inner()
+                .job_id
+        }
+
+        // alice only hears about her own jobs, charlie may watch anyone's
+        let mut alices_events = watch(&mut alice, vec![], "").await.unwrap();
+        let mut charlies_events = watch(&mut charlie, vec![], "alice").await.unwrap();
+        let charlies_job = start(&mut charlie).await;
+        let alices_job = start(&mut alice).await;
+
+        for events in [&mut alices_events, &mut charlies_events] {
+            let mut seen = vec![];
+            for _ in 0..2 {
+                let event = events.message().await.unwrap().unwrap();
+                assert_eq!(event.job_id, alices_job);
+                assert_eq!(event.owner, "alice");
+                assert_ne!(event.unix_ms, 0);
+                seen.push(event.event.unwrap());
+            }
+            assert_eq!(seen, vec![Event::Started(1), Event::ExitCode(0)]);
+        }
+
+        alice
+            .delete_job(tonic::Request::new(DeleteJobRequest {
+                job_id: alices_job.clone(),
+            }))
+            .await
+            .expect("Bad delete job response");
+        let event = alices_events.message().await.unwrap().unwrap();
+        assert_eq!(event.job_id, alices_job);
+        assert_eq!(event.event, Some(Event::Deleted(true)));
+
+        let err = watch(&mut alice, charlies_job, "").await.unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn output_budget() {
+        let addr = "[::1]:50072";
+        start_server_with(
+            addr,
+            RemoteJobsService::new(
+                1024,
+                UserMap::default(),
+                EnvPolicy::default(),
+                CoordinatorConfig {
+                    output_budget: Some(1024),
+                    ..Default::default()
+                },
+            ),
+        )
+        .await;
+        let mut client = build_client("alice", addr).await;
+
+        let job_id = client
+            .start_job(tonic::Request::new(StartRequest {
+                cmd: "seq".into(),
+                args: vec!["20000".into()],
+                dir: "/tmp".into(),
+                envs: HashMap::new(),
+                limits: None,
+                isolation: None,
+                tty: None,
+                stdin: None,
+                timeout_ms: 0,
+                stop_policy: None,
+                restart_policy: None,
+                pipeline: vec![],
+                pipefail: false,
+                priority: 0,
+                name: String::new(),
+                labels: HashMap::new(),
+            }))
+            .await
+            .expect("Bad start job response")
+            .into_inner()
+            .job_id;
+        let request = || {
+            tonic::Request::new(OutputRequest {
+                job_id: job_id.clone(),
+                output: OutputType::Stdout.into(),
+                offset: 0,
+            })
+        };
+        // wait for the job to finish
+        let mut stream = client.stream_output(request()).await.unwrap().into_inner();
+        while stream.message().await.unwrap().is_some() {}
+
+        // a late client is told how much of the output the server dropped first
+        let mut stream = client.stream_output(request()).await.unwrap().into_inner();
+        let truncated = match stream.message().await.unwrap().unwrap().event {
+            Some(Event::Truncated(truncated)) => truncated,
+            event => panic!("unexpected output event: {:?}", event),
+        };
+        let mut received = vec![];
+        while let Some(response) = stream.message().await.unwrap() {
+            match response.event {
+                Some(Event::Chunk(chunk)) => received.extend_from_slice(&chunk.data),
+                event => panic!("unexpected output event: {:?}", event),
+            }
+        }
+        let expected: String = (1..=20000).map(|n| format!("{}\n", n)).collect();
+        assert_eq!(received.len(), 1024);
+        assert_eq!(truncated as usize + received.len(), expected.len());
+        assert!(expected.as_bytes().ends_with(&received));
+    }
+
+    #[tokio::test]
+    async fn slow_output_clients() {
+        let addr = "[::1]:50073";
+        start_server_with(
+            addr,
+            RemoteJobsService::new(
+                1024,
+                UserMap::default(),
+                EnvPolicy::default(),
+                CoordinatorConfig {
+                    subscriber_queue: SubscriberQueue {
+                        capacity: 4,
+                        slow: SlowSubscriberPolicy::Disconnect,
+                    },
+                    ..Default::default()
+                },
+            ),
+        )
+        .await;
+        // the default flow control windows hold most of the job's output. With 64KiB windows, the server can only send
+        // a sliver of it before the unread stream holds the rest up and the client's queue fills.
+        let mut client = build_client_with("alice", addr, |endpoint| {
+            endpoint
+                .initial_stream_window_size(65_535)
+                .initial_connection_window_size(65_535)
+        })
+        .await;
+        let mut watcher = build_client("alice", addr).await;
+
+        let job_id = client
+            .start_job(tonic::Request::new(StartRequest {
+                cmd: "sh".into(),
+                args: vec!["-c".into(), "read go; seq 1000000".into()],
+                dir: "/tmp".into(),
+                envs: HashMap::new(),
+                limits: None,
+                isolation: None,
+                tty: None,
+                stdin: Some(StdinSource {
+                    mode: stdin_source::Mode::Stream.into(),
+                    data: vec![],
+                }),
+                timeout_ms: 0,
+                stop_policy: None,
+                restart_policy: None,
+                pipeline: vec![],
+                pipefail: false,
+                priority: 0,
+                name: String::new(),
+                labels: HashMap::new(),
+            }))
+            .await
+            .expect("Bad start job response")
+            .into_inner()
+            .job_id;
+        let mut stream = client
+            .stream_output(tonic::Request::new(OutputRequest {
+                job_id: job_id.clone(),
+                output: OutputType::Stdout.into(),
+                offset: 0,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+
+        // the job only writes once the client is subscribed, so the client follows live output from the start rather
+        // than catching up from what was kept, at its own pace
+        let stdin_request = |request| StdinRequest {
+            request: Some(request),
+        };
+        let requests = vec![
+            stdin_request(stdin_request::Request::JobId(job_id.clone())),
+            stdin_request(stdin_request::Request::Data(b"go\n".to_vec())),
+            stdin_request(stdin_request::Request::Eof(true)),
+        ];
+        watcher
+            .write_stdin(tonic::Request::new(tokio_stream::iter(requests)))
+            .await
+            .expect("write stdin failed");
+
+        // don't read the output until the job is done, from another connection so this one's flow control doesn't
+        // get in the way
+        loop {
+            let status = watcher
+                .query_status(tonic::Request::new(StatusRequest {
+                    job_id: job_id.clone(),
+                }))
+                .await
+                .expect("no status response")
+                .into_inner()
+                .job_status;
+            if let Some(JobStatus::ExitCode(code)) = status {
+                assert_eq!(code, 0);
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+
+        // the client fell behind, so its stream ends with an error after whatever was already on its way
+        let err = loop {
+            match stream.message().await {
+                Ok(Some(_)) => {}
+                Ok(None) => panic!("stream ended without an error"),
+                Err(err) => break err,
+            }
+        };
+        assert_eq!(err.code(), Code::ResourceExhausted);
+    }
+
+    #[tokio::test]
+    async fn resume_output() {
+        let addr = "[::1]:50074";
+        start_server(addr).await;
+        let mut client = build_client("alice", addr).await;
+
+        let job_id = client
+            .start_job(tonic::Request::new(StartRequest {
+                cmd: "seq".into(),
+                args: vec!["20000".into()],
+                dir: "/tmp".into(),
+                envs: HashMap::new(),
+                limits: None,
+                isolation: None,
+                tty: None,
+                stdin: None,
+                timeout_ms: 0,
+                stop_policy: None,
+                restart_policy: None,
+                pipeline: vec![],
+                pipefail: false,
+                priority: 0,
+                name: String::new(),
+                labels: HashMap::new(),
+            }))
+            .await
+            .expect("Bad start job response")
+            .into_inner()
+            .job_id;
+        let expected: String = (1..=20000).map(|n| format!("{}\n", n)).collect();
+
+        // a client that stopped partway picks up at the same byte, and each message says where its data starts
+        for offset in [0, 12345] {
+            let mut stream = client
+                .stream_output(tonic::Request::new(OutputRequest {
+                    job_id: job_id.clone(),
+                    output: OutputType::Stdout.into(),
+                    offset,
+                }))
+                .await
+                .unwrap()
+                .into_inner();
+            let mut received = vec![];
+            while let Some(OutputResponse {
+                event: Some(Event::Chunk(chunk)),
+            }) = stream.message().await.unwrap()
+            {
+                assert_eq!(chunk.offset, offset + received.len() as u64);
+                received.extend_from_slice(&chunk.data);
+            }
+            assert_eq!(received, expected.as_bytes()[offset as usize..]);
+        }
+    }
+
+    #[tokio::test]
+    async fn tagged_output() {
+        let addr = "[::1]:50075";
+        start_server(addr).await;
+        let mut client = build_client("alice", addr).await;
+
+        let job_id = client
+            .start_job(tonic::Request::new(StartRequest {
+                cmd: "sh".into(),
+                args: vec!["-c".into(), "echo out; sleep 0.1; echo err >&2".into()],
+                dir: "/tmp".into(),
+                envs: HashMap::new(),
+                limits: None,
+                isolation: None,
+                tty: None,
+                stdin: None,
+                timeout_ms: 0,
+                stop_policy: None,
+                restart_policy: None,
+                pipeline: vec![],
+                pipefail: false,
+                priority: 0,
+                name: String::new(),
+                labels: HashMap::new(),
+            }))
+            .await
+            .expect("Bad start job response")
+            .into_inner()
+            .job_id;
+        let mut stream = client
+            .stream_output(tonic::Request::new(OutputRequest {
+                job_id,
+                output: OutputType::All.into(),
+                offset: 0,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        let mut chunks = vec![];
+        while let Some(OutputResponse {
+            event: Some(Event::Chunk(chunk)),
+        }) = stream.message().await.unwrap()
+        {
+            chunks.push(chunk);
+        }
+
+        // the merged stream says where each chunk came from, and when
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|chunk| (chunk.source(), chunk.seq, &chunk.data[..]))
+                .collect::<Vec<_>>(),
+            [
+                (output_chunk::Source::Stdout, 0, &b"out\n"[..]),
+                (output_chunk::Source::Stderr, 1, &b"err\n"[..])
+            ]
+        );
+        assert!(chunks[0].captured_unix_ms > 0);
+        assert!(chunks[1].captured_unix_ms >= chunks[0].captured_unix_ms + 50);
+    }
 }
@@ -1,20 +1,53 @@
 mod authorizer;
+mod user_map;
 
-use self::authorizer::{Action, Authorizer, ExistingJobAction};
+use self::authorizer::{Action, Authorizer, ExistingJobAction, Scope};
+pub use self::user_map::UserMap;
 use crate::UserExtension;
 
 use futures::Stream;
-use joblib::JobCoordinator;
+use joblib::{
+    events::{
+        JobInfo, JobListing, JobState, JobSummary, LifecycleEvent, LifecycleEventKind, OutputEvent,
+        OutputSource, StepState, WorkflowState,
+    },
+    types::{
+        Backoff, CoordinatorConfig, EnvPolicy, EventFilter, JobFilter, JobId, JobPage, JobSpec,
+        OverlapPolicy, PipelineStage, RestartPolicy, ScheduleSpec, StdinSource, StopPolicy,
+        WindowSize, WorkflowSpec, WorkflowStep,
+    },
+    JobCoordinator, JobScheduler,
+};
 use protobuf::{
+    attach_request,
+    attempt_outcome::Outcome,
     output_request::OutputType,
     remote_jobs_server::RemoteJobs,
-    status_response::JobStatus::{ExitCode, KillSignal, Running},
-    OutputRequest, OutputResponse, StartRequest, StartResponse, StatusRequest, StatusResponse,
-    StopRequest, StopResponse,
+    restart_policy, stage_outcome,
+    status_response::JobStatus::{
+        Cancelled, ExitCode, FailedToStart, KillSignal, Paused, QueuePosition, Restarting, Running,
+    },
+    stdin_request, stdin_source, step_status, workflow_status_response, AttachRequest,
+    CreateScheduleRequest, CreateScheduleResponse, DeleteJobRequest, DeleteJobResponse,
+    DeleteScheduleRequest, DeleteScheduleResponse, GetJobRequest, GetJobResponse, JobEvent,
+    ListJobsRequest, ListJobsResponse, ListSchedulesRequest, ListSchedulesResponse, OutputChunk,
+    OutputRequest, OutputResponse, PauseRequest, PauseResponse, ResolveJobRequest,
+    ResolveJobResponse, ResumeRequest, ResumeResponse, SignalRequest, SignalResponse, StartRequest,
+    StartResponse, StatusRequest, StatusResponse, StdinRequest, StdinResponse, StopReason,
+    StopRequest, StopResponse, WatchJobsRequest, WorkflowRequest, WorkflowResponse,
+    WorkflowStatusRequest, WorkflowStatusResponse,
+};
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, SystemTime},
 };
-use std::pin::Pin;
-use tokio_stream::{wrappers::UnboundedReceiverStream, StreamExt};
-use tonic::{self, Request, Response, Status};
+use tokio_stream::{
+    wrappers::{ReceiverStream, UnboundedReceiverStream},
+    StreamExt,
+};
+use tonic::{self, Request, Response, Status, Streaming};
 use uuid::Uuid;
 
 pub type UserId = String;
@@ -22,6 +55,7 @@ pub type UserId = String;
 /// A job service for remote job start/stop/status/output api.
 ///
 /// Jobs are assigned an owner when they start - the `user id` of the user who started the job.
+/// Jobs run as the unix user that the owner maps to in the `UserMap`.
 ///
 /// Authorization is provided by a mock authz database interface
 ///
@@ -31,27 +65,135 @@ pub type UserId = String;
 ///   the responses as well.
 pub struct RemoteJobsService {
     coordinator: JobCoordinator,
-    authorizer: Authorizer, // tonic wraps the struct in Arc internally, so we don't need Arc
+    scheduler: JobScheduler,
+    authorizer: Arc<Authorizer>, // shared with the task that hands scheduled jobs to their schedule's owner
+    user_map: UserMap,
+    env_policy: EnvPolicy,
 }
 
 impl Default for RemoteJobsService {
     fn default() -> Self {
-        Self::new(1024)
+        Self::new(
+            1024,
+            UserMap::default(),
+            EnvPolicy::default(),
+            CoordinatorConfig::default(),
+        )
     }
 }
 
 impl RemoteJobsService {
-    pub fn new(channel_capacity: usize) -> Self {
+    /// Every job's environment is built with `env_policy`, and the coordinator runs with `config`: jobs past its queue
+    /// limits wait in its queue, where each user's jobs share it fairly with other users' jobs, and finished jobs are
+    /// kept as long as its retention policy says. Forgetting a job also forgets who owns it.
+    pub fn new(
+        channel_capacity: usize,
+        user_map: UserMap,
+        env_policy: EnvPolicy,
+        config: CoordinatorConfig,
+    ) -> Self {
+        let authorizer = Arc::new(Authorizer::new());
+        // the coordinator keeps the authorizer's job owners in step with its jobs
+        let coordinator =
+            JobCoordinator::spawn_with_config(channel_capacity, config, authorizer.clone());
+        // scheduled jobs are started as their schedule's owner, so the coordinator registers them like any other. The
+        // scheduler registers the schedules themselves.
+        let scheduler = JobScheduler::spawn_with_registry(
+            coordinator.clone(),
+            channel_capacity,
+            authorizer.clone(),
+        );
         Self {
-            authorizer: Authorizer::new(),
-            coordinator: JobCoordinator::spawn(channel_capacity),
+            authorizer,
+            coordinator,
+            scheduler,
+            user_map,
+            env_policy,
+        }
+    }
+
+    /// Build the spec for a job started by `user_id`.
+    fn job_spec(&self, user_id: &UserId, request: StartRequest) -> Result<JobSpec, Status> {
+        let StartRequest {
+            cmd,
+            args,
+            dir,
+            envs,
+            limits,
+            isolation,
+            tty,
+            stdin,
+            timeout_ms,
+            stop_policy,
+            restart_policy,
+            pipeline,
+            pipefail,
+            priority,
+            name,
+            labels,
+        } = request;
+
+        let credentials = self
+            .user_map
+            .credentials(user_id)
+            .map_err(|err| Status::permission_denied(format!("No unix user for job: {}", err)))?;
+
+        let envs = Vec::from_iter(envs);
+        let mut spec = JobSpec::new(cmd, args, dir, envs);
+        spec.pipeline = pipeline
+            .into_iter()
+            .map(|stage| PipelineStage {
+                cmd: stage.cmd,
+                args: stage.args,
+            })
+            .collect();
+        spec.pipefail = pipefail;
+        spec.credentials = credentials;
+        spec.env_policy = self.env_policy.clone();
+        spec.owner = Some(user_id.clone());
+        spec.priority = priority;
+        spec.name = (!name.is_empty()).then_some(name);
+        spec.labels = labels.into_iter().collect();
+        if let Some(limits) = limits {
+            spec.limits = resource_limits(limits)?;
+        }
+        if let Some(isolation) = isolation {
+            spec.isolation = joblib::types::Isolation {
+                pid: isolation.pid,
+                mount: isolation.mount,
+                network: isolation.network,
+                uts: isolation.uts,
+                ipc: isolation.ipc,
+            };
+        }
+        if let Some(tty) = tty {
+            spec.tty = Some(window_size(tty)?);
+        }
+        if let Some(stdin) = stdin {
+            spec.stdin = match stdin.mode() {
+                stdin_source::Mode::Null => StdinSource::Null,
+                stdin_source::Mode::Bytes => StdinSource::Bytes(stdin.data.into()),
+                stdin_source::Mode::Stream => StdinSource::Stream,
+            };
+        }
+        if timeout_ms != 0 {
+            spec.timeout = Some(Duration::from_millis(timeout_ms));
+        }
+        if let Some(stop_policy) = stop_policy {
+            spec.stop_policy = self::stop_policy(stop_policy)?;
+        }
+        if let Some(restart_policy) = restart_policy {
+            (spec.restart_policy, spec.restart_backoff) = self::restart_policy(restart_policy);
         }
+        Ok(spec)
     }
 }
 
 #[tonic::async_trait]
 impl RemoteJobs for RemoteJobsService {
     type StreamOutputStream = Pin<Box<dyn Stream<Item = Result<OutputResponse, Status>> + Send>>;
+    type AttachStream = Pin<Box<dyn Stream<Item = Result<OutputResponse, Status>> + Send>>;
+    type WatchJobsStream = Pin<Box<dyn Stream<Item = Result<JobEvent, Status>> + Send>>;
 
     async fn start_job(
         &self,
@@ -69,17 +211,9 @@ impl RemoteJobs for RemoteJobsService {
             return Err(Status::permission_denied("Permission denied"));
         }
 
-        let StartRequest {
-            cmd,
-            args,
-            dir,
-            envs,
-        } = req.into_inner();
-
-        let envs = Vec::from_iter(envs);
-        let job_id = self.coordinator.start_job(cmd, args, dir, envs).await?;
-
-        self.authorizer.add_job(job_id, &user_id);
+        let spec = self.job_spec(&user_id, req.into_inner())?;
+        // the job's owner is known to the authorizer before the job id is returned
+        let job_id = self.coordinator.start_job(spec).await?;
         Ok(Response::new(StartResponse {
             job_id: job_id.as_bytes().to_vec(),
         }))
@@ -93,7 +227,10 @@ impl RemoteJobs for RemoteJobsService {
             .user_id
             .clone();
 
-        let job_id = req.into_inner().job_id;
+        let StopRequest {
+            job_id,
+            grace_period,
+        } = req.into_inner();
         let job_id =
             Uuid::from_slice(&job_id).map_err(|err| Status::invalid_argument(err.to_string()))?;
 
@@ -108,16 +245,375 @@ impl RemoteJobs for RemoteJobsService {
             return Err(Status::permission_denied("Permission denied"));
         }
 
+        let grace = grace_period.map(|grace_period| Duration::from_millis(grace_period.millis));
         self.coordinator
-            .stop_job(job_id)
+            .stop_job_with_grace(job_id, grace)
             .await
             .map_err(|err| match err {
-                joblib::error::Error::AlreadyStopped => Status::internal(err.to_string()),
-                joblib::error::Error::DoesNotExist => unreachable!(), // no job, so authz should have failed
+                joblib::error::Error::AlreadyStopped => {
+                    Status::failed_precondition(err.to_string())
+                }
+                // deleted or forgotten since authz was checked
+                joblib::error::Error::DoesNotExist => Status::not_found(err.to_string()),
+                _ => Status::internal(err.to_string()),
             })?;
         Ok(Response::new(StopResponse {})) // empty response on success
     }
 
+    async fn signal_job(
+        &self,
+        req: Request<SignalRequest>,
+    ) -> Result<Response<SignalResponse>, Status> {
+        let user_id = req
+            .extensions()
+            .get::<UserExtension>()
+            .unwrap()
+            .user_id
+            .clone();
+
+        let SignalRequest { job_id, signal } = req.into_inner();
+        let job_id =
+            Uuid::from_slice(&job_id).map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        // check authz, which includes the signal allowlist
+        if !self.authorizer.is_authorized(
+            &user_id,
+            Action::ExistingJob {
+                job_id,
+                inner_action: ExistingJobAction::SignalJob(signal),
+            },
+        ) {
+            return Err(Status::permission_denied("Permission denied"));
+        }
+
+        self.coordinator
+            .signal_job(job_id, signal)
+            .await
+            .map_err(|err| match err {
+                joblib::error::Error::InvalidSignal(_) => Status::invalid_argument(err.to_string()),
+                _ => Status::failed_precondition(err.to_string()),
+            })?;
+        Ok(Response::new(SignalResponse {}))
+    }
+
+    async fn pause_job(
+        &self,
+        req: Request<PauseRequest>,
+    ) -> Result<Response<PauseResponse>, Status> {
+        let user_id = req
+            .extensions()
+            .get::<UserExtension>()
+            .unwrap()
+            .user_id
+            .clone();
+
+        let job_id = req.into_inner().job_id;
+        let job_id =
+            Uuid::from_slice(&job_id).map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        // check authz
+        if !self.authorizer.is_authorized(
+            &user_id,
+            Action::ExistingJob {
+                job_id,
+                inner_action: ExistingJobAction::PauseJob,
+            },
+        ) {
+            return Err(Status::permission_denied("Permission denied"));
+        }
+
+        self.coordinator
+            .pause_job(job_id)
+            .await
+            .map_err(|err| Status::failed_precondition(err.to_string()))?;
+        Ok(Response::new(PauseResponse {}))
+    }
+
+    async fn resume_job(
+        &self,
+        req: Request<ResumeRequest>,
+    ) -> Result<Response<ResumeResponse>, Status> {
+        let user_id = req
+            .extensions()
+            .get::<UserExtension>()
+            .unwrap()
+            .user_id
+            .clone();
+
+        let job_id = req.into_inner().job_id;
+        let job_id =
+            Uuid::from_slice(&job_id).map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        // check authz
+        if !self.authorizer.is_authorized(
+            &user_id,
+            Action::ExistingJob {
+                job_id,
+                inner_action: ExistingJobAction::ResumeJob,
+            },
+        ) {
+            return Err(Status::permission_denied("Permission denied"));
+        }
+
+        self.coordinator
+            .resume_job(job_id)
+            .await
+            .map_err(|err| Status::failed_precondition(err.to_string()))?;
+        Ok(Response::new(ResumeResponse {}))
+    }
+
+    async fn resolve_job(
+        &self,
+        req: Request<ResolveJobRequest>,
+    ) -> Result<Response<ResolveJobResponse>, Status> {
+        let user_id = req
+            .extensions()
+            .get::<UserExtension>()
+            .unwrap()
+            .user_id
+            .clone();
+
+        let job = req.into_inner().job;
+        if job.is_empty() {
+            return Err(Status::invalid_argument("No job given"));
+        }
+        // a full job id or a name are exact matches, otherwise it's a prefix of a job id
+        let candidates = if let Ok(job_id) = Uuid::parse_str(&job) {
+            vec![job_id]
+        } else if let Ok(job_id) = self
+            .coordinator
+            .find_job_by_name(Some(user_id.clone()), job.clone())
+            .await
+        {
+            vec![job_id]
+        } else {
+            self.coordinator
+                .find_jobs_by_id_prefix(job.to_lowercase())
+                .await
+        };
+
+        // check authz. Jobs the user may not query are left out, as if they didn't exist.
+        let matches: Vec<_> = candidates
+            .into_iter()
+            .filter(|&job_id| {
+                self.authorizer.is_authorized(
+                    &user_id,
+                    Action::ExistingJob {
+                        job_id,
+                        inner_action: ExistingJobAction::QueryStatus,
+                    },
+                )
+            })
+            .collect();
+        match matches[..] {
+            [job_id] => Ok(Response::new(ResolveJobResponse {
+                job_id: job_id.as_bytes().to_vec(),
+            })),
+            [] => Err(Status::not_found(format!("No job matches {}", job))),
+            _ => Err(Status::invalid_argument(format!(
+                "{} matches {} jobs",
+                job,
+                matches.len()
+            ))),
+        }
+    }
+
+    async fn list_jobs(
+        &self,
+        req: Request<ListJobsRequest>,
+    ) -> Result<Response<ListJobsResponse>, Status> {
+        let user_id = req
+            .extensions()
+            .get::<UserExtension>()
+            .unwrap()
+            .user_id
+            .clone();
+
+        let ListJobsRequest {
+            owner,
+            states,
+            labels,
+            started_after_unix_ms,
+            started_before_unix_ms,
+            page_size,
+            page_token,
+        } = req.into_inner();
+        let states = states
+            .into_iter()
+            .map(|state| {
+                protobuf::JobState::from_i32(state)
+                    .map(job_state)
+                    .ok_or_else(|| Status::invalid_argument(format!("Unknown job state {}", state)))
+            })
+            .collect::<Result<_, _>>()?;
+        let since_epoch = |unix_ms| {
+            (unix_ms != 0).then(|| SystemTime::UNIX_EPOCH + Duration::from_millis(unix_ms))
+        };
+        let mut filter = JobFilter {
+            owner: (!owner.is_empty()).then_some(owner),
+            states,
+            labels: labels.into_iter().collect(),
+            started_after: since_epoch(started_after_unix_ms),
+            started_before: since_epoch(started_before_unix_ms),
+        };
+        let after = if page_token.is_empty() {
+            None
+        } else {
+            Some(parse_page_token(&page_token)?)
+        };
+        let page_size = match page_size {
+            0 => 100,
+            page_size => page_size.min(1000) as usize,
+        };
+
+        // check authz. Only the jobs the user may query, as if the rest didn't exist.
+        match self.authorizer.query_scope(&user_id) {
+            Some(Scope::All) => {}
+            Some(Scope::Owner) if filter.owner.as_ref().is_none_or(|owner| *owner == user_id) => {
+                filter.owner = Some(user_id);
+            }
+            _ => return Ok(Response::new(ListJobsResponse::default())),
+        }
+
+        let page = JobPage {
+            after,
+            limit: Some(page_size),
+        };
+        let JobListing { jobs, more } = self.coordinator.list_jobs(filter, page).await;
+        let next_page_token = match jobs.last() {
+            Some(last) if more => self::page_token(last),
+            _ => String::new(),
+        };
+        Ok(Response::new(ListJobsResponse {
+            jobs: jobs.into_iter().map(job_summary).collect(),
+            next_page_token,
+        }))
+    }
+
+    async fn delete_job(
+        &self,
+        req: Request<DeleteJobRequest>,
+    ) -> Result<Response<DeleteJobResponse>, Status> {
+        let user_id = req
+            .extensions()
+            .get::<UserExtension>()
+            .unwrap()
+            .user_id
+            .clone();
+
+        let job_id = req.into_inner().job_id;
+        let job_id =
+            Uuid::from_slice(&job_id).map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        // check authz
+        if !self.authorizer.is_authorized(
+            &user_id,
+            Action::ExistingJob {
+                job_id,
+                inner_action: ExistingJobAction::DeleteJob,
+            },
+        ) {
+            return Err(Status::permission_denied("Permission denied"));
+        }
+
+        self.coordinator
+            .delete_job(job_id)
+            .await
+            .map_err(|err| match err {
+                joblib::error::Error::NotFinished => Status::failed_precondition(err.to_string()),
+                joblib::error::Error::DoesNotExist => Status::not_found(err.to_string()),
+                _ => Status::internal(err.to_string()),
+            })?;
+        // the job's owner was forgotten along with the job
+        Ok(Response::new(DeleteJobResponse {}))
+    }
+
+    async fn get_job(
+        &self,
+        req: Request<GetJobRequest>,
+    ) -> Result<Response<GetJobResponse>, Status> {
+        let user_id = req
+            .extensions()
+            .get::<UserExtension>()
+            .unwrap()
+            .user_id
+            .clone();
+
+        let job_id = req.into_inner().job_id;
+        let job_id =
+            Uuid::from_slice(&job_id).map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        // check authz
+        if !self.authorizer.is_authorized(
+            &user_id,
+            Action::ExistingJob {
+                job_id,
+                inner_action: ExistingJobAction::QueryStatus,
+            },
+        ) {
+            return Err(Status::permission_denied("Permission denied"));
+        }
+
+        let info = self
+            .coordinator
+            .get_job_info(job_id)
+            .await
+            .map_err(|err| Status::internal(err.to_string()))?;
+        Ok(Response::new(GetJobResponse {
+            job: Some(job_info(info)),
+        }))
+    }
+
+    async fn watch_jobs(
+        &self,
+        req: Request<WatchJobsRequest>,
+    ) -> Result<Response<Self::WatchJobsStream>, Status> {
+        let user_id = req
+            .extensions()
+            .get::<UserExtension>()
+            .unwrap()
+            .user_id
+            .clone();
+
+        let WatchJobsRequest { job_id, owner } = req.into_inner();
+        let job_id = if job_id.is_empty() {
+            None
+        } else {
+            let job_id = Uuid::from_slice(&job_id)
+                .map_err(|err| Status::invalid_argument(err.to_string()))?;
+            // check authz
+            if !self.authorizer.is_authorized(
+                &user_id,
+                Action::ExistingJob {
+                    job_id,
+                    inner_action: ExistingJobAction::QueryStatus,
+                },
+            ) {
+                return Err(Status::permission_denied("Permission denied"));
+            }
+            Some(job_id)
+        };
+        let filter = EventFilter {
+            job_id,
+            owner: (!owner.is_empty()).then_some(owner),
+        };
+
+        // check authz for every event. Jobs are authorized by the owner the event names, since a deleted job's
+        // owner may be forgotten by the time its event gets here.
+        let authorizer = self.authorizer.clone();
+        let events = self.coordinator.subscribe_events(filter).await;
+        let events = UnboundedReceiverStream::from(events);
+        let response_stream = events.filter_map(move |event| {
+            let owner = event.owner.clone()?;
+            authorizer
+                .is_authorized(&user_id, Action::WatchJob { owner })
+                .then(|| Ok(job_event(event)))
+        });
+        Ok(Response::new(
+            Box::pin(response_stream) as Self::WatchJobsStream
+        ))
+    }
+
     async fn query_status(
         &self,
         req: Request<StatusRequest>,
@@ -144,22 +640,298 @@ impl RemoteJobs for RemoteJobsService {
             return Err(Status::permission_denied("Permission denied"));
         }
 
-        let job_status = self
+        let details = self
             .coordinator
-            .get_job_status(job_id)
+            .get_job_details(job_id)
             .await
-            .map_err(|err| Status::internal(err.to_string()))?;
-        let status = match job_status {
-            joblib::events::JobStatus::Running => Running(true),
-            joblib::events::JobStatus::Exited { code } => ExitCode(code),
-            joblib::events::JobStatus::Killed { signal } => KillSignal(signal),
+            .map_err(|err| match err {
+                // deleted or forgotten since authz was checked
+                joblib::error::Error::DoesNotExist => Status::not_found(err.to_string()),
+                _ => Status::internal(err.to_string()),
+            })?;
+        let (status, stopped) = match details.status {
+            joblib::events::JobStatus::Running => (Running(true), None),
+            joblib::events::JobStatus::Paused => (Paused(true), None),
+            joblib::events::JobStatus::Restarting => (Restarting(true), None),
+            joblib::events::JobStatus::Queued { position } => (QueuePosition(position), None),
+            joblib::events::JobStatus::Cancelled => (Cancelled(true), None),
+            joblib::events::JobStatus::FailedToStart => (FailedToStart(true), None),
+            joblib::events::JobStatus::Exited { code, stopped } => (ExitCode(code), stopped),
+            joblib::events::JobStatus::Killed { signal, stopped } => (KillSignal(signal), stopped),
         };
+        let previous_attempts = details
+            .attempts
+            .previous
+            .into_iter()
+            .map(|status| {
+                let (outcome, stopped) = match status {
+                    joblib::events::JobStatus::Exited { code, stopped } => {
+                        (Outcome::ExitCode(code), stopped)
+                    }
+                    joblib::events::JobStatus::Killed { signal, stopped } => {
+                        (Outcome::KillSignal(signal), stopped)
+                    }
+                    _ => unreachable!(), // only finished attempts are kept
+                };
+                protobuf::AttemptOutcome {
+                    outcome: Some(outcome),
+                    stop_reason: stop_reason(stopped).into(),
+                }
+            })
+            .collect();
+        let stages = details
+            .stages
+            .into_iter()
+            .map(|status| protobuf::StageOutcome {
+                outcome: match status {
+                    joblib::events::JobStatus::Exited { code, .. } => {
+                        Some(stage_outcome::Outcome::ExitCode(code))
+                    }
+                    joblib::events::JobStatus::Killed { signal, .. } => {
+                        Some(stage_outcome::Outcome::KillSignal(signal))
+                    }
+                    _ => None,
+                },
+            })
+            .collect();
+        let isolation = details.isolation;
         let status_response = StatusResponse {
             job_status: Some(status),
+            isolation: Some(protobuf::Isolation {
+                pid: isolation.pid,
+                mount: isolation.mount,
+                network: isolation.network,
+                uts: isolation.uts,
+                ipc: isolation.ipc,
+            }),
+            stop_reason: stop_reason(stopped).into(),
+            attempt: details.attempts.attempt,
+            previous_attempts,
+            stages,
+            environment: HashMap::from_iter(details.environment),
+            name: details.metadata.name.unwrap_or_default(),
+            labels: HashMap::from_iter(details.metadata.labels),
         };
         Ok(Response::new(status_response))
     }
 
+    async fn submit_workflow(
+        &self,
+        req: Request<WorkflowRequest>,
+    ) -> Result<Response<WorkflowResponse>, Status> {
+        let user_id = req
+            .extensions()
+            .get::<UserExtension>()
+            .unwrap()
+            .user_id
+            .clone();
+
+        // check authz, a workflow just starts jobs
+        if !self.authorizer.is_authorized(&user_id, Action::StartJob) {
+            return Err(Status::permission_denied("Permission denied"));
+        }
+
+        let mut steps = vec![];
+        for step in req.into_inner().steps {
+            let job = step.job.ok_or_else(|| {
+                Status::invalid_argument(format!("step {:?} has no job", step.name))
+            })?;
+            steps.push(WorkflowStep {
+                name: step.name,
+                spec: self.job_spec(&user_id, job)?,
+                after: step.after,
+                after_success: step.after_success,
+            });
+        }
+        let workflow_id = self
+            .coordinator
+            .submit_workflow(WorkflowSpec { steps })
+            .await
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+        let status = self
+            .coordinator
+            .get_workflow_status(workflow_id)
+            .await
+            .map_err(|err| Status::internal(err.to_string()))?;
+        Ok(Response::new(WorkflowResponse {
+            workflow_id: workflow_id.as_bytes().to_vec(),
+            steps: status.steps.into_iter().map(step_status).collect(),
+        }))
+    }
+
+    async fn query_workflow(
+        &self,
+        req: Request<WorkflowStatusRequest>,
+    ) -> Result<Response<WorkflowStatusResponse>, Status> {
+        let user_id = req
+            .extensions()
+            .get::<UserExtension>()
+            .unwrap()
+            .user_id
+            .clone();
+
+        let workflow_id = req.into_inner().workflow_id;
+        let workflow_id = Uuid::from_slice(&workflow_id)
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        // check authz
+        if !self
+            .authorizer
+            .is_authorized(&user_id, Action::QueryWorkflow { workflow_id })
+        {
+            return Err(Status::permission_denied("Permission denied"));
+        }
+
+        let status = self
+            .coordinator
+            .get_workflow_status(workflow_id)
+            .await
+            .map_err(|err| Status::internal(err.to_string()))?;
+        let state = match status.state {
+            WorkflowState::Running => workflow_status_response::State::Running,
+            WorkflowState::Succeeded => workflow_status_response::State::Succeeded,
+            WorkflowState::Failed => workflow_status_response::State::Failed,
+        };
+        Ok(Response::new(WorkflowStatusResponse {
+            state: state.into(),
+            steps: status.steps.into_iter().map(step_status).collect(),
+        }))
+    }
+
+    async fn create_schedule(
+        &self,
+        req: Request<CreateScheduleRequest>,
+    ) -> Result<Response<CreateScheduleResponse>, Status> {
+        let user_id = req
+            .extensions()
+            .get::<UserExtension>()
+            .unwrap()
+            .user_id
+            .clone();
+
+        // check authz. Every job the schedule starts is started as this user.
+        if !self.authorizer.is_authorized(&user_id, Action::StartJob) {
+            return Err(Status::permission_denied("Permission denied"));
+        }
+
+        let request = req.into_inner();
+        let overlap = match request.overlap() {
+            protobuf::OverlapPolicy::Skip => OverlapPolicy::Skip,
+            protobuf::OverlapPolicy::Queue => OverlapPolicy::Queue,
+            protobuf::OverlapPolicy::Allow => OverlapPolicy::Allow,
+        };
+        let CreateScheduleRequest {
+            cron,
+            timezone,
+            job,
+            ..
+        } = request;
+        let job = job.ok_or_else(|| Status::invalid_argument("Schedule has no job"))?;
+        if !job.name.is_empty() {
+            // every tick starts a new job, and they can't all have the same name
+            return Err(Status::invalid_argument("Scheduled jobs can't have a name"));
+        }
+        let spec = ScheduleSpec {
+            cron,
+            timezone: if timezone.is_empty() {
+                "UTC".to_string()
+            } else {
+                timezone
+            },
+            job: self.job_spec(&user_id, job)?,
+            overlap,
+        };
+        let schedule_id = self
+            .scheduler
+            .create_schedule(spec)
+            .await
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+        Ok(Response::new(CreateScheduleResponse {
+            schedule_id: schedule_id.as_bytes().to_vec(),
+        }))
+    }
+
+    async fn list_schedules(
+        &self,
+        req: Request<ListSchedulesRequest>,
+    ) -> Result<Response<ListSchedulesResponse>, Status> {
+        let user_id = req
+            .extensions()
+            .get::<UserExtension>()
+            .unwrap()
+            .user_id
+            .clone();
+
+        // only the schedules the user may query
+        let schedules = self
+            .scheduler
+            .list_schedules()
+            .await
+            .into_iter()
+            .filter(|schedule| {
+                self.authorizer.is_authorized(
+                    &user_id,
+                    Action::QuerySchedule {
+                        schedule_id: schedule.schedule_id,
+                    },
+                )
+            })
+            .map(|schedule| {
+                let overlap = match schedule.overlap {
+                    OverlapPolicy::Skip => protobuf::OverlapPolicy::Skip,
+                    OverlapPolicy::Queue => protobuf::OverlapPolicy::Queue,
+                    OverlapPolicy::Allow => protobuf::OverlapPolicy::Allow,
+                };
+                let next_tick_unix_ms = schedule.next_tick.map_or(0, unix_ms);
+                protobuf::Schedule {
+                    schedule_id: schedule.schedule_id.as_bytes().to_vec(),
+                    cron: schedule.cron,
+                    timezone: schedule.timezone,
+                    overlap: overlap.into(),
+                    next_tick_unix_ms,
+                    history: schedule
+                        .history
+                        .iter()
+                        .map(|job_id| job_id.as_bytes().to_vec())
+                        .collect(),
+                    skipped: schedule.skipped,
+                    queued: schedule.queued,
+                }
+            })
+            .collect();
+        Ok(Response::new(ListSchedulesResponse { schedules }))
+    }
+
+    async fn delete_schedule(
+        &self,
+        req: Request<DeleteScheduleRequest>,
+    ) -> Result<Response<DeleteScheduleResponse>, Status> {
+        let user_id = req
+            .extensions()
+            .get::<UserExtension>()
+            .unwrap()
+            .user_id
+            .clone();
+
+        let schedule_id = req.into_inner().schedule_id;
+        let schedule_id = Uuid::from_slice(&schedule_id)
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        // check authz
+        if !self
+            .authorizer
+            .is_authorized(&user_id, Action::DeleteSchedule { schedule_id })
+        {
+            return Err(Status::permission_denied("Permission denied"));
+        }
+
+        self.scheduler
+            .delete_schedule(schedule_id)
+            .await
+            .map_err(|err| Status::not_found(err.to_string()))?;
+        Ok(Response::new(DeleteScheduleResponse {}))
+    }
+
     async fn stream_output(
         &self,
         req: Request<OutputRequest>,
@@ -186,21 +958,487 @@ impl RemoteJobs for RemoteJobsService {
             return Err(Status::permission_denied("Permission denied"));
         }
 
-        let receiver_result = match req.into_inner().output() {
-            OutputType::Stdout => self.coordinator.stream_stdout(job_id).await,
-            OutputType::Stderr => self.coordinator.stream_stderr(job_id).await,
-            OutputType::All => self.coordinator.stream_all(job_id).await,
+        let request = req.into_inner();
+        let offset = request.offset;
+        let receiver_result = match request.output() {
+            OutputType::Stdout => self.coordinator.stream_stdout(job_id, offset).await,
+            OutputType::Stderr => self.coordinator.stream_stderr(job_id, offset).await,
+            OutputType::All => self.coordinator.stream_all(job_id, offset).await,
         };
         let receiver = receiver_result.map_err(|err| Status::internal(err.to_string()))?;
 
-        let output_stream = UnboundedReceiverStream::from(receiver);
-        let response_stream = output_stream.map(|blob| {
-            Ok(OutputResponse {
-                data: blob.to_vec(),
+        let response_stream = ReceiverStream::new(receiver).map(output_response);
+        Ok(Response::new(
+            Box::pin(response_stream) as Self::StreamOutputStream
+        ))
+    }
+
+    async fn attach(
+        &self,
+        req: Request<Streaming<AttachRequest>>,
+    ) -> Result<Response<Self::AttachStream>, Status> {
+        let user_id = req
+            .extensions()
+            .get::<UserExtension>()
+            .unwrap()
+            .user_id
+            .clone();
+
+        // the first message picks the job to attach to
+        let mut requests = req.into_inner();
+        let job_id = match requests.message().await? {
+            Some(AttachRequest {
+                request: Some(attach_request::Request::JobId(job_id)),
+            }) => job_id,
+            _ => {
+                return Err(Status::invalid_argument(
+                    "First attach message must be a job id",
+                ))
+            }
+        };
+        let job_id =
+            Uuid::from_slice(&job_id).map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        // check authz
+        if !self.authorizer.is_authorized(
+            &user_id,
+            Action::ExistingJob {
+                job_id,
+                inner_action: ExistingJobAction::Attach,
+            },
+        ) {
+            return Err(Status::permission_denied("Permission denied"));
+        }
+
+        // each attempt of a restarted job runs in a terminal of its own. Subscribing first means no restart is missed
+        // between looking up the terminal and watching for the next one.
+        let mut events = self
+            .coordinator
+            .subscribe_events(EventFilter {
+                job_id: Some(job_id),
+                owner: None,
             })
+            .await;
+        let mut terminal =
+            self.coordinator
+                .get_job_terminal(job_id)
+                .await
+                .map_err(|err| match err {
+                    joblib::error::Error::NoTerminal | joblib::error::Error::NotStarted => {
+                        Status::failed_precondition(err.to_string())
+                    }
+                    _ => Status::internal(err.to_string()),
+                })?;
+        let receiver = self
+            .coordinator
+            .stream_stdout(job_id, 0)
+            .await
+            .map_err(|err| Status::internal(err.to_string()))?;
+
+        // forward keystrokes and resizes to the current attempt's terminal until the client hangs up. Input sent
+        // while no attempt is running goes nowhere.
+        let coordinator = self.coordinator.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    Some(event) = events.recv() => {
+                        if let LifecycleEventKind::Started { .. } = event.kind {
+                            match coordinator.get_job_terminal(job_id).await {
+                                Ok(current) => terminal = current,
+                                Err(_) => break,
+                            }
+                        }
+                    }
+                    request = requests.message() => {
+                        let request = match request {
+                            Ok(Some(AttachRequest { request })) => request,
+                            _ => break,
+                        };
+                        let _ = match request {
+                            Some(attach_request::Request::Input(data)) => {
+                                terminal.write(data.into())
+                            }
+                            Some(attach_request::Request::Resize(size)) => match window_size(size) {
+                                Ok(size) => terminal.resize(size),
+                                Err(_) => continue,
+                            },
+                            Some(attach_request::Request::JobId(_)) | None => continue,
+                        };
+                    }
+                }
+            }
         });
+
+        let response_stream = ReceiverStream::new(receiver).map(output_response);
         Ok(Response::new(
-            Box::pin(response_stream) as Self::StreamOutputStream
+            Box::pin(response_stream) as Self::AttachStream
         ))
     }
+
+    async fn write_stdin(
+        &self,
+        req: Request<Streaming<StdinRequest>>,
+    ) -> Result<Response<StdinResponse>, Status> {
+        let user_id = req
+            .extensions()
+            .get::<UserExtension>()
+            .unwrap()
+            .user_id
+            .clone();
+
+        // the first message picks the job to write to
+        let mut requests = req.into_inner();
+        let job_id = match requests.message().await? {
+            Some(StdinRequest {
+                request: Some(stdin_request::Request::JobId(job_id)),
+            }) => job_id,
+            _ => {
+                return Err(Status::invalid_argument(
+                    "First stdin message must be a job id",
+                ))
+            }
+        };
+        let job_id =
+            Uuid::from_slice(&job_id).map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        // check authz
+        if !self.authorizer.is_authorized(
+            &user_id,
+            Action::ExistingJob {
+                job_id,
+                inner_action: ExistingJobAction::WriteStdin,
+            },
+        ) {
+            return Err(Status::permission_denied("Permission denied"));
+        }
+
+        let stdin = self
+            .coordinator
+            .get_job_stdin(job_id)
+            .await
+            .map_err(|err| match err {
+                joblib::error::Error::NoStdinStream | joblib::error::Error::NotStarted => {
+                    Status::failed_precondition(err.to_string())
+                }
+                _ => Status::internal(err.to_string()),
+            })?;
+
+        let stdin_closed = |err: joblib::error::Error| Status::failed_precondition(err.to_string());
+        while let Some(StdinRequest { request }) = requests.message().await? {
+            match request {
+                Some(stdin_request::Request::Data(data)) => {
+                    stdin.write(data.into()).map_err(stdin_closed)?
+                }
+                Some(stdin_request::Request::Eof(true)) => {
+                    stdin.close().map_err(stdin_closed)?;
+                    break;
+                }
+                Some(stdin_request::Request::JobId(_)) => {
+                    return Err(Status::invalid_argument(
+                        "Only the first stdin message can be a job id",
+                    ))
+                }
+                Some(stdin_request::Request::Eof(false)) | None => {}
+            }
+        }
+        Ok(Response::new(StdinResponse {}))
+    }
+}
+
+/// Convert a protobuf job state to a joblib job state.
+fn job_state(state: protobuf::JobState) -> JobState {
+    match state {
+        protobuf::JobState::Running => JobState::Running,
+        protobuf::JobState::Paused => JobState::Paused,
+        protobuf::JobState::Restarting => JobState::Restarting,
+        protobuf::JobState::Queued => JobState::Queued,
+        protobuf::JobState::Cancelled => JobState::Cancelled,
+        protobuf::JobState::FailedToStart => JobState::FailedToStart,
+        protobuf::JobState::Exited => JobState::Exited,
+        protobuf::JobState::Killed => JobState::Killed,
+    }
+}
+
+/// Convert a joblib job state to a protobuf job state.
+fn proto_job_state(state: JobState) -> protobuf::JobState {
+    match state {
+        JobState::Running => protobuf::JobState::Running,
+        JobState::Paused => protobuf::JobState::Paused,
+        JobState::Restarting => protobuf::JobState::Restarting,
+        JobState::Queued => protobuf::JobState::Queued,
+        JobState::Cancelled => protobuf::JobState::Cancelled,
+        JobState::FailedToStart => protobuf::JobState::FailedToStart,
+        JobState::Exited => protobuf::JobState::Exited,
+        JobState::Killed => protobuf::JobState::Killed,
+    }
+}
+
+/// Convert a joblib job summary to a protobuf job summary.
+fn job_summary(job: JobSummary) -> protobuf::JobSummary {
+    let state = proto_job_state(job.status.state());
+    let outcome = match job.status {
+        joblib::events::JobStatus::Exited { code, .. } => {
+            Some(protobuf::job_summary::Outcome::ExitCode(code))
+        }
+        joblib::events::JobStatus::Killed { signal, .. } => {
+            Some(protobuf::job_summary::Outcome::KillSignal(signal))
+        }
+        _ => None,
+    };
+    protobuf::JobSummary {
+        job_id: job.job_id.as_bytes().to_vec(),
+        owner: job.owner.unwrap_or_default(),
+        name: job.name.unwrap_or_default(),
+        labels: HashMap::from_iter(job.labels),
+        state: state.into(),
+        outcome,
+        submitted_unix_ms: unix_ms(job.submitted),
+        started_unix_ms: job.started.map_or(0, unix_ms),
+    }
+}
+
+/// Convert joblib job info to protobuf job info.
+fn job_info(job: JobInfo) -> protobuf::JobInfo {
+    let outcome = match job.status {
+        joblib::events::JobStatus::Exited { code, .. } => {
+            Some(protobuf::job_info::Outcome::ExitCode(code))
+        }
+        joblib::events::JobStatus::Killed { signal, .. } => {
+            Some(protobuf::job_info::Outcome::KillSignal(signal))
+        }
+        _ => None,
+    };
+    protobuf::JobInfo {
+        job_id: job.job_id.as_bytes().to_vec(),
+        owner: job.owner.unwrap_or_default(),
+        name: job.name.unwrap_or_default(),
+        labels: HashMap::from_iter(job.labels),
+        state: proto_job_state(job.status.state()).into(),
+        outcome,
+        cmd: job.cmd,
+        args: job.args,
+        dir: job.dir,
+        environment: HashMap::from_iter(job.environment),
+        submitted_unix_ms: unix_ms(job.submitted),
+        started_unix_ms: job.started.map_or(0, unix_ms),
+        finished_unix_ms: job.finished.map_or(0, unix_ms),
+        duration_ms: job.duration.unwrap_or_default().as_millis() as u64,
+        pid: job.pid.unwrap_or_default(),
+        resource_usage: job.resource_usage.map(|usage| protobuf::ResourceUsage {
+            user_cpu_us: usage.user_time.as_micros() as u64,
+            system_cpu_us: usage.system_time.as_micros() as u64,
+            max_rss_bytes: usage.max_rss,
+            block_reads: usage.block_reads,
+            block_writes: usage.block_writes,
+        }),
+    }
+}
+
+/// Convert a joblib output event to a protobuf output response. A subscriber dropped for falling behind gets an
+/// error, which ends its stream.
+fn output_response(event: OutputEvent) -> Result<OutputResponse, Status> {
+    use protobuf::{output_chunk::Source, output_response::Event};
+    let event = match event {
+        OutputEvent::Data(chunk) => {
+            let source = match chunk.source {
+                OutputSource::Stdout => Source::Stdout,
+                OutputSource::Stderr => Source::Stderr,
+                OutputSource::Attempt => Source::Attempt,
+            };
+            Event::Chunk(OutputChunk {
+                data: chunk.data.to_vec(),
+                offset: chunk.offset,
+                source: source.into(),
+                seq: chunk.seq,
+                captured_unix_ms: unix_ms(chunk.timestamp),
+            })
+        }
+        OutputEvent::Truncated(dropped) => Event::Truncated(dropped),
+        OutputEvent::Gap(skipped) => Event::Skipped(skipped),
+        OutputEvent::Lagged => {
+            return Err(Status::resource_exhausted(
+                "Fell too far behind the job's output",
+            ))
+        }
+    };
+    Ok(OutputResponse { event: Some(event) })
+}
+
+/// Convert a joblib lifecycle event to a protobuf job event.
+fn job_event(event: LifecycleEvent) -> JobEvent {
+    use protobuf::job_event::Event;
+    let kind = match event.kind {
+        LifecycleEventKind::Started { attempt } => Event::Started(attempt),
+        LifecycleEventKind::Paused => Event::Paused(true),
+        LifecycleEventKind::Resumed => Event::Resumed(true),
+        LifecycleEventKind::Exited { code } => Event::ExitCode(code),
+        LifecycleEventKind::Killed { signal } => Event::KillSignal(signal),
+        LifecycleEventKind::Cancelled => Event::Cancelled(true),
+        LifecycleEventKind::FailedToStart => Event::FailedToStart(true),
+        LifecycleEventKind::Deleted => Event::Deleted(true),
+    };
+    JobEvent {
+        job_id: event.job_id.as_bytes().to_vec(),
+        owner: event.owner.unwrap_or_default(),
+        event: Some(kind),
+        unix_ms: unix_ms(event.time),
+    }
+}
+
+fn unix_ms(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// The page token of the page after `job`: where it is in the order jobs are listed in, as
+/// `<unix nanos it was submitted at>:<job id>`.
+fn page_token(job: &JobSummary) -> String {
+    let submitted = job
+        .submitted
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    format!("{}:{}", submitted.as_nanos(), job.job_id)
+}
+
+fn parse_page_token(token: &str) -> Result<(SystemTime, JobId), Status> {
+    let invalid = || Status::invalid_argument("Invalid page token");
+    let (submitted, job_id) = token.split_once(':').ok_or_else(invalid)?;
+    let submitted: u64 = submitted.parse().map_err(|_| invalid())?;
+    let job_id = Uuid::parse_str(job_id).map_err(|_| invalid())?;
+    Ok((
+        SystemTime::UNIX_EPOCH + Duration::from_nanos(submitted),
+        job_id,
+    ))
+}
+
+/// Convert a protobuf stop policy to a joblib stop policy, filling in defaults for unset fields.
+fn stop_policy(policy: protobuf::StopPolicy) -> Result<StopPolicy, Status> {
+    let mut stop_policy = StopPolicy::default();
+    match policy.signal {
+        0 => {}
+        signal @ 1..=64 => stop_policy.signal = signal,
+        _ => return Err(Status::invalid_argument("stop signal must be in 1..=64")),
+    }
+    if let Some(grace_period) = policy.grace_period {
+        stop_policy.grace = Duration::from_millis(grace_period.millis);
+    }
+    Ok(stop_policy)
+}
+
+/// Convert a protobuf restart policy to a joblib restart policy and backoff. Zero values mean "use the default".
+fn restart_policy(policy: protobuf::RestartPolicy) -> (RestartPolicy, Backoff) {
+    let restart_policy = match policy.mode() {
+        restart_policy::Mode::Never => RestartPolicy::Never,
+        restart_policy::Mode::OnFailure => RestartPolicy::OnFailure {
+            max_attempts: match policy.max_attempts {
+                0 => u32::MAX,
+                max_attempts => max_attempts,
+            },
+        },
+        restart_policy::Mode::Always => RestartPolicy::Always,
+    };
+    let mut backoff = Backoff::default();
+    if policy.backoff_ms != 0 {
+        backoff.initial = Duration::from_millis(policy.backoff_ms);
+    }
+    if policy.max_backoff_ms != 0 {
+        backoff.max = Duration::from_millis(policy.max_backoff_ms);
+    }
+    (restart_policy, backoff)
+}
+
+/// Convert a joblib workflow step status to a protobuf step status.
+fn step_status(step: joblib::events::StepStatus) -> protobuf::StepStatus {
+    let (state, outcome) = match step.state {
+        StepState::Waiting => (step_status::State::Waiting, None),
+        StepState::Running => (step_status::State::Running, None),
+        StepState::Finished(joblib::events::JobStatus::Cancelled) => {
+            (step_status::State::Cancelled, None)
+        }
+        StepState::Finished(status) => {
+            let outcome = match status {
+                joblib::events::JobStatus::Exited { code, .. } => {
+                    step_status::Outcome::ExitCode(code)
+                }
+                joblib::events::JobStatus::Killed { signal, .. } => {
+                    step_status::Outcome::KillSignal(signal)
+                }
+                _ => unreachable!(), // only finished jobs finish a step
+            };
+            (step_status::State::Finished, Some(outcome))
+        }
+        StepState::Skipped => (step_status::State::Skipped, None),
+        StepState::FailedToStart => (step_status::State::FailedToStart, None),
+    };
+    protobuf::StepStatus {
+        name: step.name,
+        job_id: step.job_id.as_bytes().to_vec(),
+        state: state.into(),
+        outcome,
+    }
+}
+
+/// Convert a joblib stop reason to a protobuf stop reason.
+fn stop_reason(stopped: Option<joblib::events::StopReason>) -> StopReason {
+    match stopped {
+        None => StopReason::NotStopped,
+        Some(joblib::events::StopReason::User) => StopReason::User,
+        Some(joblib::events::StopReason::Timeout) => StopReason::Timeout,
+    }
+}
+
+/// Convert a protobuf window size to a joblib window size.
+fn window_size(size: protobuf::WindowSize) -> Result<WindowSize, Status> {
+    match (u16::try_from(size.rows), u16::try_from(size.cols)) {
+        (Ok(rows), Ok(cols)) if rows > 0 && cols > 0 => Ok(WindowSize { rows, cols }),
+        _ => Err(Status::invalid_argument(
+            "window rows and cols must be in 1..=65535",
+        )),
+    }
+}
+
+/// Convert protobuf resource limits to joblib resource limits. Zero values mean "no limit".
+fn resource_limits(
+    limits: protobuf::ResourceLimits,
+) -> Result<joblib::types::ResourceLimits, Status> {
+    let nonzero = |value: u64| (value != 0).then_some(value);
+
+    let cpu = match limits.cpu {
+        Some(cpu) if cpu.quota_us == 0 => {
+            return Err(Status::invalid_argument("cpu quota must be non-zero"))
+        }
+        Some(cpu) if cpu.period_us == 0 => {
+            return Err(Status::invalid_argument("cpu period must be non-zero"))
+        }
+        Some(cpu) => Some(joblib::types::CpuMax {
+            quota_us: cpu.quota_us,
+            period_us: cpu.period_us,
+        }),
+        None => None,
+    };
+
+    let mut io = Vec::with_capacity(limits.io.len());
+    for io_limit in limits.io {
+        let weight = match io_limit.weight {
+            0 => None,
+            weight @ 1..=10000 => Some(weight as u16),
+            _ => return Err(Status::invalid_argument("io weight must be in 1..=10000")),
+        };
+        io.push(joblib::types::IoLimit {
+            major: io_limit.major,
+            minor: io_limit.minor,
+            weight,
+            rbps: nonzero(io_limit.rbps),
+            wbps: nonzero(io_limit.wbps),
+            riops: nonzero(io_limit.riops),
+            wiops: nonzero(io_limit.wiops),
+        });
+    }
+
+    Ok(joblib::types::ResourceLimits {
+        cpu,
+        memory_max: nonzero(limits.memory_max),
+        io,
+    })
 }
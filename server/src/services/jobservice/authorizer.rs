@@ -1,25 +1,49 @@
 mod authz_db;
 
-use self::authz_db::{AuthzDb, Permission, Scope};
+pub use self::authz_db::Scope;
+use self::authz_db::{AuthzDb, Permission};
 use super::UserId;
-use joblib::types::JobId;
+use joblib::types::{JobId, JobRegistry, ScheduleId, WorkflowId};
 use std::{collections::HashMap, sync::Mutex};
 
 type JobOwnerDb = HashMap<JobId, UserId>;
+type WorkflowOwnerDb = HashMap<WorkflowId, UserId>;
+type ScheduleOwnerDb = HashMap<ScheduleId, UserId>;
 
 pub struct Authorizer {
     job_owners: Mutex<JobOwnerDb>,
+    workflow_owners: Mutex<WorkflowOwnerDb>,
+    schedule_owners: Mutex<ScheduleOwnerDb>,
     authz_db: AuthzDb, // immutable pre-populated mock db
 }
 
 pub enum ExistingJobAction {
     StopJob,
+    SignalJob(i32),
+    PauseJob,
+    ResumeJob,
+    Attach,
+    WriteStdin,
     QueryStatus,
     StreamOutput,
+    DeleteJob,
 }
 
 pub enum Action {
     StartJob,
+    QueryWorkflow {
+        workflow_id: WorkflowId,
+    },
+    QuerySchedule {
+        schedule_id: ScheduleId,
+    },
+    DeleteSchedule {
+        schedule_id: ScheduleId,
+    },
+    /// Hear about what happens to a job of this owner, which may already be forgotten by the time the user does.
+    WatchJob {
+        owner: UserId,
+    },
     ExistingJob {
         job_id: JobId,
         inner_action: ExistingJobAction,
@@ -31,10 +55,12 @@ impl Authorizer {
         let authz_db = AuthzDb::default();
         Self {
             job_owners: Mutex::new(JobOwnerDb::new()),
+            workflow_owners: Mutex::new(WorkflowOwnerDb::new()),
+            schedule_owners: Mutex::new(ScheduleOwnerDb::new()),
             authz_db,
         }
     }
-    pub fn add_job(&self, job_id: JobId, user_id: &UserId) {
+    fn add_job(&self, job_id: JobId, user_id: &str) {
         self.job_owners
             .lock()
             .unwrap()
@@ -45,40 +71,63 @@ impl Authorizer {
         use Action::*;
         use ExistingJobAction::*;
         match action {
+            ExistingJob {
+                job_id,
+                inner_action: DeleteJob,
+            } => {
+                // only the owner may delete a job, whatever their scope
+                let maybe_owner = self.job_owners.lock().unwrap().get(&job_id).cloned();
+                if maybe_owner.as_ref() == Some(user_id) {
+                    return self
+                        .authz_db
+                        .has_permission(user_id, Permission::StartOrStop);
+                }
+            }
             ExistingJob {
                 job_id,
                 inner_action,
             } => {
+                let permission = match inner_action {
+                    // feeding input to a job is as good as controlling the job
+                    StopJob | PauseJob | ResumeJob | Attach | WriteStdin => Permission::StartOrStop,
+                    // only signals on the role's allowlist
+                    SignalJob(signal) => Permission::Signal(signal),
+                    QueryStatus | StreamOutput => Permission::Query,
+                    DeleteJob => unreachable!(), // handled above
+                };
                 let maybe_owner = self.job_owners.lock().unwrap().get(&job_id).cloned();
                 if let Some(job_owner) = maybe_owner {
-                    match inner_action {
-                        StopJob => {
-                            if job_owner == *user_id {
-                                return self
-                                    .authz_db
-                                    .has_permission(user_id, Permission::StartOrStop);
-                            } else {
-                                return self.authz_db.has_scoped_permission(
-                                    user_id,
-                                    Scope::All,
-                                    Permission::StartOrStop,
-                                );
-                            }
-                        }
-                        QueryStatus | StreamOutput => {
-                            if job_owner == *user_id {
-                                return self.authz_db.has_permission(user_id, Permission::Query);
-                            } else {
-                                return self.authz_db.has_scoped_permission(
-                                    user_id,
-                                    Scope::All,
-                                    Permission::Query,
-                                );
-                            }
-                        }
-                    }
+                    return self.has_owner_permission(user_id, &job_owner, permission);
+                }
+            }
+            QueryWorkflow { workflow_id } => {
+                let maybe_owner = self
+                    .workflow_owners
+                    .lock()
+                    .unwrap()
+                    .get(&workflow_id)
+                    .cloned();
+                if let Some(workflow_owner) = maybe_owner {
+                    return self.has_owner_permission(user_id, &workflow_owner, Permission::Query);
+                }
+            }
+            QuerySchedule { schedule_id } => {
+                if let Some(schedule_owner) = self.schedule_owner(schedule_id) {
+                    return self.has_owner_permission(user_id, &schedule_owner, Permission::Query);
                 }
             }
+            DeleteSchedule { schedule_id } => {
+                if let Some(schedule_owner) = self.schedule_owner(schedule_id) {
+                    return self.has_owner_permission(
+                        user_id,
+                        &schedule_owner,
+                        Permission::StartOrStop,
+                    );
+                }
+            }
+            WatchJob { owner } => {
+                return self.has_owner_permission(user_id, &owner, Permission::Query);
+            }
             StartJob => {
                 return self
                     .authz_db
@@ -91,4 +140,82 @@ impl Authorizer {
         //       -- dont leak info! Although I won't go as far as hardening this against timing attacks.
         false
     }
+
+    fn schedule_owner(&self, schedule_id: ScheduleId) -> Option<UserId> {
+        self.schedule_owners
+            .lock()
+            .unwrap()
+            .get(&schedule_id)
+            .cloned()
+    }
+
+    /// Whose jobs a user may query the status of: everyone's, only their own, or no one's. Lets jobs be listed
+    /// without checking them one by one.
+    pub fn query_scope(&self, user_id: &UserId) -> Option<Scope> {
+        if self
+            .authz_db
+            .has_scoped_permission(user_id, Scope::All, Permission::Query)
+        {
+            Some(Scope::All)
+        } else if self.authz_db.has_permission(user_id, Permission::Query) {
+            Some(Scope::Owner)
+        } else {
+            None
+        }
+    }
+
+    /// Owners need the permission in any scope, everyone else needs it in the `All` scope.
+    fn has_owner_permission(
+        &self,
+        user_id: &UserId,
+        owner: &UserId,
+        permission: Permission,
+    ) -> bool {
+        if owner == user_id {
+            self.authz_db.has_permission(user_id, permission)
+        } else {
+            self.authz_db
+                .has_scoped_permission(user_id, Scope::All, permission)
+        }
+    }
+}
+
+/// Job and workflow owners are kept in step with the coordinator: they are known before anyone is told about the job,
+/// and gone as soon as the coordinator forgets it.
+impl JobRegistry for Authorizer {
+    fn job_added(&self, job_id: JobId, owner: Option<&str>) {
+        if let Some(owner) = owner {
+            self.add_job(job_id, owner);
+        }
+    }
+
+    fn job_forgotten(&self, job_id: JobId) {
+        self.job_owners.lock().unwrap().remove(&job_id);
+    }
+
+    fn workflow_added(&self, workflow_id: WorkflowId, owner: Option<&str>) {
+        if let Some(owner) = owner {
+            self.workflow_owners
+                .lock()
+                .unwrap()
+                .insert(workflow_id, owner.to_string());
+        }
+    }
+
+    fn workflow_forgotten(&self, workflow_id: WorkflowId) {
+        self.workflow_owners.lock().unwrap().remove(&workflow_id);
+    }
+
+    fn schedule_added(&self, schedule_id: ScheduleId, owner: Option<&str>) {
+        if let Some(owner) = owner {
+            self.schedule_owners
+                .lock()
+                .unwrap()
+                .insert(schedule_id, owner.to_string());
+        }
+    }
+
+    fn schedule_deleted(&self, schedule_id: ScheduleId) {
+        self.schedule_owners.lock().unwrap().remove(&schedule_id);
+    }
 }
@@ -25,6 +25,8 @@ type ScopedRoles = HashMap<Scope, HashSet<Role>>;
 pub enum Permission {
     StartOrStop,
     Query,
+    /// send this signal to a job
+    Signal(i32),
 }
 
 #[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
@@ -62,11 +64,22 @@ impl Default for AuthzDb {
         // Setup role->permissions info
         let mut role_permissions = HashMap::new();
 
-        // task managers can start/stop/query jobs
-        role_permissions.insert(
-            Role::TaskManager,
-            HashSet::from_iter(vec![Permission::StartOrStop, Permission::Query]),
+        // task managers can start/stop/query jobs, and send them the signals services commonly handle
+        let mut task_manager_permissions =
+            HashSet::from_iter(vec![Permission::StartOrStop, Permission::Query]);
+        task_manager_permissions.extend(
+            [
+                libc::SIGHUP,
+                libc::SIGINT,
+                libc::SIGQUIT,
+                libc::SIGUSR1,
+                libc::SIGUSR2,
+                libc::SIGTERM,
+                libc::SIGKILL,
+            ]
+            .map(Permission::Signal),
         );
+        role_permissions.insert(Role::TaskManager, task_manager_permissions);
 
         // analysts can query job status or output
         role_permissions.insert(Role::Analyst, HashSet::from_iter(vec![Permission::Query]));
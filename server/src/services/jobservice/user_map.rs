@@ -0,0 +1,130 @@
+use super::UserId;
+use joblib::types::Credentials;
+use std::{collections::HashMap, fs, io, path::Path};
+
+/// Maps authenticated users to the unix identity their jobs run as.
+///
+/// A user id resolves to, in order:
+/// 1. its explicit entry in the map, if there is one.
+/// 2. the unix account with the same name, if the host has one.
+/// 3. the fallback account, if one is configured.
+///
+/// Users that resolve to none of these can't start jobs.
+pub struct UserMap {
+    entries: HashMap<UserId, UnixUser>,
+    fallback: Option<String>,
+    allow_root: bool,
+    /// jobs run as the server's own user when this isn't set
+    switch_users: bool,
+}
+
+/// A unix identity, either by account name or by explicit ids.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UnixUser {
+    Account(String),
+    Ids {
+        uid: u32,
+        gid: u32,
+        groups: Vec<u32>,
+    },
+}
+
+impl Default for UserMap {
+    fn default() -> Self {
+        // TODO: real deployments should load a map with `UserMap::from_file`.
+        // The mock users don't have accounts on most hosts, so run their jobs as nobody. A server that isn't allowed
+        // to switch users runs them as itself, instead of failing every job.
+        Self {
+            entries: HashMap::new(),
+            fallback: Some("nobody".into()),
+            allow_root: false,
+            switch_users: Credentials::can_switch().unwrap_or(false),
+        }
+    }
+}
+
+impl UserMap {
+    /// Load a user map file.
+    ///
+    /// Each line is `<user id> <account name>` or `<user id> <uid>:<gid>[:<group>,<group>...]`.
+    /// The user id `*` sets the fallback account. Blank lines and lines starting with `#` are ignored.
+    ///
+    /// Fails if the server isn't allowed to run jobs as the users in the map.
+    pub fn from_file(path: impl AsRef<Path>, allow_root: bool) -> io::Result<Self> {
+        if !Credentials::can_switch()? {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "running jobs as the users in a user map takes CAP_SETUID and CAP_SETGID",
+            ));
+        }
+        let mut user_map = Self {
+            entries: HashMap::new(),
+            fallback: None,
+            allow_root,
+            switch_users: true,
+        };
+        for (n, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("user map line {}: {:?}", n + 1, line),
+                )
+            };
+            let (user_id, unix_user) = line.split_once(char::is_whitespace).ok_or_else(invalid)?;
+            let unix_user = unix_user.trim();
+            if user_id == "*" {
+                user_map.fallback = Some(unix_user.to_string());
+            } else {
+                let unix_user = parse_unix_user(unix_user).ok_or_else(invalid)?;
+                user_map.entries.insert(user_id.to_string(), unix_user);
+            }
+        }
+        Ok(user_map)
+    }
+
+    /// Resolve the credentials a user's jobs run as. `None` if they run as the server's own user.
+    pub fn credentials(&self, user_id: &UserId) -> io::Result<Option<Credentials>> {
+        if !self.switch_users {
+            return Ok(None);
+        }
+        let mut credentials = match self.entries.get(user_id) {
+            Some(UnixUser::Account(account)) => Credentials::for_account(account)?,
+            Some(UnixUser::Ids { uid, gid, groups }) => Credentials {
+                uid: *uid,
+                gid: *gid,
+                groups: groups.clone(),
+                allow_root: false,
+            },
+            None => match (Credentials::for_account(user_id), &self.fallback) {
+                (Ok(credentials), _) => credentials,
+                (Err(err), _) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                (Err(_), Some(fallback)) => Credentials::for_account(fallback)?,
+                (Err(err), None) => return Err(err),
+            },
+        };
+        credentials.allow_root = self.allow_root;
+        Ok(Some(credentials))
+    }
+}
+
+fn parse_unix_user(s: &str) -> Option<UnixUser> {
+    if !s.starts_with(|c: char| c.is_ascii_digit()) {
+        return Some(UnixUser::Account(s.to_string()));
+    }
+    let mut parts = s.splitn(3, ':');
+    let uid = parts.next()?.parse().ok()?;
+    let gid = parts.next()?.parse().ok()?;
+    let groups = match parts.next() {
+        Some(groups) => groups
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .ok()?,
+        None => vec![],
+    };
+    Some(UnixUser::Ids { uid, gid, groups })
+}